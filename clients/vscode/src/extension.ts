@@ -3,7 +3,7 @@
  * Licensed under the MIT License. See License.txt in the project root for license information.
  * ------------------------------------------------------------------------------------------ */
 
-import { workspace, ExtensionContext, window, commands, CompletionList } from 'vscode';
+import { workspace, ExtensionContext, window, commands, CompletionList, Uri } from 'vscode';
 import {
 	CloseAction,
 	ErrorAction,
@@ -46,7 +46,10 @@ export function activate(context: ExtensionContext) {
 	// Options to control the language client
 	let clientOptions: LanguageClientOptions = {
 	  // Register the server for plain text documents
-	  documentSelector: [{ scheme: "file", language: "amber" }],
+	  documentSelector: [
+			{ scheme: "file", language: "amber" },
+			{ scheme: "amber-std", language: "amber" },
+		],
 	  synchronize: {
 			// Notify the server about file changes to '.clientrc files contained in the workspace
 			fileEvents: workspace.createFileSystemWatcher("**/.clientrc"),
@@ -139,6 +142,14 @@ export function activate(context: ExtensionContext) {
 
 	context.subscriptions.push(pathEdits);
 
+	// Files of the bundled standard library are served by the server
+	const stdlibProvider = workspace.registerTextDocumentContentProvider("amber-std", {
+		provideTextDocumentContent: (uri: Uri) =>
+			client.sendRequest<string>("amber/stdlibContent", { uri: uri.toString() }),
+	});
+
+	context.subscriptions.push(stdlibProvider);
+
 	client.setTrace(Trace.Verbose)
 	client.start();
 }
@@ -1,11 +1,15 @@
+use std::{io::ErrorKind, sync::Arc};
+
 use crate::{
     analysis::{
-        self, import_symbol, insert_symbol_definition, map_import_path,
+        self, get_import_cycle, import_symbol, insert_symbol_definition, map_import_path,
+        query::FunctionQuery,
         types::{make_union_type, matches_type, DataType},
         Context, FunctionContext, FunctionSymbol, ImportContext, SymbolInfo, SymbolType,
         VariableSymbol,
     },
-    backend::Backend,
+    analyzer::{AmberVersion, Analyzer},
+    diagnostics::Diagnostic,
     files::FileVersion,
     grammar::{
         alpha034::{FunctionArgument, GlobalStatement, ImportContent},
@@ -21,9 +25,13 @@ pub async fn analyze_global_stmnt(
     file_id: FileId,
     file_version: FileVersion,
     ast: &[Spanned<GlobalStatement>],
-    backend: &Backend,
+    analyzer: &Arc<Analyzer>,
 ) {
     for (global, span) in ast.iter() {
+        if analyzer.files.is_outdated(&(file_id, file_version)) {
+            return;
+        }
+
         match global {
             GlobalStatement::FunctionDefinition(
                 compiler_flags,
@@ -34,16 +42,22 @@ pub async fn analyze_global_stmnt(
                 ty,
                 body,
             ) => {
+                let query =
+                    FunctionQuery::new(&analyzer.files, (file_id, file_version), name, *span, None);
+
+                if query.reuse(&analyzer.files) {
+                    continue;
+                }
+
                 // We create scoped generics map, to not overwrite other generics, not defined here
-                let scoped_generics_map = backend.files.generic_types.clone();
+                let scoped_generics_map = analyzer.files.generic_types.scoped();
 
                 let mut new_generic_types = vec![];
                 args.iter().for_each(|(arg, _)| {
                     let (name, ty, name_span) = match arg {
                         FunctionArgument::Generic(_, (name, span)) => {
-                            let generic_id = scoped_generics_map.new_generic_id();
-
-                            scoped_generics_map.constrain_generic_type(generic_id, DataType::Any);
+                            let generic_id =
+                                scoped_generics_map.new_generic_id((file_id, file_version));
                             new_generic_types.push(generic_id);
 
                             (name, DataType::Generic(generic_id), span)
@@ -54,20 +68,24 @@ pub async fn analyze_global_stmnt(
                         _ => return,
                     };
 
-                    let mut symbol_table =
-                        match backend.files.symbol_table.get_mut(&(file_id, file_version)) {
-                            Some(symbol_table) => symbol_table,
-                            None => {
-                                tracing::error!(
-                                    "Symbol table for file {:?} not found",
-                                    (file_id, file_version)
-                                );
-                                return;
-                            }
-                        };
+                    let mut symbol_table = match analyzer
+                        .files
+                        .symbol_table
+                        .get_mut(&(file_id, file_version))
+                    {
+                        Some(symbol_table) => symbol_table,
+                        None => {
+                            tracing::error!(
+                                "Symbol table for file {:?} not found",
+                                (file_id, file_version)
+                            );
+                            return;
+                        }
+                    };
+                    let symbol_table = Arc::make_mut(&mut symbol_table);
 
                     insert_symbol_definition(
-                        &mut symbol_table,
+                        symbol_table,
                         &SymbolInfo {
                             name: name.to_string(),
                             symbol_type: SymbolType::Variable(VariableSymbol { is_const: false }),
@@ -90,7 +108,7 @@ pub async fn analyze_global_stmnt(
                         file_id,
                         file_version,
                         stmnt,
-                        &backend.files,
+                        &analyzer.files,
                         span.end,
                         &scoped_generics_map,
                         &vec![Context::Function(FunctionContext {
@@ -102,11 +120,7 @@ pub async fn analyze_global_stmnt(
                 });
 
                 new_generic_types.iter().for_each(|generic_id| {
-                    backend
-                        .files
-                        .generic_types
-                        .constrain_generic_type(*generic_id, scoped_generics_map.get(*generic_id));
-                    backend.files.generic_types.mark_as_inferred(*generic_id);
+                    analyzer.files.generic_types.mark_as_inferred(*generic_id);
                 });
 
                 let return_type = match return_types.len() {
@@ -116,12 +130,14 @@ pub async fn analyze_global_stmnt(
 
                 let data_type = match ty {
                     Some((ty, ty_span)) => {
-                        if !matches_type(ty, &return_type, &backend.files.generic_types) {
-                            backend.files.report_error(
+                        if !matches_type(ty, &return_type, &analyzer.files.generic_types) {
+                            analyzer.files.report(
                                 &(file_id, file_version),
-                                &format!(
-                                    "Function returns type {return_type:?}, but expected {ty:?}",
-                                ),
+                                Diagnostic::TypeMismatch {
+                                    expected: ty.to_string(&analyzer.files.generic_types),
+                                    found: return_type.to_string(&analyzer.files.generic_types),
+                                    definition: None,
+                                },
                                 *ty_span,
                             );
                         }
@@ -131,14 +147,15 @@ pub async fn analyze_global_stmnt(
                     None => return_type,
                 };
 
-                let mut symbol_table = backend
+                let mut symbol_table_entry = analyzer
                     .files
                     .symbol_table
                     .entry((file_id, file_version))
                     .or_default();
+                let symbol_table = Arc::make_mut(&mut symbol_table_entry);
 
                 insert_symbol_definition(
-                    &mut symbol_table,
+                    symbol_table,
                     &SymbolInfo {
                         name: name.to_string(),
                         symbol_type: SymbolType::Function(FunctionSymbol {
@@ -187,6 +204,9 @@ pub async fn analyze_global_stmnt(
                     span.end..=usize::MAX,
                     *is_pub,
                 );
+
+                drop(symbol_table_entry);
+                query.record(&analyzer.files);
             }
             GlobalStatement::Import(
                 (is_public_import, _),
@@ -195,21 +215,24 @@ pub async fn analyze_global_stmnt(
                 _,
                 (path, path_span),
             ) => {
-                let uri = &backend.files.lookup(&file_id);
+                let uri = &analyzer.files.lookup(&file_id);
 
-                let result = backend
-                    .open_document(&map_import_path(uri, path, backend).await)
-                    .await;
+                let result =
+                    match map_import_path(uri, path, analyzer, &AmberVersion::Alpha034).await {
+                        Some(import_uri) => analyzer.open_document(&import_uri).await,
+                        None => Err(ErrorKind::NotFound.into()),
+                    };
 
                 {
-                    let mut symbol_table = backend
+                    let mut symbol_table = analyzer
                         .files
                         .symbol_table
                         .entry((file_id, file_version))
                         .or_default();
+                    let symbol_table = Arc::make_mut(&mut symbol_table);
 
                     insert_symbol_definition(
-                        &mut symbol_table,
+                        symbol_table,
                         &SymbolInfo {
                             name: path.to_string(),
                             symbol_type: SymbolType::ImportPath,
@@ -225,34 +248,38 @@ pub async fn analyze_global_stmnt(
                     );
                 }
 
-                if result.is_err() {
-                    backend.files.report_error(
+                let Ok(imported_file) = result else {
+                    analyzer.files.report(
                         &(file_id, file_version),
-                        "File doesn't exist",
+                        Diagnostic::FileNotFound {
+                            path: path.to_string(),
+                        },
                         *path_span,
                     );
 
                     continue;
-                }
-
-                let imported_file = result.clone().unwrap();
-
-                if backend.files.is_depending_on(&imported_file, file_id) {
-                    backend.files.report_error(
-                        &(file_id, file_version),
-                        "Circular dependency",
-                        *path_span,
-                    );
+                };
 
-                    continue;
+                if let Some(cycle) = get_import_cycle(
+                    &analyzer.files,
+                    &(file_id, file_version),
+                    &imported_file,
+                    path,
+                    *path_span,
+                ) {
+                    analyzer
+                        .files
+                        .report(&(file_id, file_version), cycle, *path_span);
                 }
 
-                backend
-                    .files
-                    .add_file_dependency(&(file_id, file_version), imported_file.0);
+                analyzer.files.add_file_dependency(
+                    &(file_id, file_version),
+                    imported_file.0,
+                    *path_span,
+                );
 
                 let imported_file_symbol_table =
-                    match backend.files.symbol_table.get(&imported_file) {
+                    match analyzer.files.symbol_table.get(&imported_file) {
                         Some(symbol_table_ref) => symbol_table_ref.clone(),
                         None => continue,
                     };
@@ -268,17 +295,20 @@ pub async fn analyze_global_stmnt(
 
                         ident_list.iter().for_each(|(ident, span)| {
                             if import_context.imported_symbols.contains(&ident.to_string()) {
-                                backend.files.report_error(
+                                analyzer.files.report(
                                     &(file_id, file_version),
-                                    &format!("Duplicate import '{ident}'"),
+                                    Diagnostic::DuplicateImport {
+                                        name: ident.to_string(),
+                                    },
                                     *span,
                                 );
 
-                                let mut symbol_table = backend
+                                let mut symbol_table = analyzer
                                     .files
                                     .symbol_table
                                     .entry((file_id, file_version))
                                     .or_default();
+                                let symbol_table = Arc::make_mut(&mut symbol_table);
 
                                 symbol_table.symbols.insert(
                                     span.start..=span.end,
@@ -302,7 +332,7 @@ pub async fn analyze_global_stmnt(
 
                             match symbol_definition {
                                 Some(definition_location) => {
-                                    let definition_file_symbol_table = match backend
+                                    let definition_file_symbol_table = match analyzer
                                         .files
                                         .symbol_table
                                         .get(&definition_location.file)
@@ -319,18 +349,19 @@ pub async fn analyze_global_stmnt(
                                         None => return,
                                     };
 
-                                    let mut symbol_table = backend
+                                    let mut symbol_table = analyzer
                                         .files
                                         .symbol_table
                                         .entry((file_id, file_version))
                                         .or_default();
+                                    let symbol_table = Arc::make_mut(&mut symbol_table);
 
                                     import_symbol(
-                                        &mut symbol_table,
+                                        symbol_table,
                                         &SymbolInfo {
                                             is_definition: false,
                                             contexts: vec![Context::Import(import_context.clone())],
-                                            ..symbol_info.clone()
+                                            ..symbol_info.exported(&analyzer.files.generic_types)
                                         },
                                         Some(span.start..=span.end),
                                         definition_location,
@@ -340,17 +371,20 @@ pub async fn analyze_global_stmnt(
                                     import_context.imported_symbols.push(ident.to_string());
                                 }
                                 None => {
-                                    backend.files.report_error(
+                                    analyzer.files.report(
                                         &(file_id, file_version),
-                                        &format!("Could not resolve '{ident}'"),
+                                        Diagnostic::UnresolvedImport {
+                                            name: ident.to_string(),
+                                        },
                                         *span,
                                     );
 
-                                    let mut symbol_table = backend
+                                    let mut symbol_table = analyzer
                                         .files
                                         .symbol_table
                                         .entry((file_id, file_version))
                                         .or_default();
+                                    let symbol_table = Arc::make_mut(&mut symbol_table);
 
                                     symbol_table.symbols.insert(
                                         span.start..=span.end,
@@ -375,7 +409,7 @@ pub async fn analyze_global_stmnt(
                         .iter()
                         .for_each(|(_, location)| {
                             let definition_file_symbol_table =
-                                match backend.files.symbol_table.get(&location.file) {
+                                match analyzer.files.symbol_table.get(&location.file) {
                                     Some(symbol_table) => symbol_table.clone(),
                                     None => return,
                                 };
@@ -386,14 +420,15 @@ pub async fn analyze_global_stmnt(
                                     None => return,
                                 };
 
-                            let mut symbol_table = backend
+                            let mut symbol_table = analyzer
                                 .files
                                 .symbol_table
                                 .entry((file_id, file_version))
                                 .or_default();
+                            let symbol_table = Arc::make_mut(&mut symbol_table);
 
                             import_symbol(
-                                &mut symbol_table,
+                                symbol_table,
                                 &SymbolInfo {
                                     is_definition: false,
                                     contexts: vec![Context::Import(ImportContext {
@@ -402,7 +437,7 @@ pub async fn analyze_global_stmnt(
                                             .clone(),
                                         imported_symbols: vec![],
                                     })],
-                                    ..symbol_info.clone()
+                                    ..symbol_info.exported(&analyzer.files.generic_types)
                                 },
                                 None,
                                 location,
@@ -413,14 +448,15 @@ pub async fn analyze_global_stmnt(
             }
             GlobalStatement::Main(_, args, body) => {
                 if let Some((args, args_span)) = args {
-                    let mut symbol_table = backend
+                    let mut symbol_table = analyzer
                         .files
                         .symbol_table
                         .entry((file_id, file_version))
                         .or_default();
+                    let symbol_table = Arc::make_mut(&mut symbol_table);
 
                     insert_symbol_definition(
-                        &mut symbol_table,
+                        symbol_table,
                         &SymbolInfo {
                             name: args.to_string(),
                             symbol_type: SymbolType::Variable(VariableSymbol { is_const: false }),
@@ -441,9 +477,9 @@ pub async fn analyze_global_stmnt(
                         file_id,
                         file_version,
                         stmnt,
-                        &backend.files,
+                        &analyzer.files,
                         span.end,
-                        &backend.files.generic_types.clone(),
+                        &analyzer.files.generic_types.scoped(),
                         &vec![Context::Main],
                     );
                 });
@@ -453,9 +489,9 @@ pub async fn analyze_global_stmnt(
                     file_id,
                     file_version,
                     stmnt,
-                    &backend.files,
+                    &analyzer.files,
                     usize::MAX,
-                    &backend.files.generic_types.clone(),
+                    &analyzer.files.generic_types.scoped(),
                     &vec![],
                 );
             }
@@ -1,9 +1,13 @@
+use std::sync::Arc;
+
 use crate::{
     analysis::{
-        get_symbol_definition_info, insert_symbol_definition, insert_symbol_reference,
+        get_related_definition, get_symbol_definition_info, insert_symbol_definition,
+        insert_symbol_reference,
         types::{make_union_type, matches_type, GenericsMap},
         BlockContext, Context, DataType, SymbolInfo, SymbolLocation, SymbolType, VariableSymbol,
     },
+    diagnostics::{ControlFlowKind, Diagnostic},
     files::{FileVersion, Files},
     grammar::{alpha034::*, Spanned},
     paths::FileId,
@@ -210,8 +214,9 @@ pub fn analyze_stmnt(
             match &vars {
                 IterLoopVars::WithIndex((var1, var1_span), (var2, var2_span)) => {
                     let mut symbol_table = files.symbol_table.entry(file).or_default();
+                    let symbol_table = Arc::make_mut(&mut symbol_table);
                     insert_symbol_definition(
-                        &mut symbol_table,
+                        symbol_table,
                         &SymbolInfo {
                             name: var1.clone(),
                             data_type: DataType::Number,
@@ -227,7 +232,7 @@ pub fn analyze_stmnt(
                     );
 
                     insert_symbol_definition(
-                        &mut symbol_table,
+                        symbol_table,
                         &SymbolInfo {
                             name: var2.to_string(),
                             symbol_type: SymbolType::Variable(VariableSymbol { is_const: false }),
@@ -244,8 +249,9 @@ pub fn analyze_stmnt(
                 }
                 IterLoopVars::Single((var, var_span)) => {
                     let mut symbol_table = files.symbol_table.entry(file).or_default();
+                    let symbol_table = Arc::make_mut(&mut symbol_table);
                     insert_symbol_definition(
-                        &mut symbol_table,
+                        symbol_table,
                         &SymbolInfo {
                             name: var.to_string(),
                             symbol_type: SymbolType::Variable(VariableSymbol { is_const: false }),
@@ -290,8 +296,9 @@ pub fn analyze_stmnt(
             };
 
             let mut symbol_table = files.symbol_table.entry(file).or_default();
+            let symbol_table = Arc::make_mut(&mut symbol_table);
             insert_symbol_definition(
-                &mut symbol_table,
+                symbol_table,
                 &SymbolInfo {
                     name: var_name.to_string(),
                     symbol_type: SymbolType::Variable(VariableSymbol { is_const: false }),
@@ -318,8 +325,9 @@ pub fn analyze_stmnt(
             );
 
             let mut symbol_table = files.symbol_table.entry(file).or_default();
+            let symbol_table = Arc::make_mut(&mut symbol_table);
             insert_symbol_definition(
-                &mut symbol_table,
+                symbol_table,
                 &SymbolInfo {
                     name: var_name.to_string(),
                     symbol_type: SymbolType::Variable(VariableSymbol { is_const: true }),
@@ -361,9 +369,11 @@ pub fn analyze_stmnt(
                 .iter()
                 .any(|c| matches!(c, Context::Function(_) | Context::Main))
             {
-                files.report_error(
+                files.report(
                     &file,
-                    "Fail statements can only be used inside of functions or the main block",
+                    Diagnostic::MisplacedControlFlow {
+                        kind: ControlFlowKind::Fail,
+                    },
                     *span,
                 );
             }
@@ -382,7 +392,13 @@ pub fn analyze_stmnt(
         }
         Statement::Return(_, exp) => {
             if !contexts.iter().any(|c| matches!(c, Context::Function(_))) {
-                files.report_error(&file, "Return statement outside of function", *span);
+                files.report(
+                    &file,
+                    Diagnostic::MisplacedControlFlow {
+                        kind: ControlFlowKind::Return,
+                    },
+                    *span,
+                );
             }
 
             if let Some(exp) = exp {
@@ -429,12 +445,13 @@ pub fn analyze_stmnt(
             if !matches_type(&default_ty, &var_ty, scoped_generic_types)
                 || !matches_type(&exp_ty, &var_ty, scoped_generic_types)
             {
-                files.report_error(
+                files.report(
                     &file,
-                    &format!(
-                        "Cannot add to variable of type {}",
-                        var_ty.to_string(scoped_generic_types)
-                    ),
+                    Diagnostic::TypeMismatch {
+                        expected: default_ty.to_string(scoped_generic_types),
+                        found: var_ty.to_string(scoped_generic_types),
+                        definition: get_related_definition(files, var, &file, var_span.start),
+                    },
                     *var_span,
                 );
             }
@@ -458,12 +475,13 @@ pub fn analyze_stmnt(
             };
 
             if !matches_type(&DataType::Number, &var_ty, scoped_generic_types) {
-                files.report_error(
+                files.report(
                     &file,
-                    &format!(
-                        "Cannot divide variable of type {}",
-                        var_ty.to_string(scoped_generic_types)
-                    ),
+                    Diagnostic::TypeMismatch {
+                        expected: DataType::Number.to_string(scoped_generic_types),
+                        found: var_ty.to_string(scoped_generic_types),
+                        definition: get_related_definition(files, var, &file, var_span.start),
+                    },
                     *var_span,
                 );
             }
@@ -497,12 +515,13 @@ pub fn analyze_stmnt(
             };
 
             if !matches_type(&DataType::Number, &var_ty, scoped_generic_types) {
-                files.report_error(
+                files.report(
                     &file,
-                    &format!(
-                        "Cannot use modulo with variable of type {}",
-                        var_ty.to_string(scoped_generic_types)
-                    ),
+                    Diagnostic::TypeMismatch {
+                        expected: DataType::Number.to_string(scoped_generic_types),
+                        found: var_ty.to_string(scoped_generic_types),
+                        definition: get_related_definition(files, var, &file, var_span.start),
+                    },
                     *var_span,
                 );
             }
@@ -536,12 +555,13 @@ pub fn analyze_stmnt(
             };
 
             if !matches_type(&DataType::Number, &var_ty, scoped_generic_types) {
-                files.report_error(
+                files.report(
                     &file,
-                    &format!(
-                        "Cannot use multiply with variable of type {}",
-                        var_ty.to_string(scoped_generic_types)
-                    ),
+                    Diagnostic::TypeMismatch {
+                        expected: DataType::Number.to_string(scoped_generic_types),
+                        found: var_ty.to_string(scoped_generic_types),
+                        definition: get_related_definition(files, var, &file, var_span.start),
+                    },
                     *var_span,
                 );
             }
@@ -575,12 +595,13 @@ pub fn analyze_stmnt(
             };
 
             if !matches_type(&DataType::Number, &var_ty, scoped_generic_types) {
-                files.report_error(
+                files.report(
                     &file,
-                    &format!(
-                        "Cannot use subtract with variable of type {}",
-                        var_ty.to_string(scoped_generic_types)
-                    ),
+                    Diagnostic::TypeMismatch {
+                        expected: DataType::Number.to_string(scoped_generic_types),
+                        found: var_ty.to_string(scoped_generic_types),
+                        definition: get_related_definition(files, var, &file, var_span.start),
+                    },
                     *var_span,
                 );
             }
@@ -637,12 +658,24 @@ pub fn analyze_stmnt(
         }
         Statement::Break => {
             if !contexts.iter().any(|c| matches!(c, Context::Loop)) {
-                files.report_error(&file, "Break statement outside of loop", *span);
+                files.report(
+                    &file,
+                    Diagnostic::MisplacedControlFlow {
+                        kind: ControlFlowKind::Break,
+                    },
+                    *span,
+                );
             }
         }
         Statement::Continue => {
             if !contexts.iter().any(|c| matches!(c, Context::Loop)) {
-                files.report_error(&file, "Continue statement outside of loop", *span);
+                files.report(
+                    &file,
+                    Diagnostic::MisplacedControlFlow {
+                        kind: ControlFlowKind::Continue,
+                    },
+                    *span,
+                );
             }
         }
         Statement::Comment(_) | Statement::Shebang(_) | Statement::Error => {}
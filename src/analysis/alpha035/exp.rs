@@ -1,14 +1,15 @@
-use std::vec;
+use std::{sync::Arc, vec};
 
 use chumsky::span::SimpleSpan;
 
 use crate::{
     analysis::{
-        get_symbol_definition_info, insert_symbol_reference,
+        get_related_definition, get_symbol_definition_info, insert_symbol_reference,
         types::{make_union_type, matches_type, DataType, GenericsMap},
         BlockContext, Context, FunctionArgument, FunctionSymbol, SymbolInfo, SymbolLocation,
         SymbolType, VariableSymbol,
     },
+    diagnostics::{Diagnostic, FailableKind},
     files::{FileVersion, Files},
     grammar::{
         alpha035::{Expression, InterpolatedCommand, InterpolatedText},
@@ -65,12 +66,24 @@ pub fn analyze_exp(
                     .map(|(arg, _)| (arg.data_type.clone(), arg.is_optional, arg.is_ref))
                     .collect::<Vec<(DataType, bool, bool)>>(),
                 Some(_) => {
-                    files.report_error(&file, &format!("{name} is not a function"), *name_span);
+                    files.report(
+                        &file,
+                        Diagnostic::NotAFunction {
+                            name: name.to_string(),
+                        },
+                        *name_span,
+                    );
 
                     vec![]
                 }
                 None => {
-                    files.report_error(&file, &format!("{name} is not defined"), *exp_span);
+                    files.report(
+                        &file,
+                        Diagnostic::UndefinedSymbol {
+                            name: name.to_string(),
+                        },
+                        *exp_span,
+                    );
 
                     vec![]
                 }
@@ -99,9 +112,12 @@ pub fn analyze_exp(
                             {
                                 if let SymbolType::Variable(ref var_symbol) = var.symbol_type {
                                     if var_symbol.is_const {
-                                        files.report_error(
+                                        files.report(
                                             &file,
-                                            "Cannot modify a constant variable",
+                                            Diagnostic::ConstantAssignment {
+                                                name: name.to_string(),
+                                                by_reference: true,
+                                            },
                                             span,
                                         );
                                     }
@@ -109,11 +125,7 @@ pub fn analyze_exp(
                             }
                         }
                         (true, _) => {
-                            files.report_error(
-                                &file,
-                                "Cannot pass a non-variable as a reference",
-                                arg.1,
-                            );
+                            files.report(&file, Diagnostic::InvalidReference, arg.1);
                         }
                         _ => {}
                     }
@@ -125,9 +137,11 @@ pub fn analyze_exp(
                         scoped_generic_types.constrain_generic_type(*id, exp_ty.clone());
                     }
                 } else {
-                    files.report_error(
+                    files.report(
                         &file,
-                        &format!("Function takes only {} arguments", expected_types.len()),
+                        Diagnostic::TooManyArguments {
+                            expected: expected_types.len(),
+                        },
                         arg.1,
                     );
                 }
@@ -140,9 +154,11 @@ pub fn analyze_exp(
                 > args.len()
                 && fun_symbol.is_some()
             {
-                files.report_error(
+                files.report(
                     &file,
-                    &format!("Function takes {} arguments", expected_types.len()),
+                    Diagnostic::MissingArguments {
+                        expected: expected_types.len(),
+                    },
                     *name_span,
                 );
             };
@@ -186,6 +202,7 @@ pub fn analyze_exp(
                         };
                     }
                 };
+                let symbol_table = Arc::make_mut(&mut symbol_table);
 
                 let mut last_span = SimpleSpan::new(name_span.end, name_span.end);
                 symbol_table.symbols.insert(
@@ -241,9 +258,12 @@ pub fn analyze_exp(
                 })
                 && failure.is_none()
             {
-                files.report_error(
+                files.report(
                     &file,
-                    "Failable function must be handled with a failure handler or marked as unsafe",
+                    Diagnostic::MissingFailureHandler {
+                        kind: FailableKind::Function,
+                        modifier: CommandModifier::Unsafe,
+                    },
                     *name_span,
                 );
             }
@@ -266,7 +286,13 @@ pub fn analyze_exp(
             match get_symbol_definition_info(files, name, &file, name_span.start) {
                 Some(info) => {
                     if matches!(info.symbol_type, SymbolType::Function(_)) {
-                        files.report_error(&file, &format!("{name} is a function"), *name_span);
+                        files.report(
+                            &file,
+                            Diagnostic::FunctionAsValue {
+                                name: name.to_string(),
+                            },
+                            *name_span,
+                        );
                     }
 
                     info.data_type
@@ -319,13 +345,13 @@ pub fn analyze_exp(
             }
 
             if !matches_type(&right_hand_ty, &ty, scoped_generic_types) {
-                files.report_error(
+                files.report(
                     &file,
-                    &format!(
-                        "Expected type {}, found type {}",
-                        right_hand_ty.to_string(scoped_generic_types),
-                        ty.to_string(scoped_generic_types),
-                    ),
+                    Diagnostic::TypeMismatch {
+                        expected: right_hand_ty.to_string(scoped_generic_types),
+                        found: ty.to_string(scoped_generic_types),
+                        definition: None,
+                    },
                     exp1.1,
                 );
             }
@@ -395,11 +421,7 @@ pub fn analyze_exp(
             let array_type = make_union_type(types);
 
             if let DataType::Union(_) = array_type {
-                files.report_error(
-                    &file,
-                    "Array must have elements of the same type",
-                    *exp_span,
-                );
+                files.report(&file, Diagnostic::MixedArrayTypes, *exp_span);
             }
 
             DataType::Array(Box::new(array_type))
@@ -471,7 +493,14 @@ pub fn analyze_exp(
                     _ => false,
                 })
             {
-                files.report_error(&file, "Command must have a failure handler", *exp_span);
+                files.report(
+                    &file,
+                    Diagnostic::MissingFailureHandler {
+                        kind: FailableKind::Command,
+                        modifier: CommandModifier::Unsafe,
+                    },
+                    *exp_span,
+                );
             }
 
             DataType::Text
@@ -1061,6 +1090,7 @@ pub fn analyze_exp(
         Expression::Null => DataType::Null,
         Expression::Status => {
             let mut symbol_table = files.symbol_table.get_mut(&file).unwrap();
+            let symbol_table = Arc::make_mut(&mut symbol_table);
             symbol_table.symbols.insert(
                 exp_span_inclusive,
                 SymbolInfo {
@@ -1080,13 +1110,20 @@ pub fn analyze_exp(
     };
 
     if !matches_type(&expected_type, &ty, scoped_generic_types) {
-        files.report_error(
+        let definition = match exp {
+            Expression::Var((name, _)) | Expression::FunctionInvocation(_, (name, _), _, _) => {
+                get_related_definition(files, name, &file, exp_span.start)
+            }
+            _ => None,
+        };
+
+        files.report(
             &file,
-            &format!(
-                "Expected type {}, found type {}",
-                expected_type.to_string(scoped_generic_types),
-                ty.to_string(scoped_generic_types)
-            ),
+            Diagnostic::TypeMismatch {
+                expected: expected_type.to_string(scoped_generic_types),
+                found: ty.to_string(scoped_generic_types),
+                definition,
+            },
             *exp_span,
         );
     } else if let DataType::Generic(id) = ty {
@@ -1,9 +1,13 @@
+use std::sync::Arc;
+
 use crate::{
     analysis::{
-        get_symbol_definition_info, insert_symbol_definition, insert_symbol_reference,
+        get_related_definition, get_symbol_definition_info, insert_symbol_definition,
+        insert_symbol_reference,
         types::{make_union_type, matches_type, GenericsMap},
         BlockContext, Context, DataType, SymbolInfo, SymbolLocation, SymbolType, VariableSymbol,
     },
+    diagnostics::{ControlFlowKind, Diagnostic, FailableKind},
     files::{FileVersion, Files},
     grammar::{
         alpha035::{
@@ -273,8 +277,9 @@ pub fn analyze_stmnt(
             match &vars {
                 IterLoopVars::WithIndex((var1, var1_span), (var2, var2_span)) => {
                     let mut symbol_table = files.symbol_table.entry(file).or_default();
+                    let symbol_table = Arc::make_mut(&mut symbol_table);
                     insert_symbol_definition(
-                        &mut symbol_table,
+                        symbol_table,
                         &SymbolInfo {
                             name: var1.to_string(),
                             symbol_type: SymbolType::Variable(VariableSymbol { is_const: false }),
@@ -290,7 +295,7 @@ pub fn analyze_stmnt(
                     );
 
                     insert_symbol_definition(
-                        &mut symbol_table,
+                        symbol_table,
                         &SymbolInfo {
                             name: var2.to_string(),
                             symbol_type: SymbolType::Variable(VariableSymbol { is_const: false }),
@@ -307,8 +312,9 @@ pub fn analyze_stmnt(
                 }
                 IterLoopVars::Single((var, var_span)) => {
                     let mut symbol_table = files.symbol_table.entry(file).or_default();
+                    let symbol_table = Arc::make_mut(&mut symbol_table);
                     insert_symbol_definition(
-                        &mut symbol_table,
+                        symbol_table,
                         &SymbolInfo {
                             name: var.to_string(),
                             symbol_type: SymbolType::Variable(VariableSymbol { is_const: false }),
@@ -364,6 +370,7 @@ pub fn analyze_stmnt(
             };
 
             let mut symbol_table = files.symbol_table.entry(file).or_default();
+            let symbol_table = Arc::make_mut(&mut symbol_table);
 
             let var_type = match exp.exp_ty {
                 DataType::Failable(ty) => scoped_generic_types.deref_type(&ty),
@@ -371,7 +378,7 @@ pub fn analyze_stmnt(
             };
 
             insert_symbol_definition(
-                &mut symbol_table,
+                symbol_table,
                 &SymbolInfo {
                     name: var_name.to_string(),
                     symbol_type: SymbolType::Variable(VariableSymbol { is_const: false }),
@@ -403,6 +410,7 @@ pub fn analyze_stmnt(
             );
 
             let mut symbol_table = files.symbol_table.entry(file).or_default();
+            let symbol_table = Arc::make_mut(&mut symbol_table);
 
             let var_type = match exp.exp_ty {
                 DataType::Failable(ty) => scoped_generic_types.deref_type(&ty),
@@ -410,7 +418,7 @@ pub fn analyze_stmnt(
             };
 
             insert_symbol_definition(
-                &mut symbol_table,
+                symbol_table,
                 &SymbolInfo {
                     name: const_name.to_string(),
                     symbol_type: SymbolType::Variable(VariableSymbol { is_const: true }),
@@ -467,9 +475,11 @@ pub fn analyze_stmnt(
                 .iter()
                 .any(|c| matches!(c, Context::Function(_) | Context::Main))
             {
-                files.report_error(
+                files.report(
                     &file,
-                    "Fail statements can only be used inside of functions or the main block",
+                    Diagnostic::MisplacedControlFlow {
+                        kind: ControlFlowKind::Fail,
+                    },
                     *span,
                 );
             }
@@ -498,7 +508,13 @@ pub fn analyze_stmnt(
         }
         Statement::Return(_, exp) => {
             if !contexts.iter().any(|c| matches!(c, Context::Function(_))) {
-                files.report_error(&file, "Return statement outside of function", *span);
+                files.report(
+                    &file,
+                    Diagnostic::MisplacedControlFlow {
+                        kind: ControlFlowKind::Return,
+                    },
+                    *span,
+                );
             }
 
             if let Some(exp) = exp {
@@ -558,12 +574,13 @@ pub fn analyze_stmnt(
             if !matches_type(&default_ty, &var_ty, scoped_generic_types)
                 || !matches_type(&exp_analysis.exp_ty, &var_ty, scoped_generic_types)
             {
-                files.report_error(
+                files.report(
                     &file,
-                    &format!(
-                        "Cannot add to variable of type {}",
-                        var_ty.to_string(scoped_generic_types)
-                    ),
+                    Diagnostic::TypeMismatch {
+                        expected: default_ty.to_string(scoped_generic_types),
+                        found: var_ty.to_string(scoped_generic_types),
+                        definition: get_related_definition(files, var, &file, var_span.start),
+                    },
                     *var_span,
                 );
             }
@@ -592,12 +609,13 @@ pub fn analyze_stmnt(
             };
 
             if !matches_type(&DataType::Number, &var_ty, scoped_generic_types) {
-                files.report_error(
+                files.report(
                     &file,
-                    &format!(
-                        "Cannot divide variable of type {}",
-                        var_ty.to_string(scoped_generic_types)
-                    ),
+                    Diagnostic::TypeMismatch {
+                        expected: DataType::Number.to_string(scoped_generic_types),
+                        found: var_ty.to_string(scoped_generic_types),
+                        definition: get_related_definition(files, var, &file, var_span.start),
+                    },
                     *var_span,
                 );
             }
@@ -636,12 +654,13 @@ pub fn analyze_stmnt(
             };
 
             if !matches_type(&DataType::Number, &var_ty, scoped_generic_types) {
-                files.report_error(
+                files.report(
                     &file,
-                    &format!(
-                        "Cannot use modulo with variable of type {}",
-                        var_ty.to_string(scoped_generic_types)
-                    ),
+                    Diagnostic::TypeMismatch {
+                        expected: DataType::Number.to_string(scoped_generic_types),
+                        found: var_ty.to_string(scoped_generic_types),
+                        definition: get_related_definition(files, var, &file, var_span.start),
+                    },
                     *var_span,
                 );
             }
@@ -680,12 +699,13 @@ pub fn analyze_stmnt(
             };
 
             if !matches_type(&DataType::Number, &var_ty, scoped_generic_types) {
-                files.report_error(
+                files.report(
                     &file,
-                    &format!(
-                        "Cannot use multiply with variable of type {}",
-                        var_ty.to_string(scoped_generic_types)
-                    ),
+                    Diagnostic::TypeMismatch {
+                        expected: DataType::Number.to_string(scoped_generic_types),
+                        found: var_ty.to_string(scoped_generic_types),
+                        definition: get_related_definition(files, var, &file, var_span.start),
+                    },
                     *var_span,
                 );
             }
@@ -724,12 +744,13 @@ pub fn analyze_stmnt(
             };
 
             if !matches_type(&DataType::Number, &var_ty, scoped_generic_types) {
-                files.report_error(
+                files.report(
                     &file,
-                    &format!(
-                        "Cannot use subtract with variable of type {}",
-                        var_ty.to_string(scoped_generic_types)
-                    ),
+                    Diagnostic::TypeMismatch {
+                        expected: DataType::Number.to_string(scoped_generic_types),
+                        found: var_ty.to_string(scoped_generic_types),
+                        definition: get_related_definition(files, var, &file, var_span.start),
+                    },
                     *var_span,
                 );
             }
@@ -796,7 +817,13 @@ pub fn analyze_stmnt(
         }
         Statement::Break => {
             if !contexts.iter().any(|c| matches!(c, Context::Loop)) {
-                files.report_error(&file, "Break statement outside of loop", *span);
+                files.report(
+                    &file,
+                    Diagnostic::MisplacedControlFlow {
+                        kind: ControlFlowKind::Break,
+                    },
+                    *span,
+                );
             }
 
             StmntAnalysisResult {
@@ -806,7 +833,13 @@ pub fn analyze_stmnt(
         }
         Statement::Continue => {
             if !contexts.iter().any(|c| matches!(c, Context::Loop)) {
-                files.report_error(&file, "Continue statement outside of loop", *span);
+                files.report(
+                    &file,
+                    Diagnostic::MisplacedControlFlow {
+                        kind: ControlFlowKind::Continue,
+                    },
+                    *span,
+                );
             }
 
             StmntAnalysisResult {
@@ -865,7 +898,14 @@ pub fn analyze_stmnt(
                 .iter()
                 .any(|(modifier, _)| *modifier == CommandModifier::Unsafe)
             {
-                files.report_error(&file, "Command must have a failure handler", *span);
+                files.report(
+                    &file,
+                    Diagnostic::MissingFailureHandler {
+                        kind: FailableKind::Command,
+                        modifier: CommandModifier::Unsafe,
+                    },
+                    *span,
+                );
             }
 
             get_stmnt_analysis_result(vec![], vec![exp1, exp2])
@@ -985,9 +1025,11 @@ pub fn analyze_failure_handler(
                 .iter()
                 .any(|c| *c == Context::Main || matches!(c, Context::Function(_)))
             {
-                files.report_error(
+                files.report(
                     &(file_id, file_version),
-                    "Propagate can only be used inside of main block or function",
+                    Diagnostic::MisplacedControlFlow {
+                        kind: ControlFlowKind::Propagate,
+                    },
                     *span,
                 );
             }
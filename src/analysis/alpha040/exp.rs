@@ -1,14 +1,15 @@
-use std::vec;
+use std::{sync::Arc, vec};
 
 use chumsky::span::SimpleSpan;
 
 use crate::{
     analysis::{
-        get_symbol_definition_info, insert_symbol_reference,
+        get_related_definition, get_symbol_definition_info, insert_symbol_reference,
         types::{make_union_type, matches_type, DataType, GenericsMap},
         BlockContext, Context, FunctionArgument, FunctionSymbol, SymbolInfo, SymbolLocation,
         SymbolType, VariableSymbol,
     },
+    diagnostics::{Diagnostic, FailableKind},
     files::{FileVersion, Files},
     grammar::{
         alpha040::{Expression, InterpolatedCommand, InterpolatedText},
@@ -140,12 +141,24 @@ pub fn analyze_exp(
                     .map(|(arg, _)| (arg.data_type.clone(), arg.is_optional, arg.is_ref))
                     .collect::<Vec<(DataType, bool, bool)>>(),
                 Some(_) => {
-                    files.report_error(&file, &format!("{name} is not a function"), *exp_span);
+                    files.report(
+                        &file,
+                        Diagnostic::NotAFunction {
+                            name: name.to_string(),
+                        },
+                        *exp_span,
+                    );
 
                     vec![]
                 }
                 None => {
-                    files.report_error(&file, &format!("{name} is not defined"), *exp_span);
+                    files.report(
+                        &file,
+                        Diagnostic::UndefinedSymbol {
+                            name: name.to_string(),
+                        },
+                        *exp_span,
+                    );
 
                     vec![]
                 }
@@ -174,9 +187,12 @@ pub fn analyze_exp(
                             {
                                 if let SymbolType::Variable(ref var_symbol) = var.symbol_type {
                                     if var_symbol.is_const {
-                                        files.report_error(
+                                        files.report(
                                             &file,
-                                            "Cannot modify a constant variable",
+                                            Diagnostic::ConstantAssignment {
+                                                name: name.to_string(),
+                                                by_reference: true,
+                                            },
                                             span,
                                         );
                                     }
@@ -184,11 +200,7 @@ pub fn analyze_exp(
                             }
                         }
                         (true, _) => {
-                            files.report_error(
-                                &file,
-                                "Cannot pass a non-variable as a reference",
-                                arg.1,
-                            );
+                            files.report(&file, Diagnostic::InvalidReference, arg.1);
                         }
                         _ => {}
                     }
@@ -200,9 +212,11 @@ pub fn analyze_exp(
                         scoped_generic_types.constrain_generic_type(*id, exp_ty.clone());
                     }
                 } else {
-                    files.report_error(
+                    files.report(
                         &file,
-                        &format!("Function takes only {} arguments", expected_types.len()),
+                        Diagnostic::TooManyArguments {
+                            expected: expected_types.len(),
+                        },
                         arg.1,
                     );
                 }
@@ -215,9 +229,11 @@ pub fn analyze_exp(
                 > args.len()
                 && fun_symbol.is_some()
             {
-                files.report_error(
+                files.report(
                     &file,
-                    &format!("Function takes {} arguments", expected_types.len()),
+                    Diagnostic::MissingArguments {
+                        expected: expected_types.len(),
+                    },
                     *name_span,
                 );
             };
@@ -265,6 +281,7 @@ pub fn analyze_exp(
                         }
                     }
                 };
+                let symbol_table = Arc::make_mut(&mut symbol_table);
 
                 let mut last_span_end = name_span.end + 1;
                 let fun_symbol = SymbolInfo {
@@ -322,9 +339,12 @@ pub fn analyze_exp(
                 _ => true,
             }) && failure.is_none()
             {
-                files.report_error(
+                files.report(
                     &file,
-                    "Failable function must be handled with a failure handler or marked with `trust` modifier",
+                    Diagnostic::MissingFailureHandler {
+                        kind: FailableKind::Function,
+                        modifier: CommandModifier::Trust,
+                    },
                     *name_span,
                 );
             }
@@ -347,7 +367,13 @@ pub fn analyze_exp(
             match get_symbol_definition_info(files, name, &file, name_span.start) {
                 Some(info) => {
                     if matches!(info.symbol_type, SymbolType::Function(_)) {
-                        files.report_error(&file, &format!("{name} is a function"), *name_span);
+                        files.report(
+                            &file,
+                            Diagnostic::FunctionAsValue {
+                                name: name.to_string(),
+                            },
+                            *name_span,
+                        );
                     }
 
                     info.data_type
@@ -400,13 +426,13 @@ pub fn analyze_exp(
             }
 
             if !matches_type(&right_hand_ty, &ty, scoped_generic_types) {
-                files.report_error(
+                files.report(
                     &file,
-                    &format!(
-                        "Expected type {}, found type {}",
-                        right_hand_ty.to_string(scoped_generic_types),
-                        ty.to_string(scoped_generic_types),
-                    ),
+                    Diagnostic::TypeMismatch {
+                        expected: right_hand_ty.to_string(scoped_generic_types),
+                        found: ty.to_string(scoped_generic_types),
+                        definition: None,
+                    },
                     exp1.1,
                 );
             }
@@ -476,11 +502,7 @@ pub fn analyze_exp(
             let array_type = make_union_type(types);
 
             if let DataType::Union(_) = array_type {
-                files.report_error(
-                    &file,
-                    "Array must have elements of the same type",
-                    *exp_span,
-                );
+                files.report(&file, Diagnostic::MixedArrayTypes, *exp_span);
             }
 
             DataType::Array(Box::new(array_type))
@@ -550,7 +572,14 @@ pub fn analyze_exp(
                 }),
                 _ => false,
             }) {
-                files.report_error(&file, "Command must have a failure handler", *exp_span);
+                files.report(
+                    &file,
+                    Diagnostic::MissingFailureHandler {
+                        kind: FailableKind::Command,
+                        modifier: CommandModifier::Trust,
+                    },
+                    *exp_span,
+                );
             }
 
             DataType::Text
@@ -1149,6 +1178,7 @@ pub fn analyze_exp(
                     };
                 }
             };
+            let symbol_table = Arc::make_mut(&mut symbol_table);
             symbol_table.symbols.insert(
                 exp_span_inclusive,
                 SymbolInfo {
@@ -1168,13 +1198,20 @@ pub fn analyze_exp(
     };
 
     if !matches_type(&expected_type, &ty, scoped_generic_types) {
-        files.report_error(
+        let definition = match exp {
+            Expression::Var((name, _)) | Expression::FunctionInvocation(_, (name, _), _, _) => {
+                get_related_definition(files, name, &file, exp_span.start)
+            }
+            _ => None,
+        };
+
+        files.report(
             &file,
-            &format!(
-                "Expected type `{}`, found type `{}`",
-                expected_type.to_string(scoped_generic_types),
-                ty.to_string(scoped_generic_types)
-            ),
+            Diagnostic::TypeMismatch {
+                expected: expected_type.to_string(scoped_generic_types),
+                found: ty.to_string(scoped_generic_types),
+                definition,
+            },
             *exp_span,
         );
     } else if let DataType::Generic(id) = ty {
@@ -1,11 +1,15 @@
+use std::{io::ErrorKind, sync::Arc};
+
 use crate::{
     analysis::{
-        self, import_symbol, insert_symbol_definition, map_import_path,
+        self, get_import_cycle, import_symbol, insert_symbol_definition, map_import_path,
+        query::FunctionQuery,
         types::{make_union_type, matches_type, DataType},
         Context, FunctionContext, FunctionSymbol, ImportContext, SymbolInfo, SymbolType,
         VariableSymbol,
     },
-    backend::Backend,
+    analyzer::{AmberVersion, Analyzer},
+    diagnostics::Diagnostic,
     files::FileVersion,
     grammar::{
         alpha040::{FunctionArgument, GlobalStatement, ImportContent},
@@ -25,15 +29,15 @@ pub async fn analyze_global_stmnt(
     file_id: FileId,
     file_version: FileVersion,
     ast: &[Spanned<GlobalStatement>],
-    backend: &Backend,
+    analyzer: &Arc<Analyzer>,
 ) {
     let mut contexts = vec![];
 
-    let uri = backend.files.lookup(&file_id);
+    let uri = analyzer.files.lookup(&file_id);
 
     let mut default_imports = vec![];
 
-    if !is_builtin_file(&uri, backend.amber_version.clone()) {
+    if !is_builtin_file(&uri, analyzer, &AmberVersion::Alpha040) {
         default_imports.push((
             GlobalStatement::Import(
                 (false, Span::new(0, 0)),
@@ -47,6 +51,10 @@ pub async fn analyze_global_stmnt(
     }
 
     for (global, span) in default_imports.iter().chain(ast.iter()) {
+        if analyzer.files.is_outdated(&(file_id, file_version)) {
+            return;
+        }
+
         match global {
             GlobalStatement::FunctionDefinition(
                 compiler_flags,
@@ -57,8 +65,28 @@ pub async fn analyze_global_stmnt(
                 declared_return_ty,
                 body,
             ) => {
+                let docs = match contexts.last() {
+                    Some(Context::DocString(docs)) => Some(docs.clone()),
+                    _ => None,
+                };
+                let query = FunctionQuery::new(
+                    &analyzer.files,
+                    (file_id, file_version),
+                    name,
+                    *span,
+                    docs.as_deref(),
+                );
+
+                if query.reuse(&analyzer.files) {
+                    if docs.is_some() {
+                        contexts.pop();
+                    }
+
+                    continue;
+                }
+
                 // We create scoped generics map, to not overwrite other generics, not defined here
-                let scoped_generics_map = backend.files.generic_types.clone();
+                let scoped_generics_map = analyzer.files.generic_types.scoped();
 
                 let mut new_generic_types = vec![];
                 let mut prev_arg_optional = false;
@@ -66,15 +94,14 @@ pub async fn analyze_global_stmnt(
                 args.iter().for_each(|(arg, _)| {
                     let (name, ty, name_span) = match arg {
                         FunctionArgument::Generic(_, (name, span)) => {
-                            let generic_id = scoped_generics_map.new_generic_id();
-
-                            scoped_generics_map.constrain_generic_type(generic_id, DataType::Any);
+                            let generic_id =
+                                scoped_generics_map.new_generic_id((file_id, file_version));
                             new_generic_types.push(generic_id);
 
                             if prev_arg_optional {
-                                backend.files.report_error(
+                                analyzer.files.report(
                                     &(file_id, file_version),
-                                    "Optional argument must be the last one",
+                                    Diagnostic::MisplacedOptionalArgument,
                                     *span,
                                 );
                             }
@@ -83,9 +110,9 @@ pub async fn analyze_global_stmnt(
                         }
                         FunctionArgument::Typed(_, (name, span), (ty, _)) => {
                             if prev_arg_optional {
-                                backend.files.report_error(
+                                analyzer.files.report(
                                     &(file_id, file_version),
-                                    "Optional argument must be the last one",
+                                    Diagnostic::MisplacedOptionalArgument,
                                     *span,
                                 );
                             }
@@ -96,9 +123,9 @@ pub async fn analyze_global_stmnt(
                             prev_arg_optional = true;
 
                             if *is_ref {
-                                backend.files.report_error(
+                                analyzer.files.report(
                                     &(file_id, file_version),
-                                    "Optional argument cannot be a reference",
+                                    Diagnostic::OptionalReference,
                                     *span,
                                 );
                             }
@@ -112,18 +139,16 @@ pub async fn analyze_global_stmnt(
                                             file_version,
                                             exp,
                                             ty.clone(),
-                                            &backend.files,
-                                            &backend.files.generic_types.clone(),
+                                            &analyzer.files,
+                                            &analyzer.files.generic_types.scoped(),
                                             &vec![],
                                         );
 
                                         ty.clone()
                                     }
                                     None => {
-                                        let generic_id = scoped_generics_map.new_generic_id();
-
-                                        scoped_generics_map
-                                            .constrain_generic_type(generic_id, DataType::Any);
+                                        let generic_id = scoped_generics_map
+                                            .new_generic_id((file_id, file_version));
                                         new_generic_types.push(generic_id);
 
                                         DataType::Generic(generic_id)
@@ -135,21 +160,25 @@ pub async fn analyze_global_stmnt(
                         FunctionArgument::Error => return,
                     };
 
-                    let mut symbol_table =
-                        match backend.files.symbol_table.get_mut(&(file_id, file_version)) {
-                            Some(symbol_table) => symbol_table,
-                            None => {
-                                tracing::warn!(
-                                    "Symbol table not found for file: {:?}, version: {}",
-                                    file_id,
-                                    file_version.0,
-                                );
-                                return;
-                            }
-                        };
+                    let mut symbol_table = match analyzer
+                        .files
+                        .symbol_table
+                        .get_mut(&(file_id, file_version))
+                    {
+                        Some(symbol_table) => symbol_table,
+                        None => {
+                            tracing::warn!(
+                                "Symbol table not found for file: {:?}, version: {}",
+                                file_id,
+                                file_version.0,
+                            );
+                            return;
+                        }
+                    };
+                    let symbol_table = Arc::make_mut(&mut symbol_table);
 
                     insert_symbol_definition(
-                        &mut symbol_table,
+                        symbol_table,
                         &SymbolInfo {
                             name: name.to_string(),
                             symbol_type: SymbolType::Variable(VariableSymbol { is_const: false }),
@@ -180,7 +209,7 @@ pub async fn analyze_global_stmnt(
                         file_id,
                         file_version,
                         stmnt,
-                        &backend.files,
+                        &analyzer.files,
                         span.end,
                         &scoped_generics_map,
                         &mut function_contexts,
@@ -191,11 +220,7 @@ pub async fn analyze_global_stmnt(
                 });
 
                 new_generic_types.iter().for_each(|generic_id| {
-                    backend
-                        .files
-                        .generic_types
-                        .constrain_generic_type(*generic_id, scoped_generics_map.get(*generic_id));
-                    backend.files.generic_types.mark_as_inferred(*generic_id);
+                    analyzer.files.generic_types.mark_as_inferred(*generic_id);
                 });
 
                 let mut inferred_return_type = match return_types.len() {
@@ -209,20 +234,23 @@ pub async fn analyze_global_stmnt(
 
                 let data_type = match declared_return_ty {
                     Some((ty, ty_span)) => {
-                        if !matches_type(ty, &inferred_return_type, &backend.files.generic_types) {
-                            backend.files.report_error(
+                        if !matches_type(ty, &inferred_return_type, &analyzer.files.generic_types) {
+                            analyzer.files.report(
                                 &(file_id, file_version),
-                                &format!(
-                                    "Function returns type {inferred_return_type:?}, but expected {ty:?}",
-                                ),
+                                Diagnostic::TypeMismatch {
+                                    expected: ty.to_string(&analyzer.files.generic_types),
+                                    found: inferred_return_type
+                                        .to_string(&analyzer.files.generic_types),
+                                    definition: None,
+                                },
                                 *ty_span,
                             );
                         }
 
                         if is_propagating && !matches!(ty, DataType::Failable(_)) {
-                            backend.files.report_error(
+                            analyzer.files.report(
                                 &(file_id, file_version),
-                                "Function is propagating an error, but return type is not failable",
+                                Diagnostic::NonFailablePropagation,
                                 *ty_span,
                             );
                         }
@@ -232,14 +260,15 @@ pub async fn analyze_global_stmnt(
                     None => inferred_return_type,
                 };
 
-                let mut symbol_table = backend
+                let mut symbol_table_entry = analyzer
                     .files
                     .symbol_table
                     .entry((file_id, file_version))
                     .or_default();
+                let symbol_table = Arc::make_mut(&mut symbol_table_entry);
 
                 insert_symbol_definition(
-                    &mut symbol_table,
+                    symbol_table,
                     &SymbolInfo {
                         name: name.to_string(),
                         symbol_type: SymbolType::Function(FunctionSymbol {
@@ -310,6 +339,9 @@ pub async fn analyze_global_stmnt(
                     span.end..=usize::MAX,
                     *is_pub,
                 );
+
+                drop(symbol_table_entry);
+                query.record(&analyzer.files);
             }
             GlobalStatement::Import(
                 (is_public_import, _),
@@ -318,21 +350,24 @@ pub async fn analyze_global_stmnt(
                 _,
                 (path, path_span),
             ) => {
-                let uri = &backend.files.lookup(&file_id);
+                let uri = &analyzer.files.lookup(&file_id);
 
-                let result = backend
-                    .open_document(&map_import_path(uri, path, backend).await)
-                    .await;
+                let result =
+                    match map_import_path(uri, path, analyzer, &AmberVersion::Alpha040).await {
+                        Some(import_uri) => analyzer.open_document(&import_uri).await,
+                        None => Err(ErrorKind::NotFound.into()),
+                    };
 
                 {
-                    let mut symbol_table = backend
+                    let mut symbol_table = analyzer
                         .files
                         .symbol_table
                         .entry((file_id, file_version))
                         .or_default();
+                    let symbol_table = Arc::make_mut(&mut symbol_table);
 
                     insert_symbol_definition(
-                        &mut symbol_table,
+                        symbol_table,
                         &SymbolInfo {
                             name: path.to_string(),
                             symbol_type: SymbolType::ImportPath,
@@ -348,34 +383,38 @@ pub async fn analyze_global_stmnt(
                     );
                 }
 
-                if result.is_err() {
-                    backend.files.report_error(
+                let Ok(imported_file) = result else {
+                    analyzer.files.report(
                         &(file_id, file_version),
-                        "File doesn't exist",
+                        Diagnostic::FileNotFound {
+                            path: path.to_string(),
+                        },
                         *path_span,
                     );
 
                     continue;
-                }
-
-                let imported_file = result.clone().unwrap();
-
-                if backend.files.is_depending_on(&imported_file, file_id) {
-                    backend.files.report_error(
-                        &(file_id, file_version),
-                        "Circular dependency",
-                        *path_span,
-                    );
+                };
 
-                    continue;
+                if let Some(cycle) = get_import_cycle(
+                    &analyzer.files,
+                    &(file_id, file_version),
+                    &imported_file,
+                    path,
+                    *path_span,
+                ) {
+                    analyzer
+                        .files
+                        .report(&(file_id, file_version), cycle, *path_span);
                 }
 
-                backend
-                    .files
-                    .add_file_dependency(&(file_id, file_version), imported_file.0);
+                analyzer.files.add_file_dependency(
+                    &(file_id, file_version),
+                    imported_file.0,
+                    *path_span,
+                );
 
                 let imported_file_symbol_table =
-                    match backend.files.symbol_table.get(&imported_file) {
+                    match analyzer.files.symbol_table.get(&imported_file) {
                         Some(symbol_table_ref) => symbol_table_ref.clone(),
                         None => continue,
                     };
@@ -391,17 +430,20 @@ pub async fn analyze_global_stmnt(
 
                         ident_list.iter().for_each(|(ident, span)| {
                             if import_context.imported_symbols.contains(&ident.to_string()) {
-                                backend.files.report_error(
+                                analyzer.files.report(
                                     &(file_id, file_version),
-                                    &format!("Duplicate import '{ident}'"),
+                                    Diagnostic::DuplicateImport {
+                                        name: ident.to_string(),
+                                    },
                                     *span,
                                 );
 
-                                let mut symbol_table = backend
+                                let mut symbol_table = analyzer
                                     .files
                                     .symbol_table
                                     .entry((file_id, file_version))
                                     .or_default();
+                                let symbol_table = Arc::make_mut(&mut symbol_table);
 
                                 symbol_table.symbols.insert(
                                     span.start..=span.end,
@@ -425,7 +467,7 @@ pub async fn analyze_global_stmnt(
 
                             match symbol_definition {
                                 Some(definition_location) => {
-                                    let definition_file_symbol_table = match backend
+                                    let definition_file_symbol_table = match analyzer
                                         .files
                                         .symbol_table
                                         .get(&definition_location.file)
@@ -442,18 +484,19 @@ pub async fn analyze_global_stmnt(
                                         None => return,
                                     };
 
-                                    let mut symbol_table = backend
+                                    let mut symbol_table = analyzer
                                         .files
                                         .symbol_table
                                         .entry((file_id, file_version))
                                         .or_default();
+                                    let symbol_table = Arc::make_mut(&mut symbol_table);
 
                                     import_symbol(
-                                        &mut symbol_table,
+                                        symbol_table,
                                         &SymbolInfo {
                                             is_definition: false,
                                             contexts: vec![Context::Import(import_context.clone())],
-                                            ..symbol_info.clone()
+                                            ..symbol_info.exported(&analyzer.files.generic_types)
                                         },
                                         Some(span.start..=span.end),
                                         definition_location,
@@ -463,17 +506,20 @@ pub async fn analyze_global_stmnt(
                                     import_context.imported_symbols.push(ident.to_string());
                                 }
                                 None => {
-                                    backend.files.report_error(
+                                    analyzer.files.report(
                                         &(file_id, file_version),
-                                        &format!("Could not resolve '{ident}'"),
+                                        Diagnostic::UnresolvedImport {
+                                            name: ident.to_string(),
+                                        },
                                         *span,
                                     );
 
-                                    let mut symbol_table = backend
+                                    let mut symbol_table = analyzer
                                         .files
                                         .symbol_table
                                         .entry((file_id, file_version))
                                         .or_default();
+                                    let symbol_table = Arc::make_mut(&mut symbol_table);
 
                                     symbol_table.symbols.insert(
                                         span.start..=span.end,
@@ -498,7 +544,7 @@ pub async fn analyze_global_stmnt(
                         .iter()
                         .for_each(|(_, location)| {
                             let definition_file_symbol_table =
-                                match backend.files.symbol_table.get(&location.file) {
+                                match analyzer.files.symbol_table.get(&location.file) {
                                     Some(symbol_table) => symbol_table.clone(),
                                     None => return,
                                 };
@@ -509,14 +555,15 @@ pub async fn analyze_global_stmnt(
                                     None => return,
                                 };
 
-                            let mut symbol_table = backend
+                            let mut symbol_table = analyzer
                                 .files
                                 .symbol_table
                                 .entry((file_id, file_version))
                                 .or_default();
+                            let symbol_table = Arc::make_mut(&mut symbol_table);
 
                             import_symbol(
-                                &mut symbol_table,
+                                symbol_table,
                                 &SymbolInfo {
                                     is_definition: false,
                                     contexts: vec![Context::Import(ImportContext {
@@ -525,7 +572,7 @@ pub async fn analyze_global_stmnt(
                                             .clone(),
                                         imported_symbols: vec![],
                                     })],
-                                    ..symbol_info.clone()
+                                    ..symbol_info.exported(&analyzer.files.generic_types)
                                 },
                                 None,
                                 location,
@@ -536,14 +583,15 @@ pub async fn analyze_global_stmnt(
             }
             GlobalStatement::Main(_, args, body) => {
                 if let Some((args, args_span)) = args {
-                    let mut symbol_table = backend
+                    let mut symbol_table = analyzer
                         .files
                         .symbol_table
                         .entry((file_id, file_version))
                         .or_default();
+                    let symbol_table = Arc::make_mut(&mut symbol_table);
 
                     insert_symbol_definition(
-                        &mut symbol_table,
+                        symbol_table,
                         &SymbolInfo {
                             name: args.to_string(),
                             symbol_type: SymbolType::Variable(VariableSymbol { is_const: false }),
@@ -564,9 +612,9 @@ pub async fn analyze_global_stmnt(
                         file_id,
                         file_version,
                         stmnt,
-                        &backend.files,
+                        &analyzer.files,
                         span.end,
-                        &backend.files.generic_types.clone(),
+                        &analyzer.files.generic_types.scoped(),
                         &mut vec![Context::Main],
                     );
                 });
@@ -576,9 +624,9 @@ pub async fn analyze_global_stmnt(
                     file_id,
                     file_version,
                     stmnt,
-                    &backend.files,
+                    &analyzer.files,
                     usize::MAX,
-                    &backend.files.generic_types.clone(),
+                    &analyzer.files.generic_types.scoped(),
                     &mut contexts,
                 );
             }
@@ -1,9 +1,13 @@
+use std::sync::Arc;
+
 use crate::{
     analysis::{
-        get_symbol_definition_info, insert_symbol_definition, insert_symbol_reference,
+        get_related_definition, get_symbol_definition_info, insert_symbol_definition,
+        insert_symbol_reference,
         types::{make_union_type, matches_type, GenericsMap},
         BlockContext, Context, DataType, SymbolInfo, SymbolLocation, SymbolType, VariableSymbol,
     },
+    diagnostics::{ControlFlowKind, Diagnostic, FailableKind},
     files::{FileVersion, Files},
     grammar::{
         alpha040::{
@@ -278,8 +282,9 @@ pub fn analyze_stmnt(
             match &vars {
                 IterLoopVars::WithIndex((var1, var1_span), (var2, var2_span)) => {
                     let mut symbol_table = files.symbol_table.entry(file).or_default();
+                    let symbol_table = Arc::make_mut(&mut symbol_table);
                     insert_symbol_definition(
-                        &mut symbol_table,
+                        symbol_table,
                         &SymbolInfo {
                             name: var1.to_string(),
                             symbol_type: SymbolType::Variable(VariableSymbol { is_const: false }),
@@ -295,7 +300,7 @@ pub fn analyze_stmnt(
                     );
 
                     insert_symbol_definition(
-                        &mut symbol_table,
+                        symbol_table,
                         &SymbolInfo {
                             name: var2.to_string(),
                             symbol_type: SymbolType::Variable(VariableSymbol { is_const: false }),
@@ -312,8 +317,9 @@ pub fn analyze_stmnt(
                 }
                 IterLoopVars::Single((var, var_span)) => {
                     let mut symbol_table = files.symbol_table.entry(file).or_default();
+                    let symbol_table = Arc::make_mut(&mut symbol_table);
                     insert_symbol_definition(
-                        &mut symbol_table,
+                        symbol_table,
                         &SymbolInfo {
                             name: var.to_string(),
                             symbol_type: SymbolType::Variable(VariableSymbol { is_const: false }),
@@ -369,6 +375,7 @@ pub fn analyze_stmnt(
             };
 
             let mut symbol_table = files.symbol_table.entry(file).or_default();
+            let symbol_table = Arc::make_mut(&mut symbol_table);
 
             let var_type = match exp.exp_ty {
                 DataType::Failable(ty) => scoped_generic_types.deref_type(&ty),
@@ -376,7 +383,7 @@ pub fn analyze_stmnt(
             };
 
             insert_symbol_definition(
-                &mut symbol_table,
+                symbol_table,
                 &SymbolInfo {
                     name: var_name.to_string(),
                     symbol_type: SymbolType::Variable(VariableSymbol { is_const: false }),
@@ -408,6 +415,7 @@ pub fn analyze_stmnt(
             );
 
             let mut symbol_table = files.symbol_table.entry(file).or_default();
+            let symbol_table = Arc::make_mut(&mut symbol_table);
 
             let var_type = match exp.exp_ty {
                 DataType::Failable(ty) => scoped_generic_types.deref_type(&ty),
@@ -415,7 +423,7 @@ pub fn analyze_stmnt(
             };
 
             insert_symbol_definition(
-                &mut symbol_table,
+                symbol_table,
                 &SymbolInfo {
                     name: var_name.to_string(),
                     symbol_type: SymbolType::Variable(VariableSymbol { is_const: true }),
@@ -472,9 +480,11 @@ pub fn analyze_stmnt(
                 .iter()
                 .any(|c| matches!(c, Context::Function(_) | Context::Main))
             {
-                files.report_error(
+                files.report(
                     &file,
-                    "Fail statements can only be used inside of functions or the main block",
+                    Diagnostic::MisplacedControlFlow {
+                        kind: ControlFlowKind::Fail,
+                    },
                     *span,
                 );
             }
@@ -503,7 +513,13 @@ pub fn analyze_stmnt(
         }
         Statement::Return(_, exp) => {
             if !contexts.iter().any(|c| matches!(c, Context::Function(_))) {
-                files.report_error(&file, "Return statement outside of function", *span);
+                files.report(
+                    &file,
+                    Diagnostic::MisplacedControlFlow {
+                        kind: ControlFlowKind::Return,
+                    },
+                    *span,
+                );
             }
 
             if let Some(exp) = exp {
@@ -540,10 +556,23 @@ pub fn analyze_stmnt(
                 Some(info) => {
                     match info.symbol_type {
                         SymbolType::Function(_) => {
-                            files.report_error(&file, "Cannot assign to a function", *var_span);
+                            files.report(
+                                &file,
+                                Diagnostic::FunctionAssignment {
+                                    name: var.to_string(),
+                                },
+                                *var_span,
+                            );
                         }
-                        SymbolType::Variable(var) if var.is_const => {
-                            files.report_error(&file, "Cannot assign to a constant", *var_span);
+                        SymbolType::Variable(variable) if variable.is_const => {
+                            files.report(
+                                &file,
+                                Diagnostic::ConstantAssignment {
+                                    name: var.to_string(),
+                                    by_reference: false,
+                                },
+                                *var_span,
+                            );
                         }
                         _ => {}
                     }
@@ -575,12 +604,13 @@ pub fn analyze_stmnt(
             if !matches_type(&default_ty, &var_ty, scoped_generic_types)
                 || !matches_type(&exp_analysis.exp_ty, &var_ty, scoped_generic_types)
             {
-                files.report_error(
+                files.report(
                     &file,
-                    &format!(
-                        "Cannot add to variable of type {}",
-                        var_ty.to_string(scoped_generic_types)
-                    ),
+                    Diagnostic::TypeMismatch {
+                        expected: default_ty.to_string(scoped_generic_types),
+                        found: var_ty.to_string(scoped_generic_types),
+                        definition: get_related_definition(files, var, &file, var_span.start),
+                    },
                     *var_span,
                 );
             }
@@ -611,10 +641,23 @@ pub fn analyze_stmnt(
                 Some(info) => {
                     match info.symbol_type {
                         SymbolType::Function(_) => {
-                            files.report_error(&file, "Cannot assign to a function", *var_span);
+                            files.report(
+                                &file,
+                                Diagnostic::FunctionAssignment {
+                                    name: var.to_string(),
+                                },
+                                *var_span,
+                            );
                         }
-                        SymbolType::Variable(var) if var.is_const => {
-                            files.report_error(&file, "Cannot assign to a constant", *var_span);
+                        SymbolType::Variable(variable) if variable.is_const => {
+                            files.report(
+                                &file,
+                                Diagnostic::ConstantAssignment {
+                                    name: var.to_string(),
+                                    by_reference: false,
+                                },
+                                *var_span,
+                            );
                         }
                         _ => {}
                     }
@@ -625,12 +668,13 @@ pub fn analyze_stmnt(
             };
 
             if !matches_type(&DataType::Number, &var_ty, scoped_generic_types) {
-                files.report_error(
+                files.report(
                     &file,
-                    &format!(
-                        "Cannot divide variable of type {}",
-                        var_ty.to_string(scoped_generic_types)
-                    ),
+                    Diagnostic::TypeMismatch {
+                        expected: DataType::Number.to_string(scoped_generic_types),
+                        found: var_ty.to_string(scoped_generic_types),
+                        definition: get_related_definition(files, var, &file, var_span.start),
+                    },
                     *var_span,
                 );
             }
@@ -671,10 +715,23 @@ pub fn analyze_stmnt(
                 Some(info) => {
                     match info.symbol_type {
                         SymbolType::Function(_) => {
-                            files.report_error(&file, "Cannot assign to a function", *var_span);
+                            files.report(
+                                &file,
+                                Diagnostic::FunctionAssignment {
+                                    name: var.to_string(),
+                                },
+                                *var_span,
+                            );
                         }
-                        SymbolType::Variable(var) if var.is_const => {
-                            files.report_error(&file, "Cannot assign to a constant", *var_span);
+                        SymbolType::Variable(variable) if variable.is_const => {
+                            files.report(
+                                &file,
+                                Diagnostic::ConstantAssignment {
+                                    name: var.to_string(),
+                                    by_reference: false,
+                                },
+                                *var_span,
+                            );
                         }
                         _ => {}
                     }
@@ -685,12 +742,13 @@ pub fn analyze_stmnt(
             };
 
             if !matches_type(&DataType::Number, &var_ty, scoped_generic_types) {
-                files.report_error(
+                files.report(
                     &file,
-                    &format!(
-                        "Cannot use modulo with variable of type {}",
-                        var_ty.to_string(scoped_generic_types)
-                    ),
+                    Diagnostic::TypeMismatch {
+                        expected: DataType::Number.to_string(scoped_generic_types),
+                        found: var_ty.to_string(scoped_generic_types),
+                        definition: get_related_definition(files, var, &file, var_span.start),
+                    },
                     *var_span,
                 );
             }
@@ -731,10 +789,23 @@ pub fn analyze_stmnt(
                 Some(info) => {
                     match info.symbol_type {
                         SymbolType::Function(_) => {
-                            files.report_error(&file, "Cannot assign to a function", *var_span);
+                            files.report(
+                                &file,
+                                Diagnostic::FunctionAssignment {
+                                    name: var.to_string(),
+                                },
+                                *var_span,
+                            );
                         }
-                        SymbolType::Variable(var) if var.is_const => {
-                            files.report_error(&file, "Cannot assign to a constant", *var_span);
+                        SymbolType::Variable(variable) if variable.is_const => {
+                            files.report(
+                                &file,
+                                Diagnostic::ConstantAssignment {
+                                    name: var.to_string(),
+                                    by_reference: false,
+                                },
+                                *var_span,
+                            );
                         }
                         _ => {}
                     }
@@ -745,12 +816,13 @@ pub fn analyze_stmnt(
             };
 
             if !matches_type(&DataType::Number, &var_ty, scoped_generic_types) {
-                files.report_error(
+                files.report(
                     &file,
-                    &format!(
-                        "Cannot use multiply with variable of type {}",
-                        var_ty.to_string(scoped_generic_types)
-                    ),
+                    Diagnostic::TypeMismatch {
+                        expected: DataType::Number.to_string(scoped_generic_types),
+                        found: var_ty.to_string(scoped_generic_types),
+                        definition: get_related_definition(files, var, &file, var_span.start),
+                    },
                     *var_span,
                 );
             }
@@ -791,10 +863,23 @@ pub fn analyze_stmnt(
                 Some(info) => {
                     match info.symbol_type {
                         SymbolType::Function(_) => {
-                            files.report_error(&file, "Cannot assign to a function", *var_span);
+                            files.report(
+                                &file,
+                                Diagnostic::FunctionAssignment {
+                                    name: var.to_string(),
+                                },
+                                *var_span,
+                            );
                         }
-                        SymbolType::Variable(var) if var.is_const => {
-                            files.report_error(&file, "Cannot assign to a constant", *var_span);
+                        SymbolType::Variable(variable) if variable.is_const => {
+                            files.report(
+                                &file,
+                                Diagnostic::ConstantAssignment {
+                                    name: var.to_string(),
+                                    by_reference: false,
+                                },
+                                *var_span,
+                            );
                         }
                         _ => {}
                     }
@@ -805,12 +890,13 @@ pub fn analyze_stmnt(
             };
 
             if !matches_type(&DataType::Number, &var_ty, scoped_generic_types) {
-                files.report_error(
+                files.report(
                     &file,
-                    &format!(
-                        "Cannot use subtract with variable of type {}",
-                        var_ty.to_string(scoped_generic_types)
-                    ),
+                    Diagnostic::TypeMismatch {
+                        expected: DataType::Number.to_string(scoped_generic_types),
+                        found: var_ty.to_string(scoped_generic_types),
+                        definition: get_related_definition(files, var, &file, var_span.start),
+                    },
                     *var_span,
                 );
             }
@@ -851,10 +937,23 @@ pub fn analyze_stmnt(
                 Some(info) => {
                     match info.symbol_type {
                         SymbolType::Function(_) => {
-                            files.report_error(&file, "Cannot assign to a function", *var_span);
+                            files.report(
+                                &file,
+                                Diagnostic::FunctionAssignment {
+                                    name: var.to_string(),
+                                },
+                                *var_span,
+                            );
                         }
-                        SymbolType::Variable(var) if var.is_const => {
-                            files.report_error(&file, "Cannot assign to a constant", *var_span);
+                        SymbolType::Variable(variable) if variable.is_const => {
+                            files.report(
+                                &file,
+                                Diagnostic::ConstantAssignment {
+                                    name: var.to_string(),
+                                    by_reference: false,
+                                },
+                                *var_span,
+                            );
                         }
                         _ => {}
                     }
@@ -893,7 +992,13 @@ pub fn analyze_stmnt(
         }
         Statement::Break => {
             if !contexts.iter().any(|c| matches!(c, Context::Loop)) {
-                files.report_error(&file, "Break statement outside of loop", *span);
+                files.report(
+                    &file,
+                    Diagnostic::MisplacedControlFlow {
+                        kind: ControlFlowKind::Break,
+                    },
+                    *span,
+                );
             }
 
             StmntAnalysisResult {
@@ -903,7 +1008,13 @@ pub fn analyze_stmnt(
         }
         Statement::Continue => {
             if !contexts.iter().any(|c| matches!(c, Context::Loop)) {
-                files.report_error(&file, "Continue statement outside of loop", *span);
+                files.report(
+                    &file,
+                    Diagnostic::MisplacedControlFlow {
+                        kind: ControlFlowKind::Continue,
+                    },
+                    *span,
+                );
             }
 
             StmntAnalysisResult {
@@ -961,7 +1072,14 @@ pub fn analyze_stmnt(
             } else if !modifiers.iter().any(|(modifier, _)| {
                 *modifier == CommandModifier::Unsafe || *modifier == CommandModifier::Trust
             }) {
-                files.report_error(&file, "Command must have a failure handler", *span);
+                files.report(
+                    &file,
+                    Diagnostic::MissingFailureHandler {
+                        kind: FailableKind::Command,
+                        modifier: CommandModifier::Trust,
+                    },
+                    *span,
+                );
             }
 
             get_stmnt_analysis_result(vec![], vec![exp1, exp2])
@@ -1081,9 +1199,11 @@ pub fn analyze_failure_handler(
                 .iter()
                 .any(|c| *c == Context::Main || matches!(c, Context::Function(_)))
             {
-                files.report_error(
+                files.report(
                     &(file_id, file_version),
-                    "Propagate can only be used inside of main block or function",
+                    Diagnostic::MisplacedControlFlow {
+                        kind: ControlFlowKind::Propagate,
+                    },
                     *span,
                 );
             }
@@ -0,0 +1,183 @@
+use std::hash::{Hash, Hasher};
+
+use ropey::Rope;
+use rustc_hash::FxHasher;
+
+use crate::{
+    analyzer::AmberVersion,
+    diagnostics::Diagnostic,
+    files::{FileVersion, Files},
+    grammar::{Grammar, Span, Spanned, SpannedSemanticToken},
+    paths::FileId,
+};
+
+use super::{
+    snapshot::TextChange, Context, FunctionSymbol, SymbolLocation, SymbolTable, SymbolType,
+};
+
+/// Syntax of a document, that is reused while its text doesn't change,
+/// e.g. when the document is analyzed again after one of its imports changed.
+#[derive(Debug, Clone)]
+pub struct ParsedDocument {
+    pub document: Rope,
+    pub amber_version: AmberVersion,
+    pub ast: Grammar,
+    /// Errors of the lexer and of the parser.
+    pub errors: Vec<Spanned<Diagnostic>>,
+    pub semantic_tokens: Vec<SpannedSemanticToken>,
+}
+
+impl ParsedDocument {
+    pub fn is_parse_of(&self, document: &Rope, amber_version: &AmberVersion) -> bool {
+        self.amber_version == *amber_version && self.document == *document
+    }
+}
+
+/// Hashes everything, that files importing the file can observe: names, signatures
+/// and docs of its public symbols, and the files it imports.
+///
+/// Files importing the file don't have to be analyzed again, as long as it doesn't change.
+pub fn interface_fingerprint(files: &Files, file: &(FileId, FileVersion)) -> Option<u64> {
+    let symbol_table = files.symbol_table.get(file)?.clone();
+
+    let mut public_definitions = symbol_table.public_definitions.iter().collect::<Vec<_>>();
+    public_definitions.sort_by_key(|(name, _)| *name);
+
+    let mut hasher = FxHasher::default();
+
+    for (name, location) in public_definitions {
+        name.hash(&mut hasher);
+
+        let symbol_info = if location.file == *file {
+            symbol_table.symbols.get(&location.start).cloned()
+        } else {
+            // Re-exported from another file
+            location.file.0 .0.hash(&mut hasher);
+
+            files
+                .symbol_table
+                .get(&location.file)
+                .and_then(|symbol_table| symbol_table.symbols.get(&location.start).cloned())
+        };
+
+        let Some(symbol_info) = symbol_info else {
+            continue;
+        };
+
+        symbol_info
+            .to_string(&files.generic_types)
+            .hash(&mut hasher);
+
+        if let SymbolType::Function(FunctionSymbol { docs, .. }) = &symbol_info.symbol_type {
+            docs.hash(&mut hasher);
+        }
+
+        for context in symbol_info.contexts.iter() {
+            if let Context::DocString(docs) = context {
+                docs.hash(&mut hasher);
+            }
+        }
+    }
+
+    let mut imports = files
+        .get_file_dependencies(file)
+        .into_iter()
+        .map(|(dependency, _)| dependency.0)
+        .collect::<Vec<_>>();
+    imports.sort_unstable();
+    imports.dedup();
+    imports.hash(&mut hasher);
+
+    Some(hasher.finish())
+}
+
+/// Points locations in `symbol_table`, that refer to the `from` version of a file,
+/// to the `to` version, moving them through the change between the versions.
+pub fn retarget_symbol_table(
+    symbol_table: &mut SymbolTable,
+    from: (FileId, FileVersion),
+    to: (FileId, FileVersion),
+    change: &TextChange,
+) {
+    let retarget = |location: &mut SymbolLocation| {
+        if location.file != from {
+            return;
+        }
+
+        // The text of the symbol itself can't change without changing the interface
+        if let Some((start, end)) = change.map_span(location.start, location.end) {
+            *location = SymbolLocation {
+                file: to,
+                start,
+                end,
+            };
+        }
+    };
+
+    for definitions in symbol_table.definitions.values_mut() {
+        let scopes = definitions
+            .iter()
+            .map(|(scope, location)| {
+                let mut location = location.clone();
+                retarget(&mut location);
+
+                (scope.clone(), location)
+            })
+            .collect::<Vec<_>>();
+
+        for (scope, location) in scopes {
+            definitions.insert(scope, location);
+        }
+    }
+
+    for references in symbol_table.references.values_mut() {
+        references.iter_mut().for_each(retarget);
+    }
+
+    symbol_table
+        .public_definitions
+        .values_mut()
+        .for_each(retarget);
+
+    let symbols = symbol_table
+        .symbols
+        .iter()
+        .filter(|(_, symbol_info)| {
+            symbol_info
+                .contexts
+                .iter()
+                .any(|context| matches!(context, Context::Import(_)))
+        })
+        .map(|(range, symbol_info)| {
+            let mut symbol_info = symbol_info.clone();
+
+            for context in symbol_info.contexts.iter_mut() {
+                if let Context::Import(import_context) = context {
+                    import_context
+                        .public_definitions
+                        .values_mut()
+                        .for_each(retarget);
+                }
+            }
+
+            (range.clone(), symbol_info)
+        })
+        .collect::<Vec<_>>();
+
+    for (range, symbol_info) in symbols {
+        symbol_table.symbols.insert(range, symbol_info);
+    }
+}
+
+/// Moves spans of related locations in `file`, through the change of its text.
+pub fn retarget_diagnostic(diagnostic: &mut Diagnostic, file: FileId, change: &TextChange) {
+    for related in diagnostic.related_mut() {
+        if related.file != file {
+            continue;
+        }
+
+        if let Some((start, end)) = change.map_span(related.span.start, related.span.end) {
+            related.span = Span::new(start, end);
+        }
+    }
+}
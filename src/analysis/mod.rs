@@ -1,19 +1,27 @@
 use rangemap::RangeInclusiveMap;
-use std::{collections::HashMap, ops::RangeInclusive};
+use std::{
+    collections::HashMap,
+    ops::RangeInclusive,
+    sync::{Arc, PoisonError},
+};
 use tower_lsp_server::{lsp_types::Uri, UriExt};
 use types::{DataType, GenericsMap};
 
 use crate::{
-    backend::{AmberVersion, Backend},
+    analyzer::{AmberVersion, Analyzer},
+    diagnostics::{Diagnostic, RelatedLocation},
     files::{FileVersion, Files},
     grammar::{CommandModifier, CompilerFlag, Span, Spanned},
     paths::FileId,
-    stdlib::resolve,
+    stdlib::{join_stdlib_uri, resolve},
 };
 
 pub mod alpha034;
 pub mod alpha035;
 pub mod alpha040;
+pub mod incremental;
+pub mod query;
+pub mod snapshot;
 pub mod types;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -134,6 +142,36 @@ impl SymbolInfo {
             _ => format!("{}: {}", self.name, self.data_type.to_string(generics_map)),
         }
     }
+
+    /// Symbol, as seen by files importing it: with the inferred types of its generics,
+    /// so that importers don't refer to type variables of the file defining it.
+    pub fn exported(&self, generics_map: &GenericsMap) -> SymbolInfo {
+        let symbol_type = match &self.symbol_type {
+            SymbolType::Function(function) => SymbolType::Function(FunctionSymbol {
+                arguments: function
+                    .arguments
+                    .iter()
+                    .map(|(arg, span)| {
+                        (
+                            FunctionArgument {
+                                data_type: generics_map.deref_type(&arg.data_type),
+                                ..arg.clone()
+                            },
+                            *span,
+                        )
+                    })
+                    .collect(),
+                ..function.clone()
+            }),
+            symbol_type => symbol_type.clone(),
+        };
+
+        SymbolInfo {
+            symbol_type,
+            data_type: generics_map.deref_type(&self.data_type),
+            ..self.clone()
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -312,6 +350,7 @@ pub fn insert_symbol_reference(
                         return;
                     }
                 };
+            let current_file_symbol_table = Arc::make_mut(&mut current_file_symbol_table);
 
             // If generic is already inferred, use the inferred type
             // if not, use generic as a pointer to the inferred type in the map
@@ -370,9 +409,11 @@ pub fn insert_symbol_reference(
             );
         }
         None => {
-            files.report_error(
+            files.report(
                 &reference_location.file,
-                &format!("\"{symbol}\" is not defined"),
+                Diagnostic::UndefinedSymbol {
+                    name: symbol.to_string(),
+                },
                 (reference_location.start..reference_location.end).into(),
             );
 
@@ -387,6 +428,7 @@ pub fn insert_symbol_reference(
                         return;
                     }
                 };
+            let current_file_symbol_table = Arc::make_mut(&mut current_file_symbol_table);
 
             current_file_symbol_table.symbols.insert(
                 span.clone(),
@@ -413,6 +455,7 @@ pub fn insert_symbol_reference(
             return;
         }
     };
+    let current_file_symbol_table = Arc::make_mut(&mut current_file_symbol_table);
 
     let symbol_references = match current_file_symbol_table.references.get_mut(symbol) {
         Some(symbol_references) => symbol_references,
@@ -459,10 +502,7 @@ pub fn get_symbol_definition_info(
                     .get(&definition.start)
                     .cloned()
             } else {
-                let definition_file_symbol_table = match files
-                    .symbol_table
-                    .get_mut(&definition.file)
-                {
+                let definition_file_symbol_table = match files.symbol_table.get(&definition.file) {
                     Some(symbol_table) => symbol_table,
                     None => {
                         tracing::error!("Symbol table for file {:?} not found", definition.file);
@@ -480,24 +520,146 @@ pub fn get_symbol_definition_info(
     }
 }
 
+/// Checks whether importing `imported_file` from `file` closes a cycle of imports.
+/// If it does, returns a diagnostic listing every import statement, that takes part in the cycle.
+pub fn get_import_cycle(
+    files: &Files,
+    file: &(FileId, FileVersion),
+    imported_file: &(FileId, FileVersion),
+    path: &str,
+    import_span: Span,
+) -> Option<Diagnostic> {
+    let dependency_path = if imported_file.0 == file.0 {
+        vec![]
+    } else {
+        files.get_dependency_path(imported_file, file.0)?
+    };
+
+    let file_name = |file_id: FileId| {
+        let uri = files.lookup(&file_id);
+
+        uri.as_str()
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    };
+
+    let mut imports = vec![(*file, import_span)];
+    imports.extend(dependency_path);
+
+    let mut names = imports
+        .iter()
+        .map(|((file_id, _), _)| file_name(*file_id))
+        .collect::<Vec<_>>();
+    names.push(file_name(file.0));
+
+    let cycle = imports
+        .iter()
+        .enumerate()
+        .map(|(idx, ((file_id, _), span))| RelatedLocation {
+            file: *file_id,
+            span: *span,
+            message: format!("`{}` imports `{}`", names[idx], names[idx + 1]),
+        })
+        .collect();
+
+    Some(Diagnostic::CircularDependency {
+        path: path.to_string(),
+        files: names,
+        cycle,
+    })
+}
+
+/// Finds the location of the definition of a symbol, that is visible at the given position.
 #[tracing::instrument(skip_all)]
-pub async fn map_import_path(uri: &Uri, path: &str, backend: &Backend) -> Uri {
+pub fn get_symbol_definition_location(
+    files: &Files,
+    symbol: &str,
+    file: &(FileId, FileVersion),
+    position: usize,
+) -> Option<SymbolLocation> {
+    let symbol_table = files.symbol_table.get(file)?;
+
+    symbol_table
+        .definitions
+        .get(symbol)
+        .and_then(|definitions| definitions.get(&position).cloned())
+}
+
+/// Returns the definition of a symbol, that is visible at the given position,
+/// as related information of a diagnostic.
+pub fn get_related_definition(
+    files: &Files,
+    symbol: &str,
+    file: &(FileId, FileVersion),
+    position: usize,
+) -> Option<RelatedLocation> {
+    get_symbol_definition_location(files, symbol, file, position).map(|location| RelatedLocation {
+        file: location.file.0,
+        span: (location.start..location.end).into(),
+        message: format!("`{symbol}` is defined here"),
+    })
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn map_import_path(
+    uri: &Uri,
+    path: &str,
+    analyzer: &Analyzer,
+    amber_version: &AmberVersion,
+) -> Option<Uri> {
     if path.starts_with("std/") || path == "std" || path == "builtin" {
-        match backend.amber_version {
+        match amber_version {
             AmberVersion::Alpha034 if path == "std" => {
-                if let Some(uri) = resolve(backend, "std/main".to_string()).await {
-                    return uri;
+                if let Some(uri) = resolve(analyzer, "std/main".to_string(), amber_version).await {
+                    return Some(uri);
                 }
             }
             _ => {
-                if let Some(uri) = resolve(backend, path.to_string()).await {
-                    return uri;
+                if let Some(uri) = resolve(analyzer, path.to_string(), amber_version).await {
+                    return Some(uri);
                 }
             }
         }
     }
 
-    let path = uri.to_file_path().unwrap().parent().unwrap().join(path);
+    let file_path = match uri.to_file_path() {
+        Some(path) => path,
+        None => return Some(join_stdlib_uri(uri, path).unwrap_or_else(|| uri.clone())),
+    };
+    let file_dir = file_path.parent()?;
+    let project_config = analyzer.project_configs.get_cached(file_dir);
+
+    if let Some(aliased_path) = project_config
+        .as_ref()
+        .and_then(|config| config.resolve_alias(path))
+    {
+        return Uri::from_file_path(aliased_path);
+    }
+
+    let relative_path = file_dir.join(path);
+
+    if !analyzer.files.fs.exists(&relative_path).await {
+        let mut candidates = project_config
+            .map(|config| config.import_candidates(path))
+            .unwrap_or_default();
+        candidates.extend(
+            analyzer
+                .config
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .import_paths
+                .iter()
+                .map(|import_path| import_path.join(path)),
+        );
+
+        for candidate in candidates {
+            if analyzer.files.fs.exists(&candidate).await {
+                return Uri::from_file_path(candidate);
+            }
+        }
+    }
 
-    Uri::from_file_path(path).unwrap()
+    Uri::from_file_path(relative_path)
 }
@@ -0,0 +1,654 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
+
+use rangemap::RangeInclusiveMap;
+use rustc_hash::FxHasher;
+
+use crate::{
+    diagnostics::{Diagnostic, RelatedLocation},
+    files::{FileVersion, Files},
+    grammar::{Span, Spanned},
+    paths::FileId,
+};
+
+use super::{
+    get_symbol_definition_info, get_symbol_definition_location,
+    types::{DataType, GenericId, SavedGeneric},
+    Context, FunctionSymbol, SymbolInfo, SymbolLocation, SymbolTable, SymbolType,
+};
+
+/// Symbol defined outside of a function, that the function uses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Dependency {
+    name: String,
+    /// Hash of the signature and the docs of the definition, `None` if the symbol is undefined.
+    signature: Option<u64>,
+    location: Option<SymbolLocation>,
+}
+
+/// Results of the analysis of a function definition.
+///
+/// They are reused while the text of the function and the signatures of the symbols
+/// it uses don't change, so that editing one function doesn't check the others again.
+#[derive(Debug, Clone)]
+pub struct FunctionAnalysis {
+    fingerprint: u64,
+    /// File version and span of the function, that the results refer to.
+    file: (FileId, FileVersion),
+    span: Span,
+    dependencies: Vec<Dependency>,
+    /// Symbols, definitions and references in the span of the function,
+    /// along with the definition of the function itself.
+    symbol_table: SymbolTable,
+    diagnostics: Vec<Spanned<Diagnostic>>,
+    /// Type variables of the function, allocated from `first_generic` on.
+    first_generic: usize,
+    generics: Vec<SavedGeneric>,
+}
+
+impl FunctionAnalysis {
+    /// File version, that the results refer to.
+    pub fn file(&self) -> (FileId, FileVersion) {
+        self.file
+    }
+}
+
+/// Query for the analysis of a function definition, created before the function is analyzed.
+///
+/// Either [`FunctionQuery::reuse`] restores the results of a previous analysis,
+/// or the function is analyzed and [`FunctionQuery::record`] saves its results.
+#[derive(Debug)]
+pub struct FunctionQuery {
+    file: (FileId, FileVersion),
+    name: String,
+    span: Span,
+    fingerprint: u64,
+    first_diagnostic: usize,
+    first_generic: usize,
+}
+
+impl FunctionQuery {
+    pub fn new(
+        files: &Files,
+        file: (FileId, FileVersion),
+        name: &str,
+        span: Span,
+        docs: Option<&str>,
+    ) -> Self {
+        let mut hasher = FxHasher::default();
+
+        if let Some(document) = files.document_map.get(&file) {
+            let end = span.end.min(document.len_chars());
+            let start = span.start.min(end);
+
+            document.slice(start..end).to_string().hash(&mut hasher);
+        }
+
+        files
+            .amber_versions
+            .get(&file.0)
+            .map(|amber_version| amber_version.clone())
+            .hash(&mut hasher);
+        docs.hash(&mut hasher);
+
+        Self {
+            file,
+            name: name.to_string(),
+            span,
+            fingerprint: hasher.finish(),
+            first_diagnostic: files
+                .diagnostics
+                .get(&file)
+                .map(|diagnostics| diagnostics.len())
+                .unwrap_or(0),
+            first_generic: files.generic_types.generic_count(file),
+        }
+    }
+
+    /// Restores the results of a previous analysis of the function, moved to its current span,
+    /// if neither its text nor the signatures of the symbols it uses changed since then.
+    ///
+    /// Returns false if the function has to be analyzed.
+    pub fn reuse(&self, files: &Files) -> bool {
+        let Some(cached) = files
+            .function_cache
+            .get(&(self.file.0, self.name.clone()))
+            .map(|entry| entry.clone())
+        else {
+            return false;
+        };
+
+        if cached.fingerprint != self.fingerprint {
+            return false;
+        }
+
+        let dependencies = get_dependencies(
+            files,
+            self.file,
+            self.span,
+            cached
+                .dependencies
+                .iter()
+                .map(|dependency| &dependency.name),
+        );
+
+        if dependencies
+            .iter()
+            .zip(cached.dependencies.iter())
+            .any(|(current, previous)| current.signature != previous.signature)
+        {
+            return false;
+        }
+
+        let mover = Mover {
+            from: cached.file,
+            to: self.file,
+            from_span: cached.span,
+            to_span: self.span,
+        };
+
+        // Related information of diagnostics points at the definitions of the dependencies
+        let mut diagnostics = Vec::with_capacity(cached.diagnostics.len());
+
+        for (diagnostic, span) in cached.diagnostics.iter() {
+            let mut diagnostic = diagnostic.clone();
+
+            for related in diagnostic.related_mut() {
+                if related.file == cached.file.0 && mover.contains(related.span) {
+                    related.span = mover.move_span(related.span);
+                    continue;
+                }
+
+                let location = cached
+                    .dependencies
+                    .iter()
+                    .zip(dependencies.iter())
+                    .find_map(|(previous, current)| {
+                        previous
+                            .location
+                            .as_ref()
+                            .filter(|location| is_related_location(location, related))
+                            .and(current.location.as_ref())
+                    });
+
+                match location {
+                    Some(location) => {
+                        related.file = location.file.0;
+                        related.span = Span::new(location.start, location.end);
+                    }
+                    None => return false,
+                }
+            }
+
+            diagnostics.push((diagnostic, mover.move_span(*span)));
+        }
+
+        let first_generic =
+            files
+                .generic_types
+                .restore(self.file, &cached.generics, |first_generic, ty| {
+                    let mut ty = ty.clone();
+                    remap_generics(&mut ty, &cached, self.file, first_generic);
+                    ty
+                });
+
+        let generics = cached
+            .generics
+            .iter()
+            .map(|generic| {
+                let mut generic = generic.clone();
+                remap_generics(&mut generic.data_type, &cached, self.file, first_generic);
+                generic
+            })
+            .collect();
+
+        let mut symbol_table = mover.move_symbol_table(&cached.symbol_table);
+        for_each_symbol_mut(&mut symbol_table, |symbol_info| {
+            for_each_symbol_type(symbol_info, |ty| {
+                remap_generics(ty, &cached, self.file, first_generic)
+            });
+        });
+
+        if let Some(mut current) = files.symbol_table.get_mut(&self.file) {
+            merge_symbol_table(Arc::make_mut(&mut current), &symbol_table);
+        }
+
+        files
+            .diagnostics
+            .entry(self.file)
+            .or_default()
+            .extend(diagnostics.iter().cloned());
+
+        files.function_cache.insert(
+            (self.file.0, self.name.clone()),
+            FunctionAnalysis {
+                fingerprint: self.fingerprint,
+                file: self.file,
+                span: self.span,
+                dependencies,
+                symbol_table,
+                diagnostics,
+                first_generic,
+                generics,
+            },
+        );
+
+        true
+    }
+
+    /// Saves the results of the analysis of the function, that just finished.
+    ///
+    /// Results, that refer to type variables or locations outside of the function,
+    /// which can't be moved with it, are not saved.
+    pub fn record(&self, files: &Files) {
+        let key = (self.file.0, self.name.clone());
+
+        let Some(symbol_table) = files
+            .symbol_table
+            .get(&self.file)
+            .map(|symbol_table| symbol_table.clone())
+        else {
+            files.function_cache.remove(&key);
+            return;
+        };
+
+        let diagnostics = files
+            .diagnostics
+            .get(&self.file)
+            .and_then(|diagnostics| diagnostics.get(self.first_diagnostic..).map(<[_]>::to_vec))
+            .unwrap_or_default();
+
+        let symbol_table = extract_symbol_table(&symbol_table, self.file, self.span);
+
+        let mut names = symbol_table.references.keys().collect::<BTreeSet<_>>();
+        names.extend(
+            symbol_table
+                .symbols
+                .iter()
+                .filter(|(_, symbol_info)| symbol_info.undefined)
+                .map(|(_, symbol_info)| &symbol_info.name),
+        );
+
+        let dependencies = get_dependencies(files, self.file, self.span, names.into_iter());
+
+        let generics = files.generic_types.save(self.file, self.first_generic);
+
+        let is_recordable = generics.as_ref().is_some_and(|generics| {
+            let own_generics = self.first_generic..self.first_generic + generics.len();
+            let mut is_own = true;
+
+            let mut check = |ty: &mut DataType| {
+                for_each_generic(ty, &mut |id| {
+                    is_own &= id.file == self.file && own_generics.contains(&id.index);
+                })
+            };
+
+            for generic in generics.iter() {
+                check(&mut generic.data_type.clone());
+            }
+
+            for_each_symbol(&symbol_table, |symbol_info| {
+                for_each_symbol_type(&mut symbol_info.clone(), &mut check);
+            });
+
+            is_own
+        }) && diagnostics.iter().all(|(diagnostic, span)| {
+            self.contains(*span)
+                && diagnostic.related().iter().all(|related| {
+                    (related.file == self.file.0 && self.contains(related.span))
+                        || dependencies.iter().any(|dependency| {
+                            dependency
+                                .location
+                                .as_ref()
+                                .is_some_and(|location| is_related_location(location, related))
+                        })
+                })
+        });
+
+        match generics {
+            Some(generics) if is_recordable => {
+                files.function_cache.insert(
+                    key,
+                    FunctionAnalysis {
+                        fingerprint: self.fingerprint,
+                        file: self.file,
+                        span: self.span,
+                        dependencies,
+                        symbol_table,
+                        diagnostics,
+                        first_generic: self.first_generic,
+                        generics,
+                    },
+                );
+            }
+            _ => {
+                files.function_cache.remove(&key);
+            }
+        }
+    }
+
+    fn contains(&self, span: Span) -> bool {
+        span.start >= self.span.start && span.end <= self.span.end
+    }
+}
+
+/// Looks up the definitions of the symbols, that are visible at the start of the function.
+fn get_dependencies<'a>(
+    files: &Files,
+    file: (FileId, FileVersion),
+    span: Span,
+    names: impl Iterator<Item = &'a String>,
+) -> Vec<Dependency> {
+    names
+        .map(|name| Dependency {
+            name: name.clone(),
+            signature: get_symbol_definition_info(files, name, &file, span.start)
+                .map(|symbol_info| symbol_fingerprint(files, &symbol_info)),
+            location: get_symbol_definition_location(files, name, &file, span.start),
+        })
+        .collect()
+}
+
+/// Hashes everything about a symbol, that the analysis of code using it can observe.
+fn symbol_fingerprint(files: &Files, symbol_info: &SymbolInfo) -> u64 {
+    let mut hasher = FxHasher::default();
+
+    symbol_info
+        .to_string(&files.generic_types)
+        .hash(&mut hasher);
+
+    match &symbol_info.symbol_type {
+        SymbolType::Function(FunctionSymbol { docs, .. }) => docs.hash(&mut hasher),
+        SymbolType::Variable(variable) => variable.is_const.hash(&mut hasher),
+        SymbolType::ImportPath => {}
+    }
+
+    for context in symbol_info.contexts.iter() {
+        if let Context::DocString(docs) = context {
+            docs.hash(&mut hasher);
+        }
+    }
+
+    hasher.finish()
+}
+
+fn is_related_location(location: &SymbolLocation, related: &RelatedLocation) -> bool {
+    location.file.0 == related.file
+        && location.start == related.span.start
+        && location.end == related.span.end
+}
+
+/// Copies the parts of the symbol table, that the analysis of the function in `span` added.
+fn extract_symbol_table(
+    symbol_table: &SymbolTable,
+    file: (FileId, FileVersion),
+    span: Span,
+) -> SymbolTable {
+    let in_span = |start: usize, end: usize| start >= span.start && end <= span.end;
+    let is_local =
+        |location: &SymbolLocation| location.file == file && in_span(location.start, location.end);
+
+    let mut fragment = SymbolTable::default();
+
+    for (range, symbol_info) in symbol_table.symbols.iter() {
+        // Symbols of the imports, that Amber adds to every file, have empty spans
+        let is_import = symbol_info
+            .contexts
+            .iter()
+            .any(|context| matches!(context, Context::Import(_)));
+
+        if !is_import && in_span(*range.start(), *range.end()) {
+            fragment.symbols.insert(range.clone(), symbol_info.clone());
+        }
+    }
+
+    for (name, definitions) in symbol_table.definitions.iter() {
+        let definitions = definitions
+            .iter()
+            .filter(|(scope, location)| *scope.start() >= span.start && is_local(location))
+            .map(|(scope, location)| (scope.clone(), location.clone()))
+            .collect::<RangeInclusiveMap<_, _>>();
+
+        if !definitions.is_empty() {
+            fragment.definitions.insert(name.clone(), definitions);
+        }
+    }
+
+    for (name, references) in symbol_table.references.iter() {
+        let references = references
+            .iter()
+            .filter(|location| is_local(location))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        if !references.is_empty() {
+            fragment.references.insert(name.clone(), references);
+        }
+    }
+
+    fragment.public_definitions = symbol_table
+        .public_definitions
+        .iter()
+        .filter(|(_, location)| is_local(location))
+        .map(|(name, location)| (name.clone(), location.clone()))
+        .collect::<HashMap<_, _>>();
+
+    for (range, symbol_info) in symbol_table.fun_call_arg_scope.iter() {
+        if in_span(*range.start(), *range.end()) {
+            fragment
+                .fun_call_arg_scope
+                .insert(range.clone(), symbol_info.clone());
+        }
+    }
+
+    fragment
+}
+
+/// Adds the results of the analysis of a function to the symbol table of its file.
+fn merge_symbol_table(symbol_table: &mut SymbolTable, fragment: &SymbolTable) {
+    for (range, symbol_info) in fragment.symbols.iter() {
+        symbol_table
+            .symbols
+            .insert(range.clone(), symbol_info.clone());
+    }
+
+    for (name, definitions) in fragment.definitions.iter() {
+        let scopes = symbol_table
+            .definitions
+            .entry(name.clone())
+            .or_insert_with(RangeInclusiveMap::new);
+
+        for (scope, location) in definitions.iter() {
+            scopes.insert(scope.clone(), location.clone());
+        }
+    }
+
+    for (name, references) in fragment.references.iter() {
+        symbol_table
+            .references
+            .entry(name.clone())
+            .or_default()
+            .extend(references.iter().cloned());
+    }
+
+    for (name, location) in fragment.public_definitions.iter() {
+        symbol_table
+            .public_definitions
+            .insert(name.clone(), location.clone());
+    }
+
+    for (range, symbol_info) in fragment.fun_call_arg_scope.iter() {
+        symbol_table
+            .fun_call_arg_scope
+            .insert(range.clone(), symbol_info.clone());
+    }
+}
+
+/// Moves results of the analysis of a function from its previous span to the current one.
+struct Mover {
+    from: (FileId, FileVersion),
+    to: (FileId, FileVersion),
+    from_span: Span,
+    to_span: Span,
+}
+
+impl Mover {
+    fn contains(&self, span: Span) -> bool {
+        span.start >= self.from_span.start && span.end <= self.from_span.end
+    }
+
+    fn move_offset(&self, offset: usize) -> usize {
+        // Scopes reaching the end of the file end at `usize::MAX`
+        if offset == usize::MAX || offset < self.from_span.start {
+            return offset;
+        }
+
+        offset - self.from_span.start + self.to_span.start
+    }
+
+    fn move_span(&self, span: Span) -> Span {
+        Span::new(self.move_offset(span.start), self.move_offset(span.end))
+    }
+
+    fn move_location(&self, location: &SymbolLocation) -> SymbolLocation {
+        if location.file != self.from {
+            return location.clone();
+        }
+
+        SymbolLocation {
+            file: self.to,
+            start: self.move_offset(location.start),
+            end: self.move_offset(location.end),
+        }
+    }
+
+    fn move_symbol_info(&self, symbol_info: &SymbolInfo) -> SymbolInfo {
+        let mut symbol_info = symbol_info.clone();
+
+        if self.contains(symbol_info.span) {
+            symbol_info.span = self.move_span(symbol_info.span);
+        }
+
+        if let SymbolType::Function(FunctionSymbol { arguments, .. }) = &mut symbol_info.symbol_type
+        {
+            for (_, span) in arguments.iter_mut() {
+                if self.contains(*span) {
+                    *span = self.move_span(*span);
+                }
+            }
+        }
+
+        symbol_info
+    }
+
+    fn move_symbol_table(&self, symbol_table: &SymbolTable) -> SymbolTable {
+        let move_range = |range: &std::ops::RangeInclusive<usize>| {
+            self.move_offset(*range.start())..=self.move_offset(*range.end())
+        };
+
+        SymbolTable {
+            symbols: symbol_table
+                .symbols
+                .iter()
+                .map(|(range, symbol_info)| (move_range(range), self.move_symbol_info(symbol_info)))
+                .collect(),
+            definitions: symbol_table
+                .definitions
+                .iter()
+                .map(|(name, definitions)| {
+                    let definitions = definitions
+                        .iter()
+                        .map(|(scope, location)| (move_range(scope), self.move_location(location)))
+                        .collect();
+
+                    (name.clone(), definitions)
+                })
+                .collect(),
+            references: symbol_table
+                .references
+                .iter()
+                .map(|(name, references)| {
+                    let references = references
+                        .iter()
+                        .map(|location| self.move_location(location))
+                        .collect();
+
+                    (name.clone(), references)
+                })
+                .collect(),
+            public_definitions: symbol_table
+                .public_definitions
+                .iter()
+                .map(|(name, location)| (name.clone(), self.move_location(location)))
+                .collect(),
+            fun_call_arg_scope: symbol_table
+                .fun_call_arg_scope
+                .iter()
+                .map(|(range, symbol_info)| (move_range(range), self.move_symbol_info(symbol_info)))
+                .collect(),
+        }
+    }
+}
+
+/// Points type variables of the cached analysis at the ones allocated from `first_generic` on.
+fn remap_generics(
+    ty: &mut DataType,
+    cached: &FunctionAnalysis,
+    file: (FileId, FileVersion),
+    first_generic: usize,
+) {
+    for_each_generic(ty, &mut |id| {
+        if id.file == cached.file && id.index >= cached.first_generic {
+            *id = GenericId {
+                file,
+                index: id.index - cached.first_generic + first_generic,
+            };
+        }
+    });
+}
+
+fn for_each_generic(ty: &mut DataType, f: &mut impl FnMut(&mut GenericId)) {
+    match ty {
+        DataType::Generic(id) => f(id),
+        DataType::Array(ty) | DataType::Failable(ty) => for_each_generic(ty, f),
+        DataType::Union(types) => types.iter_mut().for_each(|ty| for_each_generic(ty, f)),
+        _ => {}
+    }
+}
+
+fn for_each_symbol_type(symbol_info: &mut SymbolInfo, mut f: impl FnMut(&mut DataType)) {
+    f(&mut symbol_info.data_type);
+
+    if let SymbolType::Function(FunctionSymbol { arguments, .. }) = &mut symbol_info.symbol_type {
+        for (argument, _) in arguments.iter_mut() {
+            f(&mut argument.data_type);
+        }
+    }
+}
+
+fn for_each_symbol(symbol_table: &SymbolTable, mut f: impl FnMut(&SymbolInfo)) {
+    symbol_table
+        .symbols
+        .iter()
+        .chain(symbol_table.fun_call_arg_scope.iter())
+        .for_each(|(_, symbol_info)| f(symbol_info));
+}
+
+fn for_each_symbol_mut(symbol_table: &mut SymbolTable, mut f: impl FnMut(&mut SymbolInfo)) {
+    let remap = |map: &RangeInclusiveMap<usize, SymbolInfo>, f: &mut dyn FnMut(&mut SymbolInfo)| {
+        map.iter()
+            .map(|(range, symbol_info)| {
+                let mut symbol_info = symbol_info.clone();
+                f(&mut symbol_info);
+
+                (range.clone(), symbol_info)
+            })
+            .collect::<RangeInclusiveMap<_, _>>()
+    };
+
+    symbol_table.symbols = remap(&symbol_table.symbols, &mut f);
+    symbol_table.fun_call_arg_scope = remap(&symbol_table.fun_call_arg_scope, &mut f);
+}
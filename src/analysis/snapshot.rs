@@ -0,0 +1,256 @@
+use std::{ops::RangeInclusive, sync::Arc};
+
+use rangemap::RangeInclusiveMap;
+use ropey::Rope;
+
+use crate::{
+    files::{FileVersion, Files},
+    grammar::{Grammar, Span, Spanned, SpannedSemanticToken},
+    paths::FileId,
+};
+
+use super::{FunctionSymbol, SymbolInfo, SymbolLocation, SymbolTable, SymbolType};
+
+/// Analysis of the last version of a file, that could be parsed.
+///
+/// While the file doesn't parse, IDE features are served from this snapshot,
+/// with its spans moved through the edits made since then.
+/// The AST is kept with the spans of `document`, as nothing reads positions from it.
+#[derive(Debug, Clone)]
+pub struct AnalysisSnapshot {
+    pub version: FileVersion,
+    pub document: Rope,
+    pub ast: Grammar,
+    /// Symbol table of the version, once it's dropped from [`Files::symbol_table`].
+    /// Until then, the table is read from there, so that it isn't shared with writers.
+    pub symbol_table: Option<Arc<SymbolTable>>,
+    pub semantic_tokens: Vec<SpannedSemanticToken>,
+    pub dependencies: Vec<Spanned<FileId>>,
+}
+
+impl AnalysisSnapshot {
+    /// Returns the symbol table of the version of the snapshot.
+    pub fn symbol_table(&self, files: &Files, file_id: FileId) -> Option<Arc<SymbolTable>> {
+        match &self.symbol_table {
+            Some(symbol_table) => Some(symbol_table.clone()),
+            None => files
+                .symbol_table
+                .get(&(file_id, self.version))
+                .map(|symbol_table| symbol_table.clone()),
+        }
+    }
+
+    /// Moves spans of the snapshot, with its symbol table, to match `document`, the text of `file`.
+    ///
+    /// Symbols and tokens, whose text was edited, are dropped.
+    /// Scopes grow or shrink with the edits made inside of them.
+    pub fn remap(
+        &self,
+        symbol_table: &SymbolTable,
+        file: (FileId, FileVersion),
+        document: &Rope,
+    ) -> AnalysisSnapshot {
+        let change = TextChange::between(&self.document, document);
+        let old_file = (file.0, self.version);
+
+        let remap_location = |location: &SymbolLocation| {
+            if location.file != old_file {
+                return Some(location.clone());
+            }
+
+            let (start, end) = change.map_span(location.start, location.end)?;
+
+            Some(SymbolLocation { file, start, end })
+        };
+
+        let previous = symbol_table;
+        let mut symbol_table = SymbolTable::default();
+
+        for (range, symbol_info) in previous.symbols.iter() {
+            let Some((start, end)) = change.map_span(*range.start(), *range.end()) else {
+                continue;
+            };
+            let Some((span_start, span_end)) =
+                change.map_span(symbol_info.span.start, symbol_info.span.end)
+            else {
+                continue;
+            };
+
+            symbol_table.symbols.insert(
+                start..=end,
+                SymbolInfo {
+                    span: Span::new(span_start, span_end),
+                    ..symbol_info.clone()
+                },
+            );
+        }
+
+        for (name, scopes) in previous.definitions.iter() {
+            let mut definitions = RangeInclusiveMap::new();
+
+            for (scope, location) in scopes.iter() {
+                if let (Some(scope), Some(location)) =
+                    (change.map_scope(scope), remap_location(location))
+                {
+                    definitions.insert(scope, location);
+                }
+            }
+
+            if !definitions.is_empty() {
+                symbol_table.definitions.insert(name.clone(), definitions);
+            }
+        }
+
+        for (name, references) in previous.references.iter() {
+            let references = references
+                .iter()
+                .filter_map(remap_location)
+                .collect::<Vec<_>>();
+
+            if !references.is_empty() {
+                symbol_table.references.insert(name.clone(), references);
+            }
+        }
+
+        for (name, location) in previous.public_definitions.iter() {
+            if let Some(location) = remap_location(location) {
+                symbol_table
+                    .public_definitions
+                    .insert(name.clone(), location);
+            }
+        }
+
+        for (scope, symbol_info) in previous.fun_call_arg_scope.iter() {
+            let Some(scope) = change.map_scope(scope) else {
+                continue;
+            };
+
+            let mut symbol_info = symbol_info.clone();
+
+            if let Some(span) = change.map_scope(&(symbol_info.span.start..=symbol_info.span.end)) {
+                symbol_info.span = Span::new(*span.start(), *span.end());
+            }
+
+            // Spans of the arguments are spans of the expressions passed to the call
+            if let SymbolType::Function(FunctionSymbol { arguments, .. }) =
+                &mut symbol_info.symbol_type
+            {
+                for (_, span) in arguments.iter_mut() {
+                    if let Some(moved) = change.map_scope(&(span.start..=span.end)) {
+                        *span = Span::new(*moved.start(), *moved.end());
+                    }
+                }
+            }
+
+            symbol_table.fun_call_arg_scope.insert(scope, symbol_info);
+        }
+
+        let semantic_tokens = self
+            .semantic_tokens
+            .iter()
+            .filter_map(|(token, span)| {
+                let (start, end) = change.map_span(span.start, span.end)?;
+
+                Some((*token, Span::new(start, end)))
+            })
+            .collect();
+
+        let dependencies = self
+            .dependencies
+            .iter()
+            .filter_map(|(dependency, span)| {
+                let span = change.map_scope(&(span.start..=span.end))?;
+
+                Some((*dependency, Span::new(*span.start(), *span.end())))
+            })
+            .collect();
+
+        AnalysisSnapshot {
+            version: file.1,
+            document: document.clone(),
+            ast: self.ast.clone(),
+            symbol_table: Some(Arc::new(symbol_table)),
+            semantic_tokens,
+            dependencies,
+        }
+    }
+}
+
+/// Change turning one text into another: chars from `start` to `old_end`
+/// were replaced with chars from `start` to `new_end`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextChange {
+    pub start: usize,
+    pub old_end: usize,
+    pub new_end: usize,
+}
+
+impl TextChange {
+    /// Finds the smallest change between two texts, by skipping their common prefix and suffix.
+    ///
+    /// Several edits are merged into one change, that covers all of them.
+    pub fn between(old: &Rope, new: &Rope) -> Self {
+        let prefix = old
+            .chars()
+            .zip(new.chars())
+            .take_while(|(old, new)| old == new)
+            .count();
+
+        let max_suffix = old.len_chars().min(new.len_chars()) - prefix;
+        let suffix = old
+            .chars_at(old.len_chars())
+            .reversed()
+            .zip(new.chars_at(new.len_chars()).reversed())
+            .take(max_suffix)
+            .take_while(|(old, new)| old == new)
+            .count();
+
+        TextChange {
+            start: prefix,
+            old_end: old.len_chars() - suffix,
+            new_end: new.len_chars() - suffix,
+        }
+    }
+
+    fn shift(&self, offset: usize) -> usize {
+        // Scopes reaching the end of the file end at `usize::MAX`
+        (offset - self.old_end).saturating_add(self.new_end)
+    }
+
+    /// Moves a span of a token, unless the change touched its text.
+    pub fn map_span(&self, start: usize, end: usize) -> Option<(usize, usize)> {
+        let touched = if self.start == self.old_end {
+            start < self.start && end > self.start
+        } else {
+            start < self.old_end && end > self.start
+        };
+
+        if touched {
+            return None;
+        }
+
+        if end <= self.start {
+            return Some((start, end));
+        }
+
+        Some((self.shift(start), self.shift(end)))
+    }
+
+    /// Moves a scope, so that it covers text inserted inside of it.
+    ///
+    /// Returns `None` if the whole scope was removed.
+    pub fn map_scope(&self, scope: &RangeInclusive<usize>) -> Option<RangeInclusive<usize>> {
+        let start = match *scope.start() {
+            offset if offset < self.start => offset,
+            offset if offset >= self.old_end => self.shift(offset),
+            _ => self.start,
+        };
+        let end = match *scope.end() {
+            offset if offset < self.start => offset,
+            offset if offset >= self.old_end => self.shift(offset),
+            _ => self.new_end,
+        };
+
+        (start <= end).then_some(start..=end)
+    }
+}
@@ -1,12 +1,7 @@
 use std::fmt::{self, Display};
-use std::sync::atomic::Ordering::SeqCst;
-use std::{collections::HashSet, sync::atomic::AtomicUsize};
+use std::{collections::HashSet, sync::Arc};
 
-use crate::{
-    files::FileVersion,
-    paths::FileId,
-    utils::{FastDashMap, FastDashSet},
-};
+use crate::{files::FileVersion, paths::FileId, utils::FastDashMap};
 
 #[derive(PartialEq, Eq, Clone, Hash)]
 pub enum DataType {
@@ -17,7 +12,7 @@ pub enum DataType {
     Null,
     Array(Box<DataType>),
     Union(Vec<DataType>),
-    Generic(usize),
+    Generic(GenericId),
     Failable(Box<DataType>),
     Error,
 }
@@ -56,14 +51,49 @@ impl fmt::Debug for DataType {
     }
 }
 
+/// Type variable of a generic argument, allocated in the arena of the file version defining it.
+#[derive(Copy, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GenericId {
+    pub file: (FileId, FileVersion),
+    pub index: usize,
+}
+
+/// Type variables of one file version, unified with union-find.
+#[derive(Debug, Default)]
+struct GenericArena {
+    parents: Vec<usize>,
+    ranks: Vec<u8>,
+    /// Type of each class of unified variables, kept at its root.
+    types: Vec<DataType>,
+    inferred: Vec<bool>,
+}
+
+impl GenericArena {
+    fn find(&self, mut index: usize) -> usize {
+        while self.parents[index] != index {
+            index = self.parents[index];
+        }
+
+        index
+    }
+}
+
+/// Type variable saved with the analysis of a function, to be allocated again when it's reused.
 #[derive(Debug, Clone)]
-pub struct GenericsMap {
-    map: FastDashMap<usize, DataType>,
-    inferred: FastDashSet<usize>,
-    generics_per_file: FastDashMap<(FileId, FileVersion), Vec<usize>>,
+pub struct SavedGeneric {
+    /// Offset of the root of its class from the first saved variable.
+    pub root: usize,
+    pub data_type: DataType,
+    pub inferred: bool,
 }
 
-static ATOMIC_COUNTER: AtomicUsize = AtomicUsize::new(0);
+#[derive(Debug)]
+pub struct GenericsMap {
+    arenas: Arc<FastDashMap<(FileId, FileVersion), GenericArena>>,
+    /// Types, that inferred variables are narrowed to inside of a scope,
+    /// e.g. by arguments of a call to a generic function.
+    instances: FastDashMap<GenericId, DataType>,
+}
 
 impl Default for GenericsMap {
     fn default() -> Self {
@@ -74,22 +104,33 @@ impl Default for GenericsMap {
 impl GenericsMap {
     pub fn new() -> Self {
         Self {
-            map: FastDashMap::default(),
-            inferred: FastDashSet::default(),
-            generics_per_file: FastDashMap::default(),
+            arenas: Arc::new(FastDashMap::default()),
+            instances: FastDashMap::default(),
         }
     }
 
-    #[inline]
-    pub fn new_generic_id(&self) -> usize {
-        ATOMIC_COUNTER.fetch_add(1, SeqCst)
+    /// Creates a scope sharing the arenas of this map. Inferred variables narrowed
+    /// inside of the scope keep their types outside of it.
+    pub fn scoped(&self) -> Self {
+        Self {
+            arenas: self.arenas.clone(),
+            instances: FastDashMap::default(),
+        }
     }
 
-    pub fn reset_counter(&self) {
-        ATOMIC_COUNTER.store(0, SeqCst);
+    pub fn new_generic_id(&self, file: (FileId, FileVersion)) -> GenericId {
+        let mut arena = self.arenas.entry(file).or_default();
+
+        let index = arena.parents.len();
+        arena.parents.push(index);
+        arena.ranks.push(0);
+        arena.types.push(DataType::Any);
+        arena.inferred.push(false);
+
+        GenericId { file, index }
     }
 
-    pub fn constrain_generic_type(&self, id: usize, constraint: DataType) {
+    pub fn constrain_generic_type(&self, id: GenericId, constraint: DataType) {
         if self.has_ref_to_generic(&constraint, id) {
             return;
         }
@@ -97,35 +138,109 @@ impl GenericsMap {
         self.constrain(id, constraint);
     }
 
-    fn constrain(&self, id: usize, constraint: DataType) {
+    fn constrain(&self, id: GenericId, constraint: DataType) {
         match self.get(id) {
             DataType::Generic(id) => {
                 self.constrain_generic_type(id, constraint);
             }
-            ty if self.is_more_or_equally_specific(&ty, &constraint) => {
-                self.map.insert(id, constraint);
-            }
+            ty if self.is_more_or_equally_specific(&ty, &constraint) => match constraint {
+                DataType::Generic(other) if self.can_unify(id, other) => self.unify(id, other),
+                constraint => self.set(id, constraint),
+            },
             _ => {}
         }
     }
 
+    fn find(&self, id: GenericId) -> GenericId {
+        let index = match self.arenas.get(&id.file) {
+            Some(arena) if id.index < arena.parents.len() => arena.find(id.index),
+            _ => id.index,
+        };
+
+        GenericId { index, ..id }
+    }
+
+    fn can_unify(&self, id: GenericId, other: GenericId) -> bool {
+        id.file == other.file
+            && !self.is_inferred(id)
+            && !self.is_inferred(other)
+            && self.find(id) != self.find(other)
+    }
+
+    /// Merges classes of both variables, keeping the type of `other`.
+    fn unify(&self, id: GenericId, other: GenericId) {
+        let Some(mut arena) = self.arenas.get_mut(&id.file) else {
+            return;
+        };
+
+        if id.index.max(other.index) >= arena.parents.len() {
+            return;
+        }
+
+        let root = arena.find(id.index);
+        let other_root = arena.find(other.index);
+        let ty = arena.types[other_root].clone();
+
+        let (parent, child) = if arena.ranks[root] < arena.ranks[other_root] {
+            (other_root, root)
+        } else {
+            (root, other_root)
+        };
+
+        arena.parents[child] = parent;
+        if arena.ranks[parent] == arena.ranks[child] {
+            arena.ranks[parent] += 1;
+        }
+        arena.types[parent] = ty;
+    }
+
+    fn set(&self, id: GenericId, ty: DataType) {
+        let root = self.find(id);
+
+        // Inferred signatures only narrow inside of the current scope
+        if self.is_inferred(id) {
+            self.instances.insert(root, ty);
+            return;
+        }
+
+        if let Some(mut arena) = self.arenas.get_mut(&id.file) {
+            if let Some(root_ty) = arena.types.get_mut(root.index) {
+                *root_ty = ty;
+            }
+        }
+    }
+
     #[inline]
-    pub fn mark_as_inferred(&self, id: usize) {
-        self.inferred.insert(id);
+    pub fn mark_as_inferred(&self, id: GenericId) {
+        if let Some(mut arena) = self.arenas.get_mut(&id.file) {
+            if let Some(inferred) = arena.inferred.get_mut(id.index) {
+                *inferred = true;
+            }
+        }
     }
 
     #[inline]
-    pub fn is_inferred(&self, id: usize) -> bool {
-        self.inferred.contains(&id)
+    pub fn is_inferred(&self, id: GenericId) -> bool {
+        self.arenas
+            .get(&id.file)
+            .and_then(|arena| arena.inferred.get(id.index).copied())
+            .unwrap_or(false)
     }
 
-    pub fn get(&self, id: usize) -> DataType {
-        let ty = self.map.get(&id).map(|t| t.value().clone());
+    pub fn get(&self, id: GenericId) -> DataType {
+        let root = self.find(id);
 
-        ty.unwrap_or(DataType::Any)
+        if let Some(ty) = self.instances.get(&root) {
+            return ty.value().clone();
+        }
+
+        self.arenas
+            .get(&root.file)
+            .and_then(|arena| arena.types.get(root.index).cloned())
+            .unwrap_or(DataType::Any)
     }
 
-    pub fn get_recursive(&self, id: usize) -> DataType {
+    pub fn get_recursive(&self, id: GenericId) -> DataType {
         match self.get(id) {
             DataType::Generic(id) => self.get_recursive(id),
             DataType::Union(types) => DataType::Union(
@@ -161,30 +276,67 @@ impl GenericsMap {
         }
     }
 
-    pub fn clean(&self, file_id: FileId, file_version: FileVersion) {
-        self.generics_per_file
-            .get(&(file_id, file_version))
-            .iter()
-            .for_each(|generics| {
-                let ids = generics.value();
-                for id in ids {
-                    self.map.remove(id);
-                    self.inferred.remove(id);
-                }
-            });
-        self.generics_per_file.remove(&(file_id, file_version));
+    /// Number of type variables allocated for a file version.
+    pub fn generic_count(&self, file: (FileId, FileVersion)) -> usize {
+        self.arenas
+            .get(&file)
+            .map(|arena| arena.parents.len())
+            .unwrap_or(0)
+    }
+
+    /// Copies the type variables of a file version, allocated from `start` on.
+    ///
+    /// Returns `None` if some of them are unified with variables allocated before `start`.
+    pub fn save(&self, file: (FileId, FileVersion), start: usize) -> Option<Vec<SavedGeneric>> {
+        let Some(arena) = self.arenas.get(&file) else {
+            return Some(vec![]);
+        };
+
+        (start..arena.parents.len())
+            .map(|index| {
+                let root = arena.find(index);
+
+                (root >= start).then(|| SavedGeneric {
+                    root: root - start,
+                    data_type: arena.types[root].clone(),
+                    inferred: arena.inferred[index],
+                })
+            })
+            .collect()
     }
 
-    pub fn insert(&self, file_id: FileId, file_version: FileVersion, generics: Vec<usize>) {
-        self.generics_per_file
-            .insert((file_id, file_version), generics);
+    /// Allocates saved type variables in the arena of a file version, with their types mapped
+    /// by `map_type`, which is given the index of the first allocated variable.
+    ///
+    /// Returns the index of the first allocated variable.
+    pub fn restore(
+        &self,
+        file: (FileId, FileVersion),
+        saved: &[SavedGeneric],
+        map_type: impl Fn(usize, &DataType) -> DataType,
+    ) -> usize {
+        let mut arena = self.arenas.entry(file).or_default();
+        let start = arena.parents.len();
+
+        for generic in saved {
+            arena.parents.push(start + generic.root);
+            arena.ranks.push(0);
+            arena.types.push(map_type(start, &generic.data_type));
+            arena.inferred.push(generic.inferred);
+        }
+
+        for (offset, generic) in saved.iter().enumerate() {
+            if generic.root != offset {
+                arena.ranks[start + generic.root] = 1;
+            }
+        }
+
+        start
     }
 
-    pub fn get_generics(&self, file_id: FileId, file_version: FileVersion) -> Vec<usize> {
-        self.generics_per_file
-            .get(&(file_id, file_version))
-            .map(|generics| generics.value().clone())
-            .unwrap_or_default()
+    /// Drops the type variables of a file version.
+    pub fn clean(&self, file_id: FileId, file_version: FileVersion) {
+        self.arenas.remove(&(file_id, file_version));
     }
 
     fn is_more_or_equally_specific(&self, current: &DataType, new: &DataType) -> bool {
@@ -213,10 +365,10 @@ impl GenericsMap {
         }
     }
 
-    fn has_ref_to_generic(&self, ty: &DataType, id: usize) -> bool {
+    fn has_ref_to_generic(&self, ty: &DataType, id: GenericId) -> bool {
         match ty {
             DataType::Generic(new_id) => {
-                (*new_id == id) || {
+                (self.find(*new_id) == self.find(id)) || {
                     let ty = self.get(*new_id);
                     self.has_ref_to_generic(&ty, id)
                 }
@@ -230,16 +382,18 @@ impl GenericsMap {
 
 impl Display for GenericsMap {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut collection = self
-            .map
+        let mut files = self
+            .arenas
             .iter()
-            .map(|entry| (*entry.key(), entry.value().to_string(self)))
-            .collect::<Vec<(usize, String)>>();
+            .map(|entry| (*entry.key(), entry.value().parents.len()))
+            .collect::<Vec<_>>();
 
-        collection.sort_unstable_by_key(|(id, _)| *id);
+        files.sort_unstable_by_key(|((file_id, version), _)| (file_id.0, version.0));
 
-        for (_, s) in collection {
-            writeln!(f, "{s}")?;
+        for (file, len) in files {
+            for index in 0..len {
+                writeln!(f, "{}", self.get(GenericId { file, index }).to_string(self))?;
+            }
         }
 
         Ok(())
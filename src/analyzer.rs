@@ -0,0 +1,954 @@
+use std::future::Future;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError, RwLock as SyncRwLock};
+
+use dashmap::mapref::entry::Entry;
+use ropey::Rope;
+use tokio::sync::RwLock;
+use tokio::task::JoinSet;
+use tower_lsp_server::lsp_types::Uri;
+use tower_lsp_server::UriExt;
+
+use crate::analysis::incremental::{
+    interface_fingerprint, retarget_diagnostic, retarget_symbol_table, ParsedDocument,
+};
+use crate::analysis::snapshot::{AnalysisSnapshot, TextChange};
+use crate::analysis::{self, SymbolInfo, SymbolTable};
+use crate::config::{parse_shebang_version, ServerConfig};
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::files::{FileVersion, Files, DEFAULT_VERSION};
+use crate::fs::FS;
+use crate::grammar::{self, Grammar, LSPAnalysis, LexerResponse, ParserResponse, Span};
+use crate::paths::FileId;
+use crate::project::{ProjectConfig, ProjectConfigs};
+use crate::stdlib::{get_stdlib_version, is_stdlib_uri, read_stdlib_file, InstalledStdlib};
+use crate::utils::FastDashMap;
+
+type PinnedFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AmberVersion {
+    Alpha034,
+    Alpha035,
+    Alpha040,
+}
+
+/// Results of the analysis, that were not asked for directly, e.g. files
+/// analyzed again because of a change in a file they import.
+#[derive(Debug, Clone)]
+pub enum AnalysisEvent {
+    /// A project configuration file was loaded. `error` is set if it is invalid.
+    ProjectConfigLoaded {
+        path: PathBuf,
+        error: Option<ProjectConfigError>,
+    },
+    /// The file was analyzed again, because a file it depends on has changed.
+    DependantAnalyzed(FileId, FileVersion),
+}
+
+#[derive(Debug, Clone)]
+pub struct ProjectConfigError {
+    pub message: String,
+    /// Location of the error in `document`, in characters.
+    pub span: Option<Span>,
+    pub document: Rope,
+}
+
+/// Diagnostic of a file, with its severity adjusted by the lint settings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileDiagnostic {
+    pub diagnostic: Diagnostic,
+    pub severity: Severity,
+    pub span: Span,
+}
+
+/// Removes the analysis lock of a file version, unless its analysis completed.
+///
+/// If the analysis panics, or its future is dropped, the version can be analyzed again.
+struct AnalyzeLockGuard<'a> {
+    files: &'a Files,
+    file: (FileId, FileVersion),
+    completed: bool,
+}
+
+impl Drop for AnalyzeLockGuard<'_> {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.files.analyze_lock.remove(&self.file);
+        }
+    }
+}
+
+/// Analysis of Amber files, independent of the language server protocol.
+///
+/// ```no_run
+/// # async fn check() -> std::io::Result<()> {
+/// use std::{path::Path, sync::Arc};
+///
+/// use amber_lsp::{analyzer::{AmberVersion, Analyzer}, fs::LocalFs};
+///
+/// let analyzer = Arc::new(Analyzer::new(AmberVersion::Alpha040, Arc::new(LocalFs::new())));
+/// let (file_id, version) = analyzer.analyze_path(Path::new("main.ab")).await?;
+///
+/// for diagnostic in analyzer.get_diagnostics(file_id, version) {
+///     println!("{}", diagnostic.diagnostic.message());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Analyzer {
+    pub files: Files,
+    /// The LSP analysis implementations, created on demand for each Amber version in use.
+    lsp_analysis: FastDashMap<AmberVersion, Arc<dyn LSPAnalysis>>,
+    /// Version used for files, that don't specify one.
+    amber_version: SyncRwLock<AmberVersion>,
+    /// Version the analyzer was created with, used when the configuration doesn't specify one.
+    default_amber_version: AmberVersion,
+    /// Whether `default_amber_version` was detected, rather than chosen by the user.
+    /// A detected version is overridden by the version pinned in the project configuration.
+    amber_version_detected: bool,
+    /// Version pinned in the project configuration of the workspace.
+    project_amber_version: SyncRwLock<Option<AmberVersion>>,
+    pub project_configs: ProjectConfigs,
+    pub config: SyncRwLock<ServerConfig>,
+    /// Standard library of the installed compiler, preferred over the bundled one.
+    pub installed_stdlib: Option<InstalledStdlib>,
+    events: Mutex<Vec<AnalysisEvent>>,
+}
+
+impl Analyzer {
+    pub fn new(amber_version: AmberVersion, fs: Arc<dyn FS>) -> Self {
+        let files = Files::new(fs);
+
+        Self {
+            files,
+            lsp_analysis: FastDashMap::default(),
+            amber_version: SyncRwLock::new(amber_version.clone()),
+            default_amber_version: amber_version,
+            amber_version_detected: false,
+            project_amber_version: SyncRwLock::new(None),
+            project_configs: ProjectConfigs::default(),
+            config: SyncRwLock::new(ServerConfig::default()),
+            installed_stdlib: None,
+            events: Mutex::new(vec![]),
+        }
+    }
+
+    /// Marks the version, the analyzer was created with, as detected,
+    /// so that it can be overridden by the project configuration.
+    pub fn with_detected_version(mut self) -> Self {
+        self.amber_version_detected = true;
+        self
+    }
+
+    /// Uses the standard library of the installed compiler for its version of Amber.
+    pub fn with_installed_stdlib(mut self, installed_stdlib: Option<InstalledStdlib>) -> Self {
+        self.installed_stdlib = installed_stdlib;
+        self
+    }
+
+    /// Returns events, that happened since the last call.
+    pub fn take_events(&self) -> Vec<AnalysisEvent> {
+        std::mem::take(&mut *self.events.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    fn push_event(&self, event: AnalysisEvent) {
+        self.events
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(event);
+    }
+
+    pub fn get_lsp_analysis(&self, amber_version: &AmberVersion) -> Arc<dyn LSPAnalysis> {
+        self.lsp_analysis
+            .entry(amber_version.clone())
+            .or_insert_with(|| match amber_version {
+                AmberVersion::Alpha034 => Arc::new(grammar::alpha034::AmberCompiler::new()),
+                AmberVersion::Alpha035 => Arc::new(grammar::alpha035::AmberCompiler::new()),
+                AmberVersion::Alpha040 => Arc::new(grammar::alpha040::AmberCompiler::new()),
+            })
+            .clone()
+    }
+
+    /// Returns the version used for files, that don't specify one.
+    pub fn amber_version(&self) -> AmberVersion {
+        self.amber_version
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Returns the version of Amber, the file was analyzed with.
+    pub fn get_file_amber_version(&self, file_id: FileId) -> AmberVersion {
+        self.files
+            .amber_versions
+            .get(&file_id)
+            .map(|version| version.clone())
+            .unwrap_or_else(|| self.amber_version())
+    }
+
+    /// Chooses the version of Amber for a file. In order of precedence, it is taken from:
+    /// - a version in the shebang, e.g. `#!/usr/bin/env amber-0.3.5-alpha`,
+    /// - the standard library tree, the file belongs to,
+    /// - the settings of the client,
+    /// - the closest project configuration, unless the version was given on the command line,
+    /// - the version the analyzer was created with.
+    async fn detect_file_amber_version(&self, file_id: FileId, document: &Rope) -> AmberVersion {
+        let first_line = document
+            .get_line(0)
+            .map(|line| line.to_string())
+            .unwrap_or_default();
+
+        if let Some(version) = parse_shebang_version(&first_line) {
+            return version;
+        }
+
+        let uri = self.files.lookup(&file_id);
+
+        if let Some(version) = get_stdlib_version(self, &uri) {
+            return version;
+        }
+
+        let path = match uri.to_file_path() {
+            Some(path) => path.to_path_buf(),
+            None => return self.amber_version(),
+        };
+
+        let configured_version = self
+            .config
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .amber_version();
+        if let Some(version) = configured_version {
+            return version;
+        }
+
+        if self.amber_version_detected {
+            if let Some(dir) = path.parent() {
+                let project_version = self
+                    .load_project_config(dir)
+                    .await
+                    .and_then(|config| config.amber_version());
+
+                if let Some(version) = project_version {
+                    return version;
+                }
+            }
+        }
+
+        self.amber_version()
+    }
+
+    /// Replaces the settings. Returns true if files have to be analyzed again,
+    /// because the Amber version, or how imports are resolved, has changed.
+    pub async fn set_config(&self, config: ServerConfig) -> bool {
+        let old_config = std::mem::replace(
+            &mut *self.config.write().unwrap_or_else(PoisonError::into_inner),
+            config.clone(),
+        );
+
+        self.update_amber_version().await
+            || old_config.stdlib_path != config.stdlib_path
+            || old_config.import_paths != config.import_paths
+    }
+
+    /// Updates the version used for files, that don't specify one, according to the settings
+    /// of the client, the project configuration of the workspace and the command line, in that order.
+    /// Returns true if the version has changed.
+    pub async fn update_amber_version(&self) -> bool {
+        let configured_version = self
+            .config
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .amber_version();
+        let project_version = match self.amber_version_detected {
+            true => self
+                .project_amber_version
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone(),
+            false => None,
+        };
+
+        let amber_version = configured_version
+            .or(project_version)
+            .unwrap_or(self.default_amber_version.clone());
+
+        if amber_version == self.amber_version() {
+            return false;
+        }
+
+        *self
+            .amber_version
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = amber_version;
+
+        true
+    }
+
+    /// Loads the project configuration, that applies to files in `dir`.
+    #[tracing::instrument(skip_all)]
+    pub async fn load_project_config(&self, dir: &Path) -> Option<Arc<ProjectConfig>> {
+        let path = self
+            .project_configs
+            .find(self.files.fs.as_ref(), dir)
+            .await?;
+
+        if let Some(config) = self.project_configs.get(&path) {
+            return config;
+        }
+
+        let text = self.files.fs.read(&path).await.ok()?;
+        let root = path.parent()?.to_path_buf();
+
+        let (config, error) = match ProjectConfig::parse(&text, root) {
+            Ok(config) => (Some(Arc::new(config)), None),
+            Err(err) => {
+                let document = Rope::from_str(&text);
+                let span = err.span().map(|span| {
+                    Span::new(
+                        document.byte_to_char(span.start),
+                        document.byte_to_char(span.end),
+                    )
+                });
+
+                let error = ProjectConfigError {
+                    message: err.message().to_string(),
+                    span,
+                    document,
+                };
+
+                (None, Some(error))
+            }
+        };
+
+        self.project_configs.insert(path.clone(), config.clone());
+        self.push_event(AnalysisEvent::ProjectConfigLoaded { path, error });
+
+        config
+    }
+
+    /// Reads the project configuration of the workspace folder,
+    /// which may pin the version of Amber.
+    pub async fn load_workspace_config(&self, folder: &Path) {
+        let project_version = self
+            .load_project_config(folder)
+            .await
+            .and_then(|config| config.amber_version());
+
+        *self
+            .project_amber_version
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = project_version;
+    }
+
+    /// Drops analysis results of all files and analyzes them again.
+    /// Returns the analyzed files.
+    pub async fn reanalyze_all(self: &Arc<Self>) -> Vec<(FileId, FileVersion)> {
+        let files = self.get_latest_files();
+
+        for file in files.iter() {
+            self.files.invalidate(file);
+        }
+        self.files.function_cache.clear();
+
+        for (file_id, version) in files.iter() {
+            self.analyze_document(*file_id, *version).await;
+        }
+
+        self.files.collect_garbage();
+
+        files
+    }
+
+    pub fn get_latest_files(&self) -> Vec<(FileId, FileVersion)> {
+        self.files
+            .document_map
+            .iter()
+            .map(|entry| *entry.key())
+            .filter(|(file_id, version)| *version == self.files.get_latest_version(*file_id))
+            .collect()
+    }
+
+    /// Reads a document from the disk, or from the bundled standard library.
+    pub async fn read_document(&self, uri: &Uri) -> Result<String> {
+        if is_stdlib_uri(uri) {
+            return match read_stdlib_file(uri) {
+                Some(text) => Ok(text.to_string()),
+                None => Err(Error::new(
+                    ErrorKind::NotFound,
+                    "File not found in the standard library.",
+                )),
+            };
+        }
+
+        let file_path = match uri.to_file_path() {
+            Some(path) => path,
+            None => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "Invalid URI: Unable to convert to file path.",
+                ));
+            }
+        };
+
+        self.files.fs.read(&file_path).await
+    }
+
+    /// Loads the document, unless it is loaded already, and analyzes it.
+    #[tracing::instrument(skip_all)]
+    pub fn open_document<'a>(
+        self: &'a Arc<Self>,
+        uri: &'a Uri,
+    ) -> PinnedFuture<'a, (FileId, FileVersion)> {
+        Box::pin(async move {
+            let mut version = DEFAULT_VERSION;
+
+            if let Some(file_id) = self.files.get(uri) {
+                let latest_version = self.files.get_latest_version(file_id);
+
+                if self
+                    .files
+                    .document_map
+                    .contains_key(&(file_id, latest_version))
+                {
+                    // Analysis results may have been dropped, e.g. after a configuration change
+                    self.analyze_document(file_id, latest_version).await;
+
+                    return Ok((file_id, latest_version));
+                }
+
+                // The document was unloaded after a change on the disk
+                version = FileVersion(latest_version.0 + 1);
+            }
+
+            let text = Rope::from_str(&self.read_document(uri).await?);
+
+            let file_id = self.files.insert(uri.clone(), version);
+
+            self.files.document_map.insert((file_id, version), text);
+
+            self.analyze_document(file_id, version).await;
+
+            Ok((file_id, version))
+        })
+    }
+
+    /// Analyzes the file on the disk.
+    pub async fn analyze_path(self: &Arc<Self>, path: &Path) -> Result<(FileId, FileVersion)> {
+        let uri = Uri::from_file_path(path)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Invalid path"))?;
+
+        self.open_document(&uri).await
+    }
+
+    /// Analyzes `text` as the contents of the file at `path`, instead of its contents on the disk.
+    /// Files importing it are analyzed again.
+    pub async fn analyze_source(
+        self: &Arc<Self>,
+        path: &Path,
+        text: &str,
+    ) -> Result<(FileId, FileVersion)> {
+        let uri = Uri::from_file_path(path)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Invalid path"))?;
+
+        let version = match self.files.get(&uri) {
+            Some(file_id) => FileVersion(self.files.get_latest_version(file_id).0 + 1),
+            None => DEFAULT_VERSION,
+        };
+
+        let file_id = self.files.insert(uri, version);
+        self.files
+            .document_map
+            .insert((file_id, version), Rope::from_str(text));
+
+        self.analyze_document(file_id, version).await;
+
+        Ok((file_id, version))
+    }
+
+    /// Analyzes the file version, and then the files, that depend on it.
+    ///
+    /// If the public interface of the file didn't change since the previous version,
+    /// the files depending on it are only pointed at the new version.
+    #[tracing::instrument(skip_all)]
+    pub async fn analyze_document(self: &Arc<Self>, file_id: FileId, version: FileVersion) {
+        let previous_interface = self.files.interfaces.get(&file_id).map(|entry| *entry);
+
+        if !self.analyze_file(file_id, version).await {
+            return;
+        }
+
+        let interface = self.files.interfaces.get(&file_id).map(|entry| *entry);
+
+        match (previous_interface, interface) {
+            (Some((previous_version, previous)), Some((_, current)))
+                if previous == current
+                    && previous_version.0 < version.0
+                    && self.retarget_dependants(file_id, previous_version, version) => {}
+            _ => Box::pin(self.analyze_dependencies(file_id, version)).await,
+        }
+    }
+
+    /// Analyzes the file version, unless it's analyzed already.
+    /// Returns false if there was nothing to analyze, or the analysis was cancelled.
+    #[tracing::instrument(skip_all)]
+    async fn analyze_file(self: &Arc<Self>, file_id: FileId, version: FileVersion) -> bool {
+        let rope = match self.files.document_map.get(&(file_id, version)) {
+            Some(document) => document.clone(),
+            None => return false,
+        };
+
+        if self.files.is_outdated(&(file_id, version)) {
+            return false;
+        }
+
+        // The lock is claimed before any await, so that one task analyzes the version
+        let mut lock_w = match self.files.analyze_lock.entry((file_id, version)) {
+            Entry::Occupied(_) => return false,
+            Entry::Vacant(entry) => {
+                let lock = Arc::new(RwLock::new(false));
+                let Ok(lock_w) = lock.clone().try_write_owned() else {
+                    return false;
+                };
+
+                entry.insert(lock);
+                lock_w
+            }
+        };
+        let mut lock_guard = AnalyzeLockGuard {
+            files: &self.files,
+            file: (file_id, version),
+            completed: false,
+        };
+
+        if let Some(path) = self.files.lookup(&file_id).to_file_path() {
+            if let Some(dir) = path.parent() {
+                self.load_project_config(dir).await;
+            }
+        }
+
+        let amber_version = self.detect_file_amber_version(file_id, &rope).await;
+        self.files
+            .amber_versions
+            .insert(file_id, amber_version.clone());
+
+        let ParsedDocument {
+            ast,
+            errors,
+            semantic_tokens,
+            ..
+        } = self.parse_document(file_id, &rope, &amber_version);
+
+        self.files.diagnostics.insert((file_id, version), errors);
+        self.files.ast_map.insert((file_id, version), ast.clone());
+        self.files
+            .semantic_token_map
+            .insert((file_id, version), semantic_tokens);
+
+        self.files
+            .symbol_table
+            .insert((file_id, version), Arc::new(SymbolTable::default()));
+        // Generic types of a snapshot of this version are replaced, so it can't be served anymore
+        self.files
+            .last_good_analysis
+            .remove_if(&file_id, |_, snapshot| {
+                snapshot.version == version && snapshot.symbol_table.is_some()
+            });
+        self.files.generic_types.clean(file_id, version);
+
+        match &ast {
+            Grammar::Alpha034(Some(ast)) => {
+                analysis::alpha034::global::analyze_global_stmnt(file_id, version, ast, self).await;
+            }
+            Grammar::Alpha035(Some(ast)) => {
+                analysis::alpha035::global::analyze_global_stmnt(file_id, version, ast, self).await;
+            }
+            Grammar::Alpha040(Some(ast)) => {
+                analysis::alpha040::global::analyze_global_stmnt(file_id, version, ast, self).await;
+            }
+            _ => {
+                self.restore_last_good_analysis(file_id, version, &rope);
+            }
+        }
+
+        if self.files.is_outdated(&(file_id, version)) {
+            tracing::debug!(
+                "Cancelled analysis of version {} of {file_id:?}, as a newer one was received",
+                version.0
+            );
+
+            drop(lock_w);
+            self.files.invalidate(&(file_id, version));
+            return false;
+        }
+
+        if !matches!(
+            ast,
+            Grammar::Alpha034(None) | Grammar::Alpha035(None) | Grammar::Alpha040(None)
+        ) {
+            self.save_last_good_analysis(file_id, version, rope, ast);
+
+            // Results of functions, that were removed from the file
+            self.files.function_cache.retain(|(id, _), function| {
+                *id != file_id || function.file() == (file_id, version)
+            });
+        }
+
+        lock_guard.completed = true;
+        *lock_w = true;
+        drop(lock_w);
+
+        self.files.mark_as_analyzed((file_id, version));
+
+        if let Some(fingerprint) = interface_fingerprint(&self.files, &(file_id, version)) {
+            self.files
+                .interfaces
+                .insert(file_id, (version, fingerprint));
+        }
+
+        true
+    }
+
+    /// Lexes and parses the document, unless the same text was parsed before.
+    fn parse_document(
+        &self,
+        file_id: FileId,
+        document: &Rope,
+        amber_version: &AmberVersion,
+    ) -> ParsedDocument {
+        if let Some(parsed) = self.files.parse_cache.get(&file_id) {
+            if parsed.is_parse_of(document, amber_version) {
+                return parsed.clone();
+            }
+        }
+
+        let lsp_analysis = self.get_lsp_analysis(amber_version);
+
+        let LexerResponse {
+            tokens,
+            errors: lexer_errors,
+        } = lsp_analysis.lex(&document.to_string());
+
+        let ParserResponse {
+            ast,
+            errors,
+            semantic_tokens,
+        } = lsp_analysis.parse(&tokens);
+
+        let parsed = ParsedDocument {
+            document: document.clone(),
+            amber_version: amber_version.clone(),
+            ast,
+            errors: lexer_errors
+                .into_iter()
+                .map(|(message, span)| (Diagnostic::Syntax(message), span))
+                .chain(
+                    errors
+                        .iter()
+                        .map(|err| (Diagnostic::Syntax(err.to_string()), *err.span())),
+                )
+                .collect(),
+            semantic_tokens,
+        };
+
+        self.files.parse_cache.insert(file_id, parsed.clone());
+
+        parsed
+    }
+
+    /// Points files, that depend on the file, at its new version after a change,
+    /// that didn't affect its public interface, instead of analyzing them again.
+    ///
+    /// Returns false if the previous version is not available anymore.
+    fn retarget_dependants(
+        &self,
+        file_id: FileId,
+        previous_version: FileVersion,
+        version: FileVersion,
+    ) -> bool {
+        let from = (file_id, previous_version);
+        let to = (file_id, version);
+
+        let (Some(previous_document), Some(document)) = (
+            self.files
+                .document_map
+                .get(&from)
+                .map(|entry| entry.clone()),
+            self.files.document_map.get(&to).map(|entry| entry.clone()),
+        ) else {
+            return false;
+        };
+
+        let change = TextChange::between(&previous_document, &document);
+
+        for dependant in self
+            .files
+            .get_dependants_in_order(file_id)
+            .into_iter()
+            .flatten()
+        {
+            if let Some(mut symbol_table) = self.files.symbol_table.get_mut(&dependant) {
+                retarget_symbol_table(Arc::make_mut(&mut symbol_table), from, to, &change);
+            }
+
+            if let Some(mut diagnostics) = self.files.diagnostics.get_mut(&dependant) {
+                for (diagnostic, _) in diagnostics.iter_mut() {
+                    retarget_diagnostic(diagnostic, file_id, &change);
+                }
+            }
+        }
+
+        tracing::debug!(
+            "Interface of {file_id:?} didn't change in version {}, its dependants are kept",
+            version.0
+        );
+
+        true
+    }
+
+    /// Keeps the analysis of a file, that could be parsed,
+    /// to serve IDE features while the following versions don't parse.
+    fn save_last_good_analysis(
+        &self,
+        file_id: FileId,
+        version: FileVersion,
+        document: Rope,
+        ast: Grammar,
+    ) {
+        let file = (file_id, version);
+
+        let is_outdated = self
+            .files
+            .last_good_analysis
+            .get(&file_id)
+            .is_some_and(|snapshot| snapshot.version.0 > version.0);
+
+        if is_outdated {
+            return;
+        }
+
+        if !self.files.symbol_table.contains_key(&file) {
+            return;
+        }
+
+        let semantic_tokens = self
+            .files
+            .semantic_token_map
+            .get(&file)
+            .map(|tokens| tokens.clone())
+            .unwrap_or_default();
+
+        let previous = self.files.last_good_analysis.insert(
+            file_id,
+            AnalysisSnapshot {
+                version,
+                document,
+                ast,
+                symbol_table: None,
+                semantic_tokens,
+                dependencies: self.files.get_file_dependencies(&file),
+            },
+        );
+
+        // Generic types of a dropped version were only kept for its snapshot
+        if let Some(previous) = previous {
+            if previous.version != version && previous.symbol_table.is_some() {
+                self.files.generic_types.clean(file_id, previous.version);
+            }
+        }
+    }
+
+    /// Serves a version of a file, that doesn't parse, with the last analysis,
+    /// that succeeded, moved through the edits made since then.
+    fn restore_last_good_analysis(&self, file_id: FileId, version: FileVersion, document: &Rope) {
+        let file = (file_id, version);
+
+        let Some(last_good) = self
+            .files
+            .last_good_analysis
+            .get(&file_id)
+            .map(|snapshot| snapshot.clone())
+        else {
+            return;
+        };
+
+        tracing::debug!(
+            "Serving version {} of {file_id:?} from the analysis of version {}",
+            version.0,
+            last_good.version.0
+        );
+
+        let Some(symbol_table) = last_good.symbol_table(&self.files, file_id) else {
+            return;
+        };
+
+        let snapshot = last_good.remap(&symbol_table, file, document);
+
+        if let Some(symbol_table) = snapshot.symbol_table {
+            self.files.symbol_table.insert(file, symbol_table);
+        }
+        self.files
+            .semantic_token_map
+            .insert(file, snapshot.semantic_tokens);
+
+        for (dependency, span) in snapshot.dependencies {
+            self.files.add_file_dependency(&file, dependency, span);
+        }
+    }
+
+    /// Analyzes files, that depend on the file, again.
+    ///
+    /// Each dependant is analyzed once, after the files it imports.
+    /// Dependants, that don't import each other, are analyzed concurrently.
+    pub async fn analyze_dependencies(
+        self: &Arc<Self>,
+        file_id: FileId,
+        file_version: FileVersion,
+    ) {
+        for group in self.files.get_dependants_in_order(file_id) {
+            if self.files.is_outdated(&(file_id, file_version)) {
+                return;
+            }
+
+            for file in group.iter() {
+                self.files.invalidate(file);
+            }
+
+            let mut tasks = JoinSet::new();
+
+            for (dep_file_id, dep_version) in group {
+                let analyzer = self.clone();
+
+                tasks.spawn(async move {
+                    let is_analyzed = analyzer.analyze_file(dep_file_id, dep_version).await;
+
+                    (dep_file_id, dep_version, is_analyzed)
+                });
+            }
+
+            while let Some(result) = tasks.join_next().await {
+                match result {
+                    Ok((dep_file_id, dep_version, true)) => {
+                        self.push_event(AnalysisEvent::DependantAnalyzed(dep_file_id, dep_version));
+                    }
+                    Ok(_) => {}
+                    Err(err) => tracing::error!("Analysis of a dependant failed: {err}"),
+                }
+            }
+        }
+    }
+
+    /// Analyzes files, that failed to import a file, which might exist now.
+    /// Returns the analyzed files.
+    pub async fn analyze_files_with_missing_imports(
+        self: &Arc<Self>,
+    ) -> Vec<(FileId, FileVersion)> {
+        let files = self
+            .files
+            .diagnostics
+            .iter()
+            .filter(|entry| {
+                let (file_id, version) = *entry.key();
+
+                version == self.files.get_latest_version(file_id)
+                    && entry.value().iter().any(|(diagnostic, _)| {
+                        matches!(diagnostic, Diagnostic::FileNotFound { .. })
+                    })
+            })
+            .map(|entry| *entry.key())
+            .collect::<Vec<_>>();
+
+        for file in files.iter() {
+            self.files.invalidate(file);
+            self.analyze_document(file.0, file.1).await;
+        }
+
+        files
+    }
+
+    /// Collects errors and warnings of the given file version, with severities
+    /// set by the lint settings of the client and of the project.
+    pub fn get_diagnostics(
+        &self,
+        file_id: FileId,
+        file_version: FileVersion,
+    ) -> Vec<FileDiagnostic> {
+        let config = self
+            .config
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        let project_config = self
+            .files
+            .lookup(&file_id)
+            .to_file_path()
+            .and_then(|path| Some(self.project_configs.get_cached(path.parent()?)))
+            .flatten();
+
+        match self.files.diagnostics.get(&(file_id, file_version)) {
+            Some(diagnostics) => diagnostics
+                .iter()
+                .filter_map(|(diagnostic, span)| {
+                    let lint = diagnostic.code().and_then(|code| {
+                        config.lints.get(code).copied().or_else(|| {
+                            project_config
+                                .as_ref()
+                                .and_then(|project_config| project_config.lints.get(code).copied())
+                        })
+                    });
+
+                    let severity = match lint {
+                        Some(lint) => lint.to_severity()?,
+                        None => diagnostic.severity(),
+                    };
+
+                    Some(FileDiagnostic {
+                        diagnostic: diagnostic.clone(),
+                        severity,
+                        span: *span,
+                    })
+                })
+                .take(config.max_number_of_problems)
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// Returns definitions of symbols in the file, ordered by their position.
+    pub fn get_symbols(&self, file_id: FileId, file_version: FileVersion) -> Vec<SymbolInfo> {
+        match self.files.symbol_table.get(&(file_id, file_version)) {
+            Some(symbol_table) => symbol_table
+                .symbols
+                .iter()
+                .map(|(_, symbol_info)| symbol_info)
+                .filter(|symbol_info| symbol_info.is_definition)
+                .cloned()
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// Returns the symbol at the offset (in characters) in the file.
+    pub fn get_symbol_at(
+        &self,
+        file_id: FileId,
+        file_version: FileVersion,
+        offset: usize,
+    ) -> Option<SymbolInfo> {
+        self.files
+            .symbol_table
+            .get(&(file_id, file_version))?
+            .symbols
+            .get(&offset)
+            .cloned()
+    }
+
+    /// Formats the type of the symbol, with generic types resolved.
+    pub fn format_type(&self, symbol_info: &SymbolInfo) -> String {
+        symbol_info.data_type.to_string(&self.files.generic_types)
+    }
+}
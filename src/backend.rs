@@ -1,42 +1,67 @@
+use std::collections::HashMap;
 use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::io::ErrorKind;
+use std::path::Path;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::{Arc, PoisonError};
+use std::time::Duration;
 
 use chumsky::container::Seq;
 use ropey::Rope;
-use tokio::sync::RwLock;
+use rustc_hash::FxHasher;
+use tokio::task::AbortHandle;
 use tower_lsp_server::jsonrpc::{Error, Result};
 use tower_lsp_server::lsp_types::*;
 use tower_lsp_server::UriExt;
 use tower_lsp_server::{Client, LanguageServer};
 
 use crate::analysis::{
-    self, get_symbol_definition_info, Context, FunctionSymbol, SymbolInfo, SymbolTable, SymbolType,
-    VariableSymbol,
+    get_symbol_definition_info, get_symbol_definition_location, map_import_path, Context,
+    FunctionSymbol, SymbolInfo, SymbolType, VariableSymbol,
 };
-use crate::files::{FileVersion, Files, DEFAULT_VERSION};
-use crate::fs::{LocalFs, FS};
-use crate::grammar::{self, Grammar, LSPAnalysis, ParserResponse};
+pub use crate::analyzer::AmberVersion;
+use crate::analyzer::{AnalysisEvent, Analyzer, FileDiagnostic};
+use crate::config::ServerConfig;
+use crate::diagnostics::Severity;
+use crate::files::{FileVersion, DEFAULT_VERSION};
+use crate::format::format_document;
+use crate::fs::{find_amber_files, LocalFs, FS};
+use crate::grammar;
 use crate::paths::FileId;
-use crate::stdlib::{find_in_stdlib, save_resources};
+use crate::project::{is_project_config_file, ProjectConfig};
+use crate::stdlib::{find_in_stdlib, get_file_stdlib_source, is_stdlib_uri, STDLIB_SCHEME};
+use crate::trace::TraceSession;
+use crate::utils::{FastDashMap, FastDashSet};
 
 type PinnedFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
 
-#[derive(Clone, Debug, PartialEq)]
-pub enum AmberVersion {
-    Alpha034,
-    Alpha035,
-    Alpha040,
-}
-
-#[derive(Debug)]
+/// Language server, that exposes the [`Analyzer`] to an LSP client.
+///
+/// Clones share all of the state, so that a clone can be moved into a spawned task.
+#[derive(Debug, Clone)]
 pub struct Backend {
     pub client: Client,
-    pub files: Files,
-    /// The LSP analysis implementation.
-    pub lsp_analysis: Box<dyn LSPAnalysis>,
+    pub analyzer: Arc<Analyzer>,
     pub token_types: Box<[SemanticTokenType]>,
-    pub amber_version: AmberVersion,
+    /// Whether the client supports `workspace/configuration` requests.
+    pub configuration_support: Arc<AtomicBool>,
+    /// Root folders of the workspace opened in the client.
+    pub workspace_folders: Arc<FastDashSet<Uri>>,
+    /// Whether the client pulls diagnostics (`textDocument/diagnostic`),
+    /// in which case they are not pushed with `textDocument/publishDiagnostics`.
+    pub pull_diagnostics: Arc<AtomicBool>,
+    /// Whether the client supports `workspace/diagnostic/refresh` requests.
+    pub diagnostics_refresh: Arc<AtomicBool>,
+    /// Whether the client supports server initiated `$/progress` notifications.
+    pub work_done_progress: Arc<AtomicBool>,
+    progress_token_counter: Arc<AtomicI32>,
+    /// Delayed publishes of diagnostics of edited files.
+    pending_publishes: Arc<FastDashMap<FileId, (FileVersion, AbortHandle)>>,
+    /// Whether log events are forwarded to the client with `$/logTrace`.
+    pub trace: Arc<TraceSession>,
 }
 
 impl Backend {
@@ -47,63 +72,213 @@ impl Backend {
             Arc::new(LocalFs::new())
         };
 
-        let files = Files::new(fs);
-
-        files.generic_types.reset_counter();
+        Self::with_analyzer(client, Arc::new(Analyzer::new(amber_version, fs)))
+    }
 
+    /// Creates a session using the analyzer, which may be shared with other sessions.
+    pub fn with_analyzer(client: Client, analyzer: Arc<Analyzer>) -> Self {
         Self {
+            trace: Arc::new(TraceSession::new(client.clone())),
             client,
-            files,
-            lsp_analysis: match amber_version {
-                AmberVersion::Alpha034 => Box::new(grammar::alpha034::AmberCompiler::new()),
-                AmberVersion::Alpha035 => Box::new(grammar::alpha035::AmberCompiler::new()),
-                AmberVersion::Alpha040 => Box::new(grammar::alpha040::AmberCompiler::new()),
-            },
-            token_types: match amber_version {
+            token_types: match analyzer.amber_version() {
                 AmberVersion::Alpha034 => Box::new(grammar::alpha034::semantic_tokens::LEGEND_TYPE),
                 AmberVersion::Alpha035 => Box::new(grammar::alpha035::semantic_tokens::LEGEND_TYPE),
                 AmberVersion::Alpha040 => Box::new(grammar::alpha040::semantic_tokens::LEGEND_TYPE),
             },
-            amber_version,
+            analyzer,
+            configuration_support: Arc::new(AtomicBool::new(false)),
+            workspace_folders: Arc::new(FastDashSet::default()),
+            pull_diagnostics: Arc::new(AtomicBool::new(false)),
+            diagnostics_refresh: Arc::new(AtomicBool::new(false)),
+            work_done_progress: Arc::new(AtomicBool::new(false)),
+            progress_token_counter: Arc::new(AtomicI32::new(0)),
+            pending_publishes: Arc::new(FastDashMap::default()),
+        }
+    }
+
+    /// Publishes results of the analysis, that were not asked for directly.
+    async fn handle_analysis_events(&self) {
+        let mut dependants_analyzed = false;
+
+        for event in self.analyzer.take_events() {
+            match event {
+                AnalysisEvent::ProjectConfigLoaded { path, error } => {
+                    let uri = match Uri::from_file_path(&path) {
+                        Some(uri) => uri,
+                        None => continue,
+                    };
+
+                    let diagnostics = error
+                        .into_iter()
+                        .map(|error| Diagnostic {
+                            range: error
+                                .span
+                                .map(|span| {
+                                    Range::new(
+                                        self.offset_to_position(span.start, &error.document),
+                                        self.offset_to_position(span.end, &error.document),
+                                    )
+                                })
+                                .unwrap_or_default(),
+                            severity: Some(DiagnosticSeverity::ERROR),
+                            source: Some("amber".to_string()),
+                            message: error.message,
+                            ..Default::default()
+                        })
+                        .collect();
+
+                    self.client
+                        .publish_diagnostics(uri, diagnostics, None)
+                        .await;
+                }
+                AnalysisEvent::DependantAnalyzed(file_id, version) => {
+                    self.publish_issues(file_id, version).await;
+                    dependants_analyzed = true;
+                }
+            }
+        }
+
+        if dependants_analyzed && self.diagnostics_refresh.load(Ordering::Relaxed) {
+            let _ = self.client.workspace_diagnostic_refresh().await;
         }
     }
 
+    /// Applies new settings. Changing the Amber version, or how imports are resolved,
+    /// makes all files be analyzed again.
     #[tracing::instrument(skip_all)]
-    pub fn open_document<'a>(&'a self, uri: &'a Uri) -> PinnedFuture<'a, (FileId, FileVersion)> {
-        Box::pin(async move {
-            if let Some(file_id) = self.files.get(uri) {
-                let version = self.files.get_latest_version(file_id);
-                return Ok((file_id, version));
+    pub async fn apply_config(&self, config: ServerConfig) {
+        if *self
+            .analyzer
+            .config
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            == config
+        {
+            return;
+        }
+
+        if self.analyzer.set_config(config).await {
+            self.reanalyze_all().await;
+        } else {
+            self.republish_all().await;
+        }
+    }
+
+    /// Loads the project configuration, that applies to files in `dir`.
+    /// Problems with the configuration file are reported as its diagnostics.
+    pub async fn load_project_config(&self, dir: &Path) -> Option<Arc<ProjectConfig>> {
+        let config = self.analyzer.load_project_config(dir).await;
+        self.handle_analysis_events().await;
+
+        config
+    }
+
+    /// Reads the project configuration of the first workspace folder,
+    /// which may pin the version of Amber.
+    async fn load_workspace_config(&self) {
+        let folder = match self.workspace_folders.iter().next() {
+            Some(folder) => folder.clone(),
+            None => return,
+        };
+
+        if let Some(folder_path) = folder.to_file_path() {
+            self.analyzer.load_workspace_config(&folder_path).await;
+            self.handle_analysis_events().await;
+        }
+    }
+
+    /// Drops analysis results of all files and analyzes them again.
+    async fn reanalyze_all(&self) {
+        for (file_id, version) in self.analyzer.reanalyze_all().await {
+            self.publish_issues(file_id, version).await;
+        }
+
+        self.handle_analysis_events().await;
+
+        if self.diagnostics_refresh.load(Ordering::Relaxed) {
+            let _ = self.client.workspace_diagnostic_refresh().await;
+        }
+    }
+
+    async fn republish_all(&self) {
+        for (file_id, version) in self.analyzer.get_latest_files() {
+            self.publish_issues(file_id, version).await;
+        }
+
+        if self.diagnostics_refresh.load(Ordering::Relaxed) {
+            let _ = self.client.workspace_diagnostic_refresh().await;
+        }
+    }
+
+    /// Pulls the `amber-lsp` settings section from the client.
+    async fn pull_config(&self) {
+        if !self.configuration_support.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let settings = self
+            .client
+            .configuration(vec![ConfigurationItem {
+                scope_uri: None,
+                section: Some("amber-lsp".to_string()),
+            }])
+            .await;
+
+        match settings {
+            Ok(mut settings) if !settings.is_empty() => {
+                self.apply_config(ServerConfig::from_value(settings.remove(0)))
+                    .await;
+            }
+            Ok(_) => {}
+            Err(err) => {
+                tracing::warn!("Could not pull configuration: {}", err);
             }
+        }
+    }
 
-            let file_path = match uri.to_file_path() {
-                Some(path) => path,
-                None => {
-                    return Err(Error::invalid_params(
-                        "Invalid URI: Unable to convert to file path.",
-                    ));
-                }
-            };
+    /// Handles the `amber/stdlibContent` request, which lets clients show files
+    /// of the bundled standard library, e.g. after going to a definition.
+    #[tracing::instrument(skip_all)]
+    pub async fn stdlib_content(&self, params: TextDocumentIdentifier) -> Result<String> {
+        if !is_stdlib_uri(&params.uri) {
+            return Err(Error::invalid_params("Not a standard library URI."));
+        }
 
-            let text = match self.files.fs.read(&file_path).await {
-                Ok(text) => Rope::from_str(&text),
-                Err(_) => {
-                    return Err(Error::internal_error());
-                }
-            };
+        self.analyzer
+            .read_document(&params.uri)
+            .await
+            .map_err(|err| Error::invalid_params(err.to_string()))
+    }
 
-            let file_id = self.files.insert(uri.clone(), DEFAULT_VERSION);
+    /// Handles `$/setTrace` notifications.
+    pub async fn set_trace(&self, params: SetTraceParams) {
+        self.trace.set_value(params.value);
+    }
 
-            self.files
-                .document_map
-                .insert((file_id, DEFAULT_VERSION), text);
+    #[tracing::instrument(skip_all)]
+    pub fn open_document<'a>(&'a self, uri: &'a Uri) -> PinnedFuture<'a, (FileId, FileVersion)> {
+        Box::pin(async move {
+            let result = self.analyzer.open_document(uri).await;
 
-            self.analyze_document(file_id, DEFAULT_VERSION).await;
+            self.handle_analysis_events().await;
 
-            Ok((file_id, DEFAULT_VERSION))
+            result.map_err(|err| match err.kind() {
+                ErrorKind::InvalidInput => Error::invalid_params(err.to_string()),
+                _ => Error::internal_error(),
+            })
         })
     }
 
+    pub async fn analyze_document(&self, file_id: FileId, version: FileVersion) {
+        self.analyzer.analyze_document(file_id, version).await;
+        self.handle_analysis_events().await;
+    }
+
+    async fn analyze_dependencies(&self, file_id: FileId, version: FileVersion) {
+        self.analyzer.analyze_dependencies(file_id, version).await;
+        self.handle_analysis_events().await;
+    }
+
     #[tracing::instrument(skip_all)]
     async fn publish_diagnostics(
         &self,
@@ -111,7 +286,7 @@ impl Backend {
         diagnostics: Vec<Diagnostic>,
         version: Option<FileVersion>,
     ) {
-        let uri = self.files.lookup(file_id);
+        let uri = self.analyzer.files.lookup(file_id);
         self.client
             .publish_diagnostics(uri, diagnostics, version.map(|v| v.into()))
             .await;
@@ -119,7 +294,11 @@ impl Backend {
 
     #[tracing::instrument(skip_all)]
     pub async fn publish_issues(&self, file_id: FileId, file_version: FileVersion) {
-        let (rope, version) = match self.files.get_document_latest_version(file_id) {
+        if self.pull_diagnostics.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let (_, version) = match self.analyzer.files.get_document_latest_version(file_id) {
             Some(document) => document,
             None => return,
         };
@@ -128,113 +307,171 @@ impl Backend {
             return;
         }
 
-        let mut diagnostics: Vec<Diagnostic> = vec![];
-
-        if let Some(errors) = self.files.errors.get(&(file_id, file_version)) {
-            let error_diags = errors.iter().map(|(msg, span)| {
-                let start_position = self.offset_to_position(span.start, &rope);
-                let end_position = self.offset_to_position(span.end, &rope);
-
-                Diagnostic::new(
-                    Range::new(start_position, end_position),
-                    Some(DiagnosticSeverity::ERROR),
-                    None,
-                    None,
-                    msg.to_string(),
-                    None,
-                    None,
-                )
-            });
-            diagnostics.extend(error_diags);
-        }
-
-        if let Some(warnings) = self.files.warnings.get(&(file_id, file_version)) {
-            let warning_diags = warnings.iter().map(|(msg, span)| {
-                let start_position = self.offset_to_position(span.start, &rope);
-                let end_position = self.offset_to_position(span.end, &rope);
-
-                Diagnostic::new(
-                    Range::new(start_position, end_position),
-                    Some(DiagnosticSeverity::WARNING),
-                    None,
-                    None,
-                    msg.to_string(),
-                    None,
-                    None,
-                )
-            });
-            diagnostics.extend(warning_diags);
-        }
+        let diagnostics = self.get_diagnostics(file_id, file_version);
 
         self.publish_diagnostics(&file_id, diagnostics, Some(version))
             .await;
     }
 
-    #[tracing::instrument(skip_all)]
-    pub async fn analyze_document(&self, file_id: FileId, version: FileVersion) {
-        let rope = match self.files.document_map.get(&(file_id, version)) {
-            Some(document) => document.clone(),
-            None => return,
-        };
+    /// Publishes diagnostics of an edited file after the configured delay,
+    /// cancelling the pending publish of its previous version.
+    pub fn publish_issues_after_delay(&self, file_id: FileId, file_version: FileVersion) {
+        let delay = self
+            .analyzer
+            .config
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .diagnostics_delay;
+
+        let backend = self.clone();
+        let task = tokio::spawn(async move {
+            if delay > 0 {
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+            }
 
-        if self.files.analyze_lock.contains_key(&(file_id, version)) {
-            return;
+            backend.publish_issues(file_id, file_version).await;
+            backend
+                .pending_publishes
+                .remove_if(&file_id, |_, (version, _)| *version == file_version);
+        });
+
+        if let Some((_, previous)) = self
+            .pending_publishes
+            .insert(file_id, (file_version, task.abort_handle()))
+        {
+            previous.abort();
         }
+    }
 
-        let lock = Arc::new(RwLock::new(false));
+    /// Cancels the pending publish of diagnostics of a file.
+    fn cancel_pending_publish(&self, file_id: FileId) {
+        if let Some((_, (_, task))) = self.pending_publishes.remove(&file_id) {
+            task.abort();
+        }
+    }
 
-        let c_lock = lock.clone();
-        let mut lock_w = c_lock.write().await;
+    /// Collects errors and warnings of the given file version as LSP diagnostics.
+    pub fn get_diagnostics(&self, file_id: FileId, file_version: FileVersion) -> Vec<Diagnostic> {
+        let rope = match self
+            .analyzer
+            .files
+            .document_map
+            .get(&(file_id, file_version))
+        {
+            Some(document) => document.clone(),
+            None => return vec![],
+        };
 
-        self.files.analyze_lock.insert((file_id, version), lock);
+        self.analyzer
+            .get_diagnostics(file_id, file_version)
+            .iter()
+            .map(|diagnostic| self.to_lsp_diagnostic(diagnostic, &rope))
+            .collect()
+    }
 
-        let tokens = self.lsp_analysis.tokenize(&rope.to_string());
+    fn to_lsp_diagnostic(&self, file_diagnostic: &FileDiagnostic, rope: &Rope) -> Diagnostic {
+        let FileDiagnostic {
+            diagnostic,
+            severity,
+            span,
+        } = file_diagnostic;
+
+        let related_information = diagnostic
+            .related()
+            .into_iter()
+            .filter_map(|related| {
+                let (related_rope, _) = self
+                    .analyzer
+                    .files
+                    .get_document_latest_version(related.file)?;
+
+                Some(DiagnosticRelatedInformation {
+                    location: Location::new(
+                        self.analyzer.files.lookup(&related.file),
+                        Range::new(
+                            self.offset_to_position(related.span.start, &related_rope),
+                            self.offset_to_position(related.span.end, &related_rope),
+                        ),
+                    ),
+                    message: related.message,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        Diagnostic {
+            range: Range::new(
+                self.offset_to_position(span.start, rope),
+                self.offset_to_position(span.end, rope),
+            ),
+            severity: Some(match severity {
+                Severity::Error => DiagnosticSeverity::ERROR,
+                Severity::Warning => DiagnosticSeverity::WARNING,
+                Severity::Information => DiagnosticSeverity::INFORMATION,
+                Severity::Hint => DiagnosticSeverity::HINT,
+            }),
+            code: diagnostic
+                .code()
+                .map(|code| NumberOrString::String(code.to_string())),
+            code_description: diagnostic
+                .documentation_url()
+                .and_then(|url| Uri::from_str(&url).ok())
+                .map(|href| CodeDescription { href }),
+            source: Some("amber".to_string()),
+            message: diagnostic.message(),
+            related_information: if related_information.is_empty() {
+                None
+            } else {
+                Some(related_information)
+            },
+            tags: None,
+            data: diagnostic.data(),
+        }
+    }
 
-        let ParserResponse {
-            ast,
-            errors,
-            semantic_tokens,
-        } = self.lsp_analysis.parse(&tokens);
+    /// Analyzes the file (opening it from disk if needed) and builds a diagnostic
+    /// report for it, which is `unchanged` if it matches `previous_result_id`.
+    #[tracing::instrument(skip_all)]
+    async fn diagnostic_report(
+        &self,
+        uri: &Uri,
+        previous_result_id: Option<&String>,
+    ) -> Result<(FileVersion, DocumentDiagnosticReport)> {
+        let (file_id, version) = self.open_document(uri).await?;
 
-        self.files.errors.insert(
-            (file_id, version),
-            errors
-                .iter()
-                .map(|err| (err.to_string(), *err.span()))
-                .collect(),
-        );
-        self.files.ast_map.insert((file_id, version), ast.clone());
-        self.files
-            .semantic_token_map
-            .insert((file_id, version), semantic_tokens);
+        if !self
+            .analyzer
+            .files
+            .is_file_analyzed(&(file_id, version))
+            .await
+        {
+            return Err(Error::content_modified());
+        }
 
-        self.files
-            .symbol_table
-            .insert((file_id, version), SymbolTable::default());
+        let diagnostics = self.get_diagnostics(file_id, version);
+        let result_id = diagnostics_result_id(&diagnostics);
 
-        match ast {
-            Grammar::Alpha034(Some(ast)) => {
-                analysis::alpha034::global::analyze_global_stmnt(file_id, version, &ast, self)
-                    .await;
-            }
-            Grammar::Alpha035(Some(ast)) => {
-                analysis::alpha035::global::analyze_global_stmnt(file_id, version, &ast, self)
-                    .await;
-            }
-            Grammar::Alpha040(Some(ast)) => {
-                analysis::alpha040::global::analyze_global_stmnt(file_id, version, &ast, self)
-                    .await;
-            }
-            _ => {}
+        if previous_result_id == Some(&result_id) {
+            return Ok((
+                version,
+                DocumentDiagnosticReport::Unchanged(RelatedUnchangedDocumentDiagnosticReport {
+                    related_documents: None,
+                    unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport {
+                        result_id,
+                    },
+                }),
+            ));
         }
 
-        *lock_w = true;
-        drop(lock_w);
-
-        Box::pin(async {
-            self.analyze_dependencies(file_id, version).await;
-        })
-        .await;
+        Ok((
+            version,
+            DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
+                related_documents: None,
+                full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                    result_id: Some(result_id),
+                    items: diagnostics,
+                },
+            }),
+        ))
     }
 
     pub fn offset_to_position(&self, offset: usize, rope: &Rope) -> Position {
@@ -248,22 +485,141 @@ impl Backend {
         Position::new(line as u32, column as u32)
     }
 
-    async fn analyze_dependencies(&self, file_id: FileId, file_version: FileVersion) {
-        let deps = self.files.get_files_dependant_on(file_id);
+    /// Indexes the given folders in a background task, so that handlers
+    /// of other requests don't wait for it.
+    pub fn spawn_indexing(&self, folders: Vec<Uri>) {
+        let backend = self.clone();
+
+        tokio::spawn(async move { backend.index_workspace_folders(folders).await });
+    }
+
+    /// Parses and analyzes all Amber files in the given folders, so that
+    /// references, symbols and diagnostics cover files that are not open.
+    #[tracing::instrument(skip_all)]
+    pub async fn index_workspace_folders(&self, folders: Vec<Uri>) {
+        let mut paths = vec![];
+
+        for folder in folders {
+            let folder_path = match folder.to_file_path() {
+                Some(path) => path.to_path_buf(),
+                None => continue,
+            };
+
+            let mut folder_paths =
+                find_amber_files(self.analyzer.files.fs.as_ref(), &folder_path).await;
+
+            if let Some(config) = self.load_project_config(&folder_path).await {
+                folder_paths.extend(config.entrypoints());
+            }
 
-        for (dep_file_id, dep_file_version) in deps {
-            if dep_file_id == file_id {
+            paths.extend(folder_paths.into_iter().map(|path| (path, folder.clone())));
+        }
+
+        paths.sort_by(|(path, _), (other, _)| path.cmp(other));
+        paths.dedup_by(|(path, _), (other, _)| path == other);
+
+        if paths.is_empty() {
+            return;
+        }
+
+        let token = self.begin_progress("Indexing Amber files").await;
+        let total = paths.len();
+
+        for (idx, (path, folder)) in paths.iter().enumerate() {
+            // The folder might have been removed from the workspace in the meantime
+            if !self.workspace_folders.contains(folder) {
                 continue;
             }
 
-            let new_version = self.files.get_latest_version(file_id);
-            if file_version != new_version {
-                return;
+            if let Some(uri) = Uri::from_file_path(path) {
+                if let Ok((file_id, _)) = self.open_document(&uri).await {
+                    self.analyzer.files.mark_as_indexed(file_id);
+                }
+            }
+
+            if let Some(token) = &token {
+                self.report_progress(
+                    token,
+                    format!("{}/{total}", idx + 1),
+                    ((idx + 1) * 100 / total) as u32,
+                )
+                .await;
             }
+        }
+
+        if let Some(token) = token {
+            self.end_progress(token).await;
+        }
+    }
 
-            self.analyze_document(dep_file_id, dep_file_version).await;
-            self.publish_issues(dep_file_id, dep_file_version).await;
+    /// Starts a work done progress, if the client supports it.
+    async fn begin_progress(&self, title: &str) -> Option<NumberOrString> {
+        if !self.work_done_progress.load(Ordering::Relaxed) {
+            return None;
         }
+
+        let token = NumberOrString::String(format!(
+            "amber-lsp/{}",
+            self.progress_token_counter.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        self.client
+            .send_request::<request::WorkDoneProgressCreate>(WorkDoneProgressCreateParams {
+                token: token.clone(),
+            })
+            .await
+            .ok()?;
+
+        self.send_progress(
+            &token,
+            WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                title: title.to_string(),
+                cancellable: Some(false),
+                message: None,
+                percentage: Some(0),
+            }),
+        )
+        .await;
+
+        Some(token)
+    }
+
+    async fn report_progress(&self, token: &NumberOrString, message: String, percentage: u32) {
+        self.send_progress(
+            token,
+            WorkDoneProgress::Report(WorkDoneProgressReport {
+                cancellable: Some(false),
+                message: Some(message),
+                percentage: Some(percentage),
+            }),
+        )
+        .await;
+    }
+
+    async fn end_progress(&self, token: NumberOrString) {
+        self.send_progress(
+            &token,
+            WorkDoneProgress::End(WorkDoneProgressEnd { message: None }),
+        )
+        .await;
+    }
+
+    async fn send_progress(&self, token: &NumberOrString, progress: WorkDoneProgress) {
+        self.client
+            .send_notification::<notification::Progress>(ProgressParams {
+                token: token.clone(),
+                value: ProgressParamsValue::WorkDone(progress),
+            })
+            .await;
+    }
+
+    /// Re-analyzes files, that failed to import a file, which might exist now.
+    async fn analyze_files_with_missing_imports(&self) {
+        for (file_id, version) in self.analyzer.analyze_files_with_missing_imports().await {
+            self.publish_issues(file_id, version).await;
+        }
+
+        self.handle_analysis_events().await;
     }
 
     async fn position_to_offset(
@@ -271,12 +627,12 @@ impl Backend {
         file: (FileId, FileVersion),
         position: Position,
     ) -> Option<usize> {
-        let rope = match self.files.document_map.get(&file) {
+        let rope = match self.analyzer.files.document_map.get(&file) {
             Some(document) => document.clone(),
             None => return None,
         };
 
-        if !self.files.is_file_analyzed(&file).await {
+        if !self.analyzer.files.is_file_analyzed(&file).await {
             return None;
         }
 
@@ -292,7 +648,7 @@ impl Backend {
         file_id: FileId,
         position: Position,
     ) -> Option<(SymbolInfo, usize)> {
-        let version = self.files.get_latest_version(file_id);
+        let version = self.analyzer.files.get_analyzed_version(file_id).await?;
         let file = (file_id, version);
 
         let offset = match self.position_to_offset(file, position).await {
@@ -300,7 +656,7 @@ impl Backend {
             None => return None,
         };
 
-        let symbol_table = match self.files.symbol_table.get(&file) {
+        let symbol_table = match self.analyzer.files.symbol_table.get(&file) {
             Some(symbol_table) => symbol_table.clone(),
             None => return None,
         };
@@ -314,9 +670,65 @@ impl Backend {
     }
 }
 
+/// Builds a `resultId` for a diagnostic report, that changes only when the diagnostics do.
+fn diagnostics_result_id(diagnostics: &[Diagnostic]) -> String {
+    let mut hasher = FxHasher::default();
+    serde_json::to_string(diagnostics)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+
+    format!("{:x}", hasher.finish())
+}
+
 impl LanguageServer for Backend {
-    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
-        save_resources(self).await;
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        let capabilities = &params.capabilities;
+
+        if let Some(trace) = params.trace {
+            self.trace.set_value(trace);
+        }
+
+        let pull_diagnostics = capabilities
+            .text_document
+            .as_ref()
+            .is_some_and(|text_document| text_document.diagnostic.is_some());
+        let diagnostics_refresh = capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.diagnostic.as_ref())
+            .and_then(|diagnostic| diagnostic.refresh_support)
+            .unwrap_or(false);
+
+        self.pull_diagnostics
+            .store(pull_diagnostics, Ordering::Relaxed);
+        self.diagnostics_refresh
+            .store(pull_diagnostics && diagnostics_refresh, Ordering::Relaxed);
+
+        let configuration_support = capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.configuration)
+            .unwrap_or(false);
+        self.configuration_support
+            .store(configuration_support, Ordering::Relaxed);
+
+        let work_done_progress = capabilities
+            .window
+            .as_ref()
+            .and_then(|window| window.work_done_progress)
+            .unwrap_or(false);
+        self.work_done_progress
+            .store(work_done_progress, Ordering::Relaxed);
+
+        #[allow(deprecated)]
+        let workspace_folders = match params.workspace_folders {
+            Some(folders) => folders.into_iter().map(|folder| folder.uri).collect(),
+            None => params.root_uri.into_iter().collect::<Vec<Uri>>(),
+        };
+
+        for folder in workspace_folders {
+            self.workspace_folders.insert(folder);
+        }
 
         Ok(InitializeResult {
             server_info: None,
@@ -336,11 +748,18 @@ impl LanguageServer for Backend {
                         SemanticTokensRegistrationOptions {
                             text_document_registration_options: {
                                 TextDocumentRegistrationOptions {
-                                    document_selector: Some(vec![DocumentFilter {
-                                        language: Some("amber".to_string()),
-                                        scheme: Some("file".to_string()),
-                                        pattern: None,
-                                    }]),
+                                    document_selector: Some(vec![
+                                        DocumentFilter {
+                                            language: Some("amber".to_string()),
+                                            scheme: Some("file".to_string()),
+                                            pattern: None,
+                                        },
+                                        DocumentFilter {
+                                            language: Some("amber".to_string()),
+                                            scheme: Some(STDLIB_SCHEME.to_string()),
+                                            pattern: None,
+                                        },
+                                    ]),
                                 }
                             },
                             semantic_tokens_options: SemanticTokensOptions {
@@ -359,6 +778,7 @@ impl LanguageServer for Backend {
                 definition_provider: Some(OneOf::Left(true)),
                 references_provider: Some(OneOf::Left(true)),
                 rename_provider: Some(OneOf::Left(true)),
+                document_formatting_provider: Some(OneOf::Left(true)),
                 hover_provider: Some(HoverProviderCapability::Simple(true)),
                 completion_provider: Some(CompletionOptions {
                     resolve_provider: Some(false),
@@ -374,6 +794,14 @@ impl LanguageServer for Backend {
                     retrigger_characters: Some(vec![",".to_string()]), // Retrigger on ','
                     ..Default::default()
                 }),
+                diagnostic_provider: Some(DiagnosticServerCapabilities::Options(
+                    DiagnosticOptions {
+                        identifier: Some("amber".to_string()),
+                        inter_file_dependencies: true,
+                        workspace_diagnostics: true,
+                        work_done_progress_options: WorkDoneProgressOptions::default(),
+                    },
+                )),
                 ..ServerCapabilities::default()
             },
         })
@@ -381,25 +809,55 @@ impl LanguageServer for Backend {
 
     async fn initialized(&self, _: InitializedParams) {
         let options = serde_json::to_value(DidChangeWatchedFilesRegistrationOptions {
-            watchers: vec![FileSystemWatcher {
-                glob_pattern: GlobPattern::String("**/*.ab".to_string()),
-                kind: None, // Default is 7 - Create | Change | Delete
-            }],
+            watchers: vec![
+                FileSystemWatcher {
+                    glob_pattern: GlobPattern::String("**/*.ab".to_string()),
+                    kind: None, // Default is 7 - Create | Change | Delete
+                },
+                FileSystemWatcher {
+                    glob_pattern: GlobPattern::String(
+                        "**/{amber.toml,.amber-lsp.toml}".to_string(),
+                    ),
+                    kind: None,
+                },
+            ],
         })
         .unwrap();
 
         let _ = self
             .client
-            .register_capability(vec![Registration {
-                id: "did_change_watched_files".to_string(),
-                method: "workspace/didChangeWatchedFiles".to_string(),
-                register_options: Some(options),
-            }])
+            .register_capability(vec![
+                Registration {
+                    id: "did_change_watched_files".to_string(),
+                    method: "workspace/didChangeWatchedFiles".to_string(),
+                    register_options: Some(options),
+                },
+                Registration {
+                    id: "did_change_configuration".to_string(),
+                    method: "workspace/didChangeConfiguration".to_string(),
+                    register_options: None,
+                },
+            ])
             .await;
 
+        self.pull_config().await;
+
+        self.load_workspace_config().await;
+        if self.analyzer.update_amber_version().await {
+            self.reanalyze_all().await;
+        }
+
         self.client
             .log_message(MessageType::INFO, "server initialized!")
             .await;
+
+        let folders = self
+            .workspace_folders
+            .iter()
+            .map(|folder| folder.clone())
+            .collect();
+
+        self.spawn_indexing(folders);
     }
 
     async fn shutdown(&self) -> Result<()> {
@@ -410,9 +868,13 @@ impl LanguageServer for Backend {
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
         let version = FileVersion(params.text_document.version);
 
-        let file_id = self.files.insert(params.text_document.uri, version);
+        let file_id = self
+            .analyzer
+            .files
+            .insert(params.text_document.uri, version);
+        self.analyzer.files.mark_as_open(file_id);
 
-        self.files.document_map.insert(
+        self.analyzer.files.document_map.insert(
             (file_id, version),
             Rope::from_str(&params.text_document.text),
         );
@@ -427,7 +889,7 @@ impl LanguageServer for Backend {
         let uri = params.text_document.uri.to_string();
         let new_version = FileVersion(params.text_document.version);
 
-        let file_id = match self.files.get(&params.text_document.uri) {
+        let file_id = match self.analyzer.files.get(&params.text_document.uri) {
             Some(file_id) => file_id,
             None => {
                 return self
@@ -437,8 +899,6 @@ impl LanguageServer for Backend {
             }
         };
 
-        self.files.add_new_file_version(file_id, new_version);
-
         if params
             .content_changes
             .iter()
@@ -450,17 +910,16 @@ impl LanguageServer for Backend {
                 .await;
         }
 
-        if let Some(change) = params
+        let document = if let Some(change) = params
             .content_changes
             .iter()
-            .find(|change| change.range.is_none() && change.range_length.is_none())
+            .find(|change| change.range.is_none())
         {
-            self.files
-                .document_map
-                .insert((file_id, new_version), Rope::from_str(&change.text));
+            Rope::from_str(&change.text)
         } else {
-            let mut document = match self.files.get_document_latest_version(file_id) {
-                Some((document, _)) => document.clone(),
+            // Edits with ranges apply to the previous version of the document
+            let mut document = match self.analyzer.files.get_document_latest_version(file_id) {
+                Some((document, _)) => document,
                 None => {
                     return self
                         .client
@@ -469,39 +928,159 @@ impl LanguageServer for Backend {
                 }
             };
 
-            params
-                .content_changes
-                .iter()
-                .filter(|change| change.range.is_some())
-                .for_each(|change| {
-                    let range = change.range.as_ref().unwrap();
-                    let start = document.line_to_char(range.start.line as usize)
-                        + range.start.character as usize;
-                    let end = document.line_to_char(range.end.line as usize)
-                        + range.end.character as usize;
-
-                    document.remove(start..end);
-                    document.insert(start, &change.text);
-                });
+            for change in params.content_changes.iter() {
+                let Some(range) = change.range else {
+                    continue;
+                };
 
-            self.files
-                .document_map
-                .insert((file_id, new_version), document);
-        }
+                let offset = |position: Position| {
+                    document
+                        .try_line_to_char(position.line as usize)
+                        .ok()
+                        .map(|line_start| line_start + position.character as usize)
+                };
+
+                let (start, end) = match (offset(range.start), offset(range.end)) {
+                    (Some(start), Some(end)) if start <= end && end <= document.len_chars() => {
+                        (start, end)
+                    }
+                    _ => {
+                        tracing::error!("Change at {range:?} is out of the document {uri}");
+                        return self
+                            .client
+                            .log_message(
+                                MessageType::ERROR,
+                                format!("change of {uri} is out of the document"),
+                            )
+                            .await;
+                    }
+                };
+
+                document.remove(start..end);
+                document.insert(start, &change.text);
+            }
+
+            document
+        };
+
+        // Analysis of the previous versions is cancelled, once it notices the new one
+        self.analyzer
+            .files
+            .add_new_file_version(file_id, new_version);
+        self.analyzer
+            .files
+            .document_map
+            .insert((file_id, new_version), document);
 
         self.analyze_document(file_id, new_version).await;
 
-        self.publish_issues(file_id, new_version).await;
+        self.publish_issues_after_delay(file_id, new_version);
     }
 
-    async fn did_change_watched_files(&self, _: DidChangeWatchedFilesParams) {
-        // TODO: Invalidate the file and re-analyze dependencies
+    #[tracing::instrument(skip_all)]
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        let mut file_created = false;
+        let mut project_config_changed = false;
+
+        for change in params.changes {
+            if let Some(path) = change.uri.to_file_path() {
+                if is_project_config_file(&path) {
+                    self.analyzer.project_configs.remove(&path);
+                    project_config_changed = true;
+
+                    if change.typ == FileChangeType::DELETED {
+                        self.client
+                            .publish_diagnostics(change.uri.clone(), vec![], None)
+                            .await;
+                    }
+
+                    continue;
+                }
+            }
+
+            if change.typ == FileChangeType::CREATED {
+                file_created = true;
+            }
+
+            let file_id = match self.analyzer.files.get(&change.uri) {
+                Some(file_id) => file_id,
+                None => continue,
+            };
+
+            // Contents of open files are managed by the editor
+            if self.analyzer.files.is_open(file_id) {
+                continue;
+            }
+
+            let version = self.analyzer.files.get_latest_version(file_id);
+            self.analyzer.files.unload(file_id);
+
+            if change.typ == FileChangeType::DELETED {
+                self.analyze_dependencies(file_id, version).await;
+                continue;
+            }
+
+            let text = match change.uri.to_file_path() {
+                Some(path) => self.analyzer.files.fs.read(&path).await,
+                None => continue,
+            };
+
+            let text = match text {
+                Ok(text) => text,
+                Err(_) => {
+                    self.analyze_dependencies(file_id, version).await;
+                    continue;
+                }
+            };
+
+            let new_version = FileVersion(version.0 + 1);
+            self.analyzer
+                .files
+                .add_new_file_version(file_id, new_version);
+            self.analyzer
+                .files
+                .document_map
+                .insert((file_id, new_version), Rope::from_str(&text));
+
+            self.analyze_document(file_id, new_version).await;
+        }
+
+        if project_config_changed {
+            self.load_workspace_config().await;
+            self.analyzer.update_amber_version().await;
+            self.reanalyze_all().await;
+        } else if file_created {
+            self.analyze_files_with_missing_imports().await;
+        }
     }
 
-    async fn did_close(&self, _: DidCloseTextDocumentParams) {
-        self.client
-            .log_message(MessageType::INFO, "document closed!")
-            .await;
+    #[tracing::instrument(skip_all)]
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        let uri = params.text_document.uri;
+
+        let file_id = match self.analyzer.files.get(&uri) {
+            Some(file_id) => file_id,
+            None => return,
+        };
+
+        self.analyzer.files.mark_as_closed(file_id);
+        self.cancel_pending_publish(file_id);
+
+        if !self.pull_diagnostics.load(Ordering::Relaxed) {
+            self.publish_diagnostics(&file_id, vec![], None).await;
+        }
+
+        // Files importing the closed document should see its contents on the disk,
+        // not the unsaved changes from the editor.
+        if self.analyzer.files.get_reachable_files().contains(&file_id) {
+            self.analyzer.files.remove_file(file_id);
+
+            if self.open_document(&uri).await.is_err() {
+                self.analyze_dependencies(file_id, DEFAULT_VERSION).await;
+            }
+        }
+
+        self.analyzer.files.collect_garbage();
     }
 
     async fn did_save(&self, _: DidSaveTextDocumentParams) {
@@ -515,23 +1094,29 @@ impl LanguageServer for Backend {
         &self,
         params: SemanticTokensParams,
     ) -> Result<Option<SemanticTokensResult>> {
-        let file_id = match self.files.get(&params.text_document.uri) {
+        let file_id = match self.analyzer.files.get(&params.text_document.uri) {
             Some(file_id) => file_id,
             None => {
                 return Ok(None);
             }
         };
 
-        let (rope, file_version) = match self.files.get_document_latest_version(file_id) {
+        let (rope, file_version) = match self
+            .analyzer
+            .files
+            .get_document_analyzed_version(file_id)
+            .await
+        {
             Some(document) => document,
             None => return Ok(None),
         };
 
-        if !self.files.is_file_analyzed(&(file_id, file_version)).await {
-            return Ok(None);
-        }
-
-        let semantic_tokens = match self.files.semantic_token_map.get(&(file_id, file_version)) {
+        let semantic_tokens = match self
+            .analyzer
+            .files
+            .semantic_token_map
+            .get(&(file_id, file_version))
+        {
             Some(tokens) => tokens,
             None => {
                 return Ok(None);
@@ -592,25 +1177,31 @@ impl LanguageServer for Backend {
         &self,
         params: SemanticTokensRangeParams,
     ) -> Result<Option<SemanticTokensRangeResult>> {
-        let file_id = match self.files.get(&params.text_document.uri) {
+        let file_id = match self.analyzer.files.get(&params.text_document.uri) {
             Some(file_id) => file_id,
             None => {
                 return Ok(None);
             }
         };
 
-        let (rope, file_version) = match self.files.get_document_latest_version(file_id) {
+        let (rope, file_version) = match self
+            .analyzer
+            .files
+            .get_document_analyzed_version(file_id)
+            .await
+        {
             Some(document) => document,
             None => return Ok(None),
         };
 
-        if !self.files.is_file_analyzed(&(file_id, file_version)).await {
-            return Ok(None);
-        }
-
         let requested_range = params.range;
 
-        let semantic_tokens = match self.files.semantic_token_map.get(&(file_id, file_version)) {
+        let semantic_tokens = match self
+            .analyzer
+            .files
+            .semantic_token_map
+            .get(&(file_id, file_version))
+        {
             Some(tokens) => tokens,
             None => {
                 return Ok(None);
@@ -672,20 +1263,21 @@ impl LanguageServer for Backend {
     ) -> Result<Option<GotoDefinitionResponse>> {
         let definition = {
             let uri = params.text_document_position_params.text_document.uri;
-            let file_id = match self.files.get(&uri) {
+            let file_id = match self.analyzer.files.get(&uri) {
                 Some(file_id) => file_id,
                 None => return Ok(None),
             };
 
-            let (rope, version) = match self.files.get_document_latest_version(file_id) {
+            let (rope, version) = match self
+                .analyzer
+                .files
+                .get_document_analyzed_version(file_id)
+                .await
+            {
                 Some(document) => document,
                 None => return Ok(None),
             };
 
-            if !self.files.is_file_analyzed(&(file_id, version)).await {
-                return Ok(None);
-            }
-
             let position = params.text_document_position_params.position;
             let char = rope
                 .try_line_to_char(position.line as usize)
@@ -693,7 +1285,7 @@ impl LanguageServer for Backend {
                 .unwrap_or(rope.len_chars());
             let offset = char + position.character as usize;
 
-            let symbol_table = match self.files.symbol_table.get(&(file_id, version)) {
+            let symbol_table = match self.analyzer.files.symbol_table.get(&(file_id, version)) {
                 Some(symbol_table) => symbol_table.clone(),
                 None => return Ok(None),
             };
@@ -712,20 +1304,30 @@ impl LanguageServer for Backend {
             let response = match symbol_table.definitions.get(&symbol_info.name) {
                 Some(definitions) => match definitions.get(&offset) {
                     Some(definition) => {
-                        let definition_file_rope =
-                            match self.files.get_document_latest_version(definition.file.0) {
-                                Some((document, _)) => document.clone(),
-                                None => {
-                                    return Ok(None);
-                                }
-                            };
+                        let definition_file_rope = match self
+                            .analyzer
+                            .files
+                            .document_map
+                            .get(&definition.file)
+                            .map(|document| document.clone())
+                            .or_else(|| {
+                                self.analyzer
+                                    .files
+                                    .get_document_latest_version(definition.file.0)
+                                    .map(|(document, _)| document)
+                            }) {
+                            Some(document) => document,
+                            None => {
+                                return Ok(None);
+                            }
+                        };
 
                         let start_position =
                             self.offset_to_position(definition.start, &definition_file_rope);
                         let end_position =
                             self.offset_to_position(definition.end, &definition_file_rope);
 
-                        let file_uri = self.files.lookup(&definition.file.0);
+                        let file_uri = self.analyzer.files.lookup(&definition.file.0);
 
                         match symbol_info.symbol_type {
                             SymbolType::ImportPath => {
@@ -779,20 +1381,137 @@ impl LanguageServer for Backend {
         Ok(definition)
     }
 
-    async fn did_change_configuration(&self, _: DidChangeConfigurationParams) {
-        self.client
-            .log_message(MessageType::INFO, "configuration changed!")
-            .await;
+    #[tracing::instrument(skip_all)]
+    async fn diagnostic(
+        &self,
+        params: DocumentDiagnosticParams,
+    ) -> Result<DocumentDiagnosticReportResult> {
+        let (_, report) = self
+            .diagnostic_report(
+                &params.text_document.uri,
+                params.previous_result_id.as_ref(),
+            )
+            .await?;
+
+        Ok(DocumentDiagnosticReportResult::Report(report))
     }
 
-    async fn did_change_workspace_folders(&self, _: DidChangeWorkspaceFoldersParams) {
-        self.client
-            .log_message(MessageType::INFO, "workspace folders changed!")
-            .await;
+    #[tracing::instrument(skip_all)]
+    async fn workspace_diagnostic(
+        &self,
+        params: WorkspaceDiagnosticParams,
+    ) -> Result<WorkspaceDiagnosticReportResult> {
+        let previous_result_ids = params
+            .previous_result_ids
+            .into_iter()
+            .map(|result_id| (result_id.uri, result_id.value))
+            .collect::<HashMap<Uri, String>>();
+
+        let folders = self
+            .workspace_folders
+            .iter()
+            .map(|folder| folder.clone())
+            .collect::<Vec<Uri>>();
+
+        let mut items = vec![];
+
+        for folder in folders {
+            let folder_path = match folder.to_file_path() {
+                Some(path) => path.to_path_buf(),
+                None => continue,
+            };
+
+            for path in find_amber_files(self.analyzer.files.fs.as_ref(), &folder_path).await {
+                let uri = match Uri::from_file_path(&path) {
+                    Some(uri) => uri,
+                    None => continue,
+                };
+
+                let (version, report) = match self
+                    .diagnostic_report(&uri, previous_result_ids.get(&uri))
+                    .await
+                {
+                    Ok(report) => report,
+                    Err(err) => {
+                        tracing::warn!("Could not report diagnostics of {}: {}", uri.as_str(), err);
+                        continue;
+                    }
+                };
+
+                items.push(match report {
+                    DocumentDiagnosticReport::Full(report) => {
+                        WorkspaceDocumentDiagnosticReport::Full(
+                            WorkspaceFullDocumentDiagnosticReport {
+                                uri,
+                                version: Some(version.0 as i64),
+                                full_document_diagnostic_report: report
+                                    .full_document_diagnostic_report,
+                            },
+                        )
+                    }
+                    DocumentDiagnosticReport::Unchanged(report) => {
+                        WorkspaceDocumentDiagnosticReport::Unchanged(
+                            WorkspaceUnchangedDocumentDiagnosticReport {
+                                uri,
+                                version: Some(version.0 as i64),
+                                unchanged_document_diagnostic_report: report
+                                    .unchanged_document_diagnostic_report,
+                            },
+                        )
+                    }
+                });
+            }
+        }
+
+        // Files read only for the report are dropped, unless they are indexed or imported
+        self.analyzer.files.collect_garbage();
+
+        Ok(WorkspaceDiagnosticReportResult::Report(
+            WorkspaceDiagnosticReport { items },
+        ))
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        if self.configuration_support.load(Ordering::Relaxed) {
+            return self.pull_config().await;
+        }
+
+        // Clients without `workspace/configuration` push the settings instead
+        let settings = match params.settings.get("amber-lsp") {
+            Some(settings) => settings.clone(),
+            None => params.settings,
+        };
+
+        self.apply_config(ServerConfig::from_value(settings)).await;
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
+        for folder in params.event.removed {
+            self.workspace_folders.remove(&folder.uri);
+            self.analyzer.files.remove_from_index(&folder.uri);
+        }
+
+        self.analyzer.files.collect_garbage();
+
+        let added = params
+            .event
+            .added
+            .into_iter()
+            .map(|folder| folder.uri)
+            .collect::<Vec<_>>();
+
+        for folder in added.iter() {
+            self.workspace_folders.insert(folder.clone());
+        }
+
+        self.spawn_indexing(added);
     }
 
     async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
         let file_id = match self
+            .analyzer
             .files
             .get(&params.text_document_position_params.text_document.uri)
         {
@@ -802,32 +1521,58 @@ impl LanguageServer for Backend {
             }
         };
 
-        let version = self.files.get_latest_version(file_id);
-
-        if !self.files.is_file_analyzed(&(file_id, version)).await {
-            return Ok(None);
-        }
+        let version = match self.analyzer.files.get_analyzed_version(file_id).await {
+            Some(version) => version,
+            None => return Ok(None),
+        };
 
         let position = params.text_document_position_params.position;
 
-        let symbol_info = match self.get_symbol_at_position(file_id, position).await {
-            Some((symbol_info, _)) if !symbol_info.undefined => symbol_info,
+        let (symbol_info, offset) = match self.get_symbol_at_position(file_id, position).await {
+            Some((symbol_info, offset)) if !symbol_info.undefined => (symbol_info, offset),
             _ => {
                 return Ok(None);
             }
         };
 
+        // Files of the standard library, that the symbol comes from
+        let source_uri = match symbol_info.symbol_type {
+            SymbolType::ImportPath => {
+                map_import_path(
+                    &params.text_document_position_params.text_document.uri,
+                    &symbol_info.name,
+                    &self.analyzer,
+                    &self.analyzer.get_file_amber_version(file_id),
+                )
+                .await
+            }
+            _ => get_symbol_definition_location(
+                &self.analyzer.files,
+                &symbol_info.name,
+                &(file_id, version),
+                offset,
+            )
+            .map(|location| self.analyzer.files.lookup(&location.file.0)),
+        };
+        let stdlib_source = source_uri.and_then(|uri| get_file_stdlib_source(&self.analyzer, &uri));
+
         Ok(Some(Hover {
             contents: HoverContents::Markup(MarkupContent {
                 kind: MarkupKind::Markdown,
                 value: format!(
-                    "```amber\n{}\n```{}",
-                    symbol_info.to_string(&self.files.generic_types),
+                    "```amber\n{}\n```{}{}",
+                    symbol_info.to_string(&self.analyzer.files.generic_types),
                     match symbol_info.symbol_type {
-                        SymbolType::Function(FunctionSymbol { ref docs, .. }) if docs.is_some() =>
-                            format!("\n{}", docs.clone().unwrap()),
+                        SymbolType::Function(FunctionSymbol {
+                            docs: Some(ref docs),
+                            ..
+                        }) => format!("\n{docs}"),
                         _ => "".to_string(),
                     },
+                    match stdlib_source {
+                        Some(source) => format!("\n\n---\n{source}"),
+                        None => "".to_string(),
+                    },
                 ),
             }),
             range: Some(Range {
@@ -847,18 +1592,17 @@ impl LanguageServer for Backend {
     async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
         let uri = params.text_document_position.text_document.uri;
 
-        let file_id = match self.files.get(&uri) {
+        let file_id = match self.analyzer.files.get(&uri) {
             Some(file_id) => file_id,
             None => {
                 return Ok(None);
             }
         };
 
-        let version = self.files.get_latest_version(file_id);
-
-        if !self.files.is_file_analyzed(&(file_id, version)).await {
-            return Ok(None);
-        }
+        let version = match self.analyzer.files.get_analyzed_version(file_id).await {
+            Some(version) => version,
+            None => return Ok(None),
+        };
 
         let position = params.text_document_position.position;
 
@@ -869,14 +1613,19 @@ impl LanguageServer for Backend {
             }
         };
 
-        let symbol_table = match self.files.symbol_table.get(&(file_id, version)) {
+        let symbol_table = match self.analyzer.files.symbol_table.get(&(file_id, version)) {
             Some(symbol_table) => symbol_table.clone(),
             None => return Ok(None),
         };
 
         let completions = match symbol_info.symbol_type {
             SymbolType::ImportPath => {
-                let stdlib_paths = find_in_stdlib(self, &symbol_info.name).await;
+                let stdlib_paths = find_in_stdlib(
+                    &self.analyzer,
+                    &symbol_info.name,
+                    &self.analyzer.get_file_amber_version(file_id),
+                )
+                .await;
 
                 if stdlib_paths.contains(&symbol_info.name) {
                     return Ok(None);
@@ -891,8 +1640,15 @@ impl LanguageServer for Backend {
                     })
                     .collect();
 
-                let file_path = uri.to_file_path().unwrap().canonicalize().unwrap();
-                let mut searched_path = file_path.parent().unwrap().to_path_buf();
+                // Files of the bundled standard library can only import the standard library
+                let file_path = match uri.to_file_path().and_then(|path| path.canonicalize().ok()) {
+                    Some(path) => path,
+                    None => return Ok(Some(CompletionResponse::Array(completions))),
+                };
+                let mut searched_path = match file_path.parent() {
+                    Some(dir) => dir.to_path_buf(),
+                    None => return Ok(Some(CompletionResponse::Array(completions))),
+                };
                 searched_path.push(symbol_info.name.clone());
 
                 if let Ok(path) = searched_path.canonicalize() {
@@ -904,15 +1660,14 @@ impl LanguageServer for Backend {
                 let dir_to_search = if symbol_info.name.ends_with("/") || searched_path.is_dir() {
                     searched_path.as_path()
                 } else {
-                    searched_path.parent().unwrap()
+                    searched_path.parent().unwrap_or(searched_path.as_path())
                 };
 
-                for entry_path in self.files.fs.read_dir(dir_to_search).await {
-                    let entry_name = entry_path
-                        .file_name()
-                        .unwrap()
-                        .to_string_lossy()
-                        .to_string();
+                for entry_path in self.analyzer.files.fs.read_dir(dir_to_search).await {
+                    let entry_name = match entry_path.file_name() {
+                        Some(name) => name.to_string_lossy().to_string(),
+                        None => continue,
+                    };
 
                     let entry_kind = if entry_path.is_symlink() {
                         let target = entry_path.read_link();
@@ -927,12 +1682,15 @@ impl LanguageServer for Backend {
                         CompletionItemKind::FILE
                     };
 
-                    let absolute_entry_path = entry_path.canonicalize().unwrap();
+                    // The entry might have been removed, or be a broken link
+                    let absolute_entry_path = match entry_path.canonicalize() {
+                        Ok(path) => path,
+                        Err(_) => continue,
+                    };
 
                     if absolute_entry_path != file_path
                         && (entry_path.is_dir()
-                            || entry_path.extension().map(|ext| ext.to_str().unwrap())
-                                == Some("ab"))
+                            || entry_path.extension().is_some_and(|ext| ext == "ab"))
                     {
                         completions.push(CompletionItem {
                             label: entry_name.clone(),
@@ -941,9 +1699,11 @@ impl LanguageServer for Backend {
                                 range: Range {
                                     start: Position {
                                         line: position.line,
-                                        character: position.character
-                                            - symbol_info.name.split("/").last().unwrap_or("").len()
-                                                as u32, // Move back by prefix length
+                                        // Move back by prefix length
+                                        character: position.character.saturating_sub(
+                                            symbol_info.name.split("/").last().unwrap_or("").len()
+                                                as u32,
+                                        ),
                                     },
                                     end: Position {
                                         line: position.line,
@@ -977,7 +1737,7 @@ impl LanguageServer for Backend {
                             }
 
                             get_symbol_definition_info(
-                                &self.files,
+                                &self.analyzer.files,
                                 name,
                                 &location.file,
                                 usize::MAX,
@@ -989,7 +1749,7 @@ impl LanguageServer for Backend {
                         .iter()
                         .filter_map(|(name, _)| {
                             get_symbol_definition_info(
-                                &self.files,
+                                &self.analyzer.files,
                                 name,
                                 &(file_id, version),
                                 symbol_info.span.start,
@@ -1022,7 +1782,9 @@ impl LanguageServer for Backend {
                                     ))
                                 },
                                 kind: Some(CompletionItemKind::METHOD),
-                                detail: Some(symbol_info.to_string(&self.files.generic_types)),
+                                detail: Some(
+                                    symbol_info.to_string(&self.analyzer.files.generic_types),
+                                ),
                                 insert_text_format: Some(InsertTextFormat::SNIPPET),
                                 command: Some(Command {
                                     title: "triggerParameterHints".to_string(),
@@ -1042,7 +1804,9 @@ impl LanguageServer for Backend {
                                 }),
                                 label_details: Some(CompletionItemLabelDetails {
                                     description: Some(
-                                        symbol_info.data_type.to_string(&self.files.generic_types),
+                                        symbol_info
+                                            .data_type
+                                            .to_string(&self.analyzer.files.generic_types),
                                     ),
                                     detail: None,
                                 }),
@@ -1060,8 +1824,47 @@ impl LanguageServer for Backend {
         Ok(Some(CompletionResponse::Array(completions)))
     }
 
+    async fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+        let file_id = match self.analyzer.files.get(&params.text_document.uri) {
+            Some(file_id) => file_id,
+            None => return Ok(None),
+        };
+
+        let (document, _) = match self.analyzer.files.get_document_latest_version(file_id) {
+            Some(document) => document,
+            None => return Ok(None),
+        };
+
+        let indent = if params.options.insert_spaces {
+            " ".repeat(params.options.tab_size as usize)
+        } else {
+            "\t".to_string()
+        };
+
+        let text = document.to_string();
+        let formatted = format_document(&text, &indent);
+
+        if formatted == text {
+            return Ok(Some(vec![]));
+        }
+
+        let last_line = document.len_lines() - 1;
+
+        Ok(Some(vec![TextEdit {
+            range: Range::new(
+                Position::new(0, 0),
+                Position::new(
+                    last_line as u32,
+                    document.line(last_line).len_chars() as u32,
+                ),
+            ),
+            new_text: formatted,
+        }]))
+    }
+
     async fn signature_help(&self, params: SignatureHelpParams) -> Result<Option<SignatureHelp>> {
         let file_id = match self
+            .analyzer
             .files
             .get(&params.text_document_position_params.text_document.uri)
         {
@@ -1071,13 +1874,12 @@ impl LanguageServer for Backend {
             }
         };
 
-        let version = self.files.get_latest_version(file_id);
-
-        if !self.files.is_file_analyzed(&(file_id, version)).await {
-            return Ok(None);
-        }
+        let version = match self.analyzer.files.get_analyzed_version(file_id).await {
+            Some(version) => version,
+            None => return Ok(None),
+        };
 
-        let symbol_table = match self.files.symbol_table.get(&(file_id, version)) {
+        let symbol_table = match self.analyzer.files.symbol_table.get(&(file_id, version)) {
             Some(symbol_table) => symbol_table.clone(),
             None => return Ok(None),
         };
@@ -1111,7 +1913,7 @@ impl LanguageServer for Backend {
 
                 Ok(Some(SignatureHelp {
                     signatures: vec![SignatureInformation {
-                        label: symbol_info.to_string(&self.files.generic_types),
+                        label: symbol_info.to_string(&self.analyzer.files.generic_types),
                         documentation: None,
                         parameters: Some(
                             arguments
@@ -1120,7 +1922,7 @@ impl LanguageServer for Backend {
                                     label: ParameterLabel::Simple(format!(
                                         "{}: {}",
                                         arg.name,
-                                        arg.data_type.to_string(&self.files.generic_types)
+                                        arg.data_type.to_string(&self.analyzer.files.generic_types)
                                     )),
                                     documentation: None,
                                 })
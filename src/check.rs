@@ -0,0 +1,333 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use clap::ValueEnum;
+use ropey::Rope;
+use serde_json::{json, Value};
+
+use crate::{
+    analyzer::{Analyzer, FileDiagnostic},
+    diagnostics::{Diagnostic, Severity},
+    fs::find_amber_files,
+    grammar::Span,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Diagnostics with source snippets, for people.
+    Text,
+    Json,
+    /// SARIF 2.1.0, for code scanning dashboards.
+    Sarif,
+}
+
+/// Diagnostics of a checked file.
+#[derive(Debug, Clone)]
+pub struct CheckedFile {
+    /// Path of the file, as given on the command line.
+    pub path: PathBuf,
+    pub document: Rope,
+    pub diagnostics: Vec<FileDiagnostic>,
+}
+
+/// Analyzes the files, and all Amber files in the directories.
+pub async fn check_paths(analyzer: &Arc<Analyzer>, paths: &[PathBuf]) -> Vec<CheckedFile> {
+    let mut files = vec![];
+
+    for path in paths {
+        if analyzer.files.fs.is_dir(path).await {
+            files.extend(find_amber_files(analyzer.files.fs.as_ref(), path).await);
+        } else {
+            files.push(path.clone());
+        }
+    }
+
+    let mut checked_files = vec![];
+
+    for path in files {
+        let absolute_path = if path.is_absolute() {
+            path.clone()
+        } else {
+            env::current_dir()
+                .map(|dir| dir.join(&path))
+                .unwrap_or(path.clone())
+        };
+
+        let checked_file = match analyzer.analyze_path(&absolute_path).await {
+            Ok((file_id, version)) => CheckedFile {
+                path,
+                document: analyzer
+                    .files
+                    .document_map
+                    .get(&(file_id, version))
+                    .map(|document| document.clone())
+                    .unwrap_or_default(),
+                diagnostics: analyzer.get_diagnostics(file_id, version),
+            },
+            Err(err) => CheckedFile {
+                path,
+                document: Rope::new(),
+                diagnostics: vec![FileDiagnostic {
+                    diagnostic: Diagnostic::Error(format!("Could not read the file: {err}")),
+                    severity: Severity::Error,
+                    span: Span::new(0, 0),
+                }],
+            },
+        };
+
+        checked_files.push(checked_file);
+    }
+
+    checked_files
+}
+
+pub fn has_errors(files: &[CheckedFile]) -> bool {
+    files.iter().any(|file| {
+        file.diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error)
+    })
+}
+
+pub fn format_report(files: &[CheckedFile], format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Text => format_text(files),
+        OutputFormat::Json => serde_json::to_string_pretty(&format_json(files)).unwrap(),
+        OutputFormat::Sarif => serde_json::to_string_pretty(&format_sarif(files)).unwrap(),
+    }
+}
+
+fn severity_name(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Information => "info",
+        Severity::Hint => "hint",
+    }
+}
+
+/// Returns the zero-based line and column of a character offset.
+fn line_and_column(document: &Rope, offset: usize) -> (usize, usize) {
+    let offset = offset.min(document.len_chars());
+    let line = document.char_to_line(offset);
+
+    (line, offset - document.line_to_char(line))
+}
+
+fn display_path(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+/// Formats diagnostics like the Rust compiler does:
+///
+/// ```text
+/// error[type-mismatch]: Expected type `Num`, found type `Text`
+///  --> main.ab:3:5
+///   |
+/// 3 | foo(a)
+///   |     ^
+/// ```
+pub fn format_text(files: &[CheckedFile]) -> String {
+    let mut output = String::new();
+    let mut errors = 0;
+    let mut warnings = 0;
+
+    for file in files {
+        for FileDiagnostic {
+            diagnostic,
+            severity,
+            span,
+        } in file.diagnostics.iter()
+        {
+            match severity {
+                Severity::Error => errors += 1,
+                Severity::Warning => warnings += 1,
+                _ => {}
+            }
+
+            let (line, column) = line_and_column(&file.document, span.start);
+            let (end_line, end_column) = line_and_column(&file.document, span.end);
+
+            let _ = match diagnostic.code() {
+                Some(code) => writeln!(
+                    output,
+                    "{}[{code}]: {}",
+                    severity_name(*severity),
+                    diagnostic.message()
+                ),
+                None => writeln!(
+                    output,
+                    "{}: {}",
+                    severity_name(*severity),
+                    diagnostic.message()
+                ),
+            };
+
+            let line_number = (line + 1).to_string();
+            let gutter = " ".repeat(line_number.len());
+
+            let _ = writeln!(
+                output,
+                "{gutter}--> {}:{}:{}",
+                display_path(&file.path),
+                line + 1,
+                column + 1
+            );
+
+            if let Some(source_line) = file.document.get_line(line) {
+                let source_line = source_line.to_string();
+                let source_line = source_line.trim_end_matches(['\r', '\n']);
+
+                let underline_length = if end_line == line {
+                    end_column.saturating_sub(column).max(1)
+                } else {
+                    source_line.chars().count().saturating_sub(column).max(1)
+                };
+
+                let _ = writeln!(output, "{gutter} |");
+                let _ = writeln!(output, "{line_number} | {source_line}");
+                let _ = writeln!(
+                    output,
+                    "{gutter} | {}{}",
+                    " ".repeat(column),
+                    "^".repeat(underline_length)
+                );
+            }
+
+            for related in diagnostic.related() {
+                let _ = writeln!(output, "{gutter} = note: {}", related.message);
+            }
+
+            output.push('\n');
+        }
+    }
+
+    let _ = writeln!(
+        output,
+        "Checked {} {}: {errors} {}, {warnings} {}",
+        files.len(),
+        if files.len() == 1 { "file" } else { "files" },
+        if errors == 1 { "error" } else { "errors" },
+        if warnings == 1 { "warning" } else { "warnings" },
+    );
+
+    output
+}
+
+fn json_range(document: &Rope, span: &Span) -> Value {
+    let (start_line, start_column) = line_and_column(document, span.start);
+    let (end_line, end_column) = line_and_column(document, span.end);
+
+    json!({
+        "start": { "line": start_line + 1, "column": start_column + 1 },
+        "end": { "line": end_line + 1, "column": end_column + 1 },
+    })
+}
+
+/// Formats diagnostics as a JSON object, with one-based lines and columns.
+pub fn format_json(files: &[CheckedFile]) -> Value {
+    let diagnostics = files
+        .iter()
+        .flat_map(|file| {
+            file.diagnostics.iter().map(|file_diagnostic| {
+                json!({
+                    "path": display_path(&file.path),
+                    "severity": severity_name(file_diagnostic.severity),
+                    "code": file_diagnostic.diagnostic.code(),
+                    "message": file_diagnostic.diagnostic.message(),
+                    "range": json_range(&file.document, &file_diagnostic.span),
+                    "data": file_diagnostic.diagnostic.data(),
+                })
+            })
+        })
+        .collect::<Vec<Value>>();
+
+    let count = |severity: Severity| {
+        files
+            .iter()
+            .flat_map(|file| file.diagnostics.iter())
+            .filter(|diagnostic| diagnostic.severity == severity)
+            .count()
+    };
+
+    json!({
+        "diagnostics": diagnostics,
+        "summary": {
+            "files": files.len(),
+            "errors": count(Severity::Error),
+            "warnings": count(Severity::Warning),
+        },
+    })
+}
+
+/// Formats diagnostics as a SARIF 2.1.0 log.
+pub fn format_sarif(files: &[CheckedFile]) -> Value {
+    let mut rules = BTreeMap::new();
+    let mut results = vec![];
+
+    for file in files {
+        for FileDiagnostic {
+            diagnostic,
+            severity,
+            span,
+        } in file.diagnostics.iter()
+        {
+            let (start_line, start_column) = line_and_column(&file.document, span.start);
+            let (end_line, end_column) = line_and_column(&file.document, span.end);
+
+            let mut result = json!({
+                "level": match severity {
+                    Severity::Error => "error",
+                    Severity::Warning => "warning",
+                    Severity::Information | Severity::Hint => "note",
+                },
+                "message": { "text": diagnostic.message() },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": { "uri": display_path(&file.path) },
+                        "region": {
+                            "startLine": start_line + 1,
+                            "startColumn": start_column + 1,
+                            "endLine": end_line + 1,
+                            "endColumn": end_column + 1,
+                        },
+                    },
+                }],
+            });
+
+            if let Some(code) = diagnostic.code() {
+                result["ruleId"] = json!(code);
+                rules.insert(
+                    code,
+                    json!({
+                        "id": code,
+                        "helpUri": diagnostic.documentation_url(),
+                    }),
+                );
+            }
+
+            results.push(result);
+        }
+    }
+
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "amber-lsp",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": "https://github.com/amber-lang/amber-lsp",
+                    "rules": rules.into_values().collect::<Vec<Value>>(),
+                },
+            },
+            "results": results,
+        }],
+    })
+}
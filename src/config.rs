@@ -0,0 +1,136 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use serde::Deserialize;
+
+use crate::{analyzer::AmberVersion, diagnostics::Severity};
+
+/// Settings of the server, that can be changed by the client at runtime.
+///
+/// They are pulled with `workspace/configuration` from the `amber-lsp` section.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ServerConfig {
+    /// Version of Amber to use. `auto` keeps the version the server was started with.
+    pub version: String,
+    /// Maximum number of diagnostics reported for a single file.
+    pub max_number_of_problems: usize,
+    /// Severity overrides, keyed by diagnostic code.
+    pub lints: HashMap<String, LintSeverity>,
+    /// Inlay hint toggles. They are kept in the settings for the inlay hints,
+    /// that the server doesn't provide yet.
+    pub inlay_hints: InlayHintsConfig,
+    /// Directory, that contains the standard library, instead of the bundled one.
+    pub stdlib_path: Option<PathBuf>,
+    /// Additional directories, where imports are looked up, when they
+    /// can't be found relative to the importing file.
+    pub import_paths: Vec<PathBuf>,
+    /// Milliseconds to wait after an edit, before its diagnostics are published.
+    /// Diagnostics of versions replaced by newer edits in the meantime are not published.
+    pub diagnostics_delay: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            version: "auto".to_string(),
+            max_number_of_problems: 100,
+            lints: HashMap::new(),
+            inlay_hints: InlayHintsConfig::default(),
+            stdlib_path: None,
+            import_paths: vec![],
+            diagnostics_delay: 150,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Parses settings sent by the client. Invalid settings fall back to defaults.
+    pub fn from_value(value: serde_json::Value) -> Self {
+        match serde_json::from_value(value) {
+            Ok(config) => config,
+            Err(err) => {
+                tracing::warn!("Invalid configuration: {}", err);
+                Self::default()
+            }
+        }
+    }
+
+    /// Returns the configured Amber version, or `None` if it should be detected.
+    pub fn amber_version(&self) -> Option<AmberVersion> {
+        parse_amber_version(&self.version)
+    }
+}
+
+const AMBER_VERSIONS: [(&str, AmberVersion); 3] = [
+    ("0.3.4-alpha", AmberVersion::Alpha034),
+    ("0.3.5-alpha", AmberVersion::Alpha035),
+    ("0.4.0-alpha", AmberVersion::Alpha040),
+];
+
+pub fn parse_amber_version(version: &str) -> Option<AmberVersion> {
+    AMBER_VERSIONS
+        .iter()
+        .find(|(name, _)| *name == version)
+        .map(|(_, amber_version)| amber_version.clone())
+}
+
+pub fn amber_version_name(amber_version: &AmberVersion) -> &'static str {
+    AMBER_VERSIONS
+        .iter()
+        .find(|(_, version)| version == amber_version)
+        .map(|(name, _)| *name)
+        .unwrap()
+}
+
+/// Finds a version hint in a shebang line, e.g. `#!/usr/bin/env amber-0.4.0-alpha`.
+pub fn parse_shebang_version(line: &str) -> Option<AmberVersion> {
+    if !line.starts_with("#!") || !line.contains("amber") {
+        return None;
+    }
+
+    AMBER_VERSIONS
+        .iter()
+        .find(|(name, _)| line.contains(name))
+        .map(|(_, amber_version)| amber_version.clone())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LintSeverity {
+    Off,
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+impl LintSeverity {
+    /// Returns `None` if diagnostics should not be reported at all.
+    pub fn to_severity(self) -> Option<Severity> {
+        match self {
+            LintSeverity::Off => None,
+            LintSeverity::Error => Some(Severity::Error),
+            LintSeverity::Warning => Some(Severity::Warning),
+            LintSeverity::Information => Some(Severity::Information),
+            LintSeverity::Hint => Some(Severity::Hint),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct InlayHintsConfig {
+    /// Show inferred types of variables.
+    pub variable_types: bool,
+    /// Show names of parameters in function calls.
+    pub parameter_names: bool,
+}
+
+impl Default for InlayHintsConfig {
+    fn default() -> Self {
+        Self {
+            variable_types: true,
+            parameter_names: true,
+        }
+    }
+}
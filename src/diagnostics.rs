@@ -0,0 +1,288 @@
+use serde_json::{json, Value};
+
+use crate::{
+    grammar::{CommandModifier, Span},
+    paths::FileId,
+};
+
+const DOCS_URL: &str = "https://github.com/amber-lang/amber-lsp/blob/master/docs/diagnostics.md";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+/// What kind of failable expression is missing a failure handler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailableKind {
+    Command,
+    Function,
+}
+
+/// Statement, that is only allowed inside of some blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlFlowKind {
+    Break,
+    Continue,
+    Return,
+    Fail,
+    Propagate,
+}
+
+impl ControlFlowKind {
+    fn name(self) -> &'static str {
+        match self {
+            ControlFlowKind::Break => "break",
+            ControlFlowKind::Continue => "continue",
+            ControlFlowKind::Return => "return",
+            ControlFlowKind::Fail => "fail",
+            ControlFlowKind::Propagate => "propagate",
+        }
+    }
+}
+
+/// A location in some file, that gives more context to a diagnostic.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RelatedLocation {
+    pub file: FileId,
+    pub span: Span,
+    pub message: String,
+}
+
+/// A problem found in a document.
+///
+/// Every variant, except for the generic `Error` and `Warning`,
+/// has a stable code, that can be used to look up its documentation
+/// or to configure its severity.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Diagnostic {
+    Syntax(String),
+    UndefinedSymbol {
+        name: String,
+    },
+    TypeMismatch {
+        expected: String,
+        found: String,
+        /// Definition of the symbol that has the mismatched type, if there is one.
+        definition: Option<RelatedLocation>,
+    },
+    FileNotFound {
+        path: String,
+    },
+    UnresolvedImport {
+        name: String,
+    },
+    CircularDependency {
+        path: String,
+        /// Names of the files in the cycle, starting and ending with the importing file.
+        files: Vec<String>,
+        /// Import statements, that form the cycle.
+        cycle: Vec<RelatedLocation>,
+    },
+    MissingFailureHandler {
+        kind: FailableKind,
+        /// Modifier, that can be used instead of a failure handler.
+        modifier: CommandModifier,
+    },
+    NotAFunction {
+        name: String,
+    },
+    FunctionAsValue {
+        name: String,
+    },
+    TooManyArguments {
+        expected: usize,
+    },
+    MissingArguments {
+        expected: usize,
+    },
+    ConstantAssignment {
+        name: String,
+        /// The constant is passed as a reference argument, instead of assigned to.
+        by_reference: bool,
+    },
+    FunctionAssignment {
+        name: String,
+    },
+    InvalidReference,
+    MisplacedControlFlow {
+        kind: ControlFlowKind,
+    },
+    MixedArrayTypes,
+    DuplicateImport {
+        name: String,
+    },
+    MisplacedOptionalArgument,
+    OptionalReference,
+    NonFailablePropagation,
+    Error(String),
+    Warning(String),
+}
+
+impl Diagnostic {
+    pub fn code(&self) -> Option<&'static str> {
+        match self {
+            Diagnostic::Syntax(_) => Some("syntax-error"),
+            Diagnostic::UndefinedSymbol { .. } => Some("undefined-symbol"),
+            Diagnostic::TypeMismatch { .. } => Some("type-mismatch"),
+            Diagnostic::FileNotFound { .. } => Some("file-not-found"),
+            Diagnostic::UnresolvedImport { .. } => Some("unresolved-import"),
+            Diagnostic::CircularDependency { .. } => Some("circular-dependency"),
+            Diagnostic::MissingFailureHandler { .. } => Some("missing-failure-handler"),
+            Diagnostic::NotAFunction { .. } => Some("not-a-function"),
+            Diagnostic::FunctionAsValue { .. } => Some("function-as-value"),
+            Diagnostic::TooManyArguments { .. } => Some("too-many-arguments"),
+            Diagnostic::MissingArguments { .. } => Some("missing-arguments"),
+            Diagnostic::ConstantAssignment { .. } => Some("constant-assignment"),
+            Diagnostic::FunctionAssignment { .. } => Some("function-assignment"),
+            Diagnostic::InvalidReference => Some("invalid-reference"),
+            Diagnostic::MisplacedControlFlow { .. } => Some("misplaced-control-flow"),
+            Diagnostic::MixedArrayTypes => Some("mixed-array-types"),
+            Diagnostic::DuplicateImport { .. } => Some("duplicate-import"),
+            Diagnostic::MisplacedOptionalArgument => Some("misplaced-optional-argument"),
+            Diagnostic::OptionalReference => Some("optional-reference"),
+            Diagnostic::NonFailablePropagation => Some("non-failable-propagation"),
+            Diagnostic::Error(_) | Diagnostic::Warning(_) => None,
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self {
+            Diagnostic::Warning(_) => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            Diagnostic::Syntax(msg) | Diagnostic::Error(msg) | Diagnostic::Warning(msg) => {
+                msg.clone()
+            }
+            Diagnostic::UndefinedSymbol { name } => format!("\"{name}\" is not defined"),
+            Diagnostic::TypeMismatch {
+                expected, found, ..
+            } => format!("Expected type `{expected}`, found type `{found}`"),
+            Diagnostic::FileNotFound { .. } => "File doesn't exist".to_string(),
+            Diagnostic::UnresolvedImport { name } => format!("Could not resolve '{name}'"),
+            Diagnostic::CircularDependency { files, .. } if !files.is_empty() => {
+                format!("Circular dependency: {}", files.join(" → "))
+            }
+            Diagnostic::CircularDependency { .. } => "Circular dependency".to_string(),
+            Diagnostic::MissingFailureHandler {
+                kind: FailableKind::Command,
+                ..
+            } => "Command must have a failure handler".to_string(),
+            Diagnostic::MissingFailureHandler {
+                kind: FailableKind::Function,
+                modifier,
+            } => format!(
+                "Failable function must be handled with a failure handler or marked with `{modifier}` modifier"
+            ),
+            Diagnostic::NotAFunction { name } => format!("{name} is not a function"),
+            Diagnostic::FunctionAsValue { name } => format!("{name} is a function"),
+            Diagnostic::TooManyArguments { expected } => {
+                format!("Function takes only {expected} arguments")
+            }
+            Diagnostic::MissingArguments { expected } => {
+                format!("Function takes {expected} arguments")
+            }
+            Diagnostic::ConstantAssignment {
+                by_reference: false,
+                ..
+            } => "Cannot assign to a constant".to_string(),
+            Diagnostic::ConstantAssignment {
+                by_reference: true,
+                ..
+            } => "Cannot modify a constant variable".to_string(),
+            Diagnostic::FunctionAssignment { .. } => "Cannot assign to a function".to_string(),
+            Diagnostic::InvalidReference => "Cannot pass a non-variable as a reference".to_string(),
+            Diagnostic::MisplacedControlFlow { kind } => match kind {
+                ControlFlowKind::Break => "Break statement outside of loop",
+                ControlFlowKind::Continue => "Continue statement outside of loop",
+                ControlFlowKind::Return => "Return statement outside of function",
+                ControlFlowKind::Fail => {
+                    "Fail statements can only be used inside of functions or the main block"
+                }
+                ControlFlowKind::Propagate => {
+                    "Propagate can only be used inside of main block or function"
+                }
+            }
+            .to_string(),
+            Diagnostic::MixedArrayTypes => "Array must have elements of the same type".to_string(),
+            Diagnostic::DuplicateImport { name } => format!("Duplicate import '{name}'"),
+            Diagnostic::MisplacedOptionalArgument => {
+                "Optional argument must be the last one".to_string()
+            }
+            Diagnostic::OptionalReference => "Optional argument cannot be a reference".to_string(),
+            Diagnostic::NonFailablePropagation => {
+                "Function is propagating an error, but return type is not failable".to_string()
+            }
+        }
+    }
+
+    pub fn documentation_url(&self) -> Option<String> {
+        self.code().map(|code| format!("{DOCS_URL}#{code}"))
+    }
+
+    pub fn related(&self) -> Vec<RelatedLocation> {
+        match self {
+            Diagnostic::TypeMismatch {
+                definition: Some(definition),
+                ..
+            } => vec![definition.clone()],
+            Diagnostic::CircularDependency { cycle, .. } => cycle.clone(),
+            _ => vec![],
+        }
+    }
+
+    pub fn related_mut(&mut self) -> Vec<&mut RelatedLocation> {
+        match self {
+            Diagnostic::TypeMismatch {
+                definition: Some(definition),
+                ..
+            } => vec![definition],
+            Diagnostic::CircularDependency { cycle, .. } => cycle.iter_mut().collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Machine-readable details of the diagnostic, used by code actions.
+    pub fn data(&self) -> Option<Value> {
+        match self {
+            Diagnostic::UndefinedSymbol { name }
+            | Diagnostic::UnresolvedImport { name }
+            | Diagnostic::NotAFunction { name }
+            | Diagnostic::FunctionAsValue { name }
+            | Diagnostic::ConstantAssignment { name, .. }
+            | Diagnostic::FunctionAssignment { name }
+            | Diagnostic::DuplicateImport { name } => Some(json!({ "name": name })),
+            Diagnostic::TooManyArguments { expected }
+            | Diagnostic::MissingArguments { expected } => Some(json!({ "expected": expected })),
+            Diagnostic::MisplacedControlFlow { kind } => Some(json!({ "kind": kind.name() })),
+            Diagnostic::TypeMismatch {
+                expected, found, ..
+            } => Some(json!({ "expected": expected, "found": found })),
+            Diagnostic::FileNotFound { path } | Diagnostic::CircularDependency { path, .. } => {
+                Some(json!({ "path": path }))
+            }
+            Diagnostic::MissingFailureHandler { kind, modifier } => Some(json!({
+                "kind": match kind {
+                    FailableKind::Command => "command",
+                    FailableKind::Function => "function",
+                },
+                "modifier": modifier.to_string(),
+            })),
+            Diagnostic::Syntax(_)
+            | Diagnostic::InvalidReference
+            | Diagnostic::MixedArrayTypes
+            | Diagnostic::MisplacedOptionalArgument
+            | Diagnostic::OptionalReference
+            | Diagnostic::NonFailablePropagation
+            | Diagnostic::Error(_)
+            | Diagnostic::Warning(_) => None,
+        }
+    }
+}
@@ -1,4 +1,7 @@
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use chumsky::span::SimpleSpan;
 use ropey::Rope;
@@ -6,11 +9,16 @@ use tokio::sync::RwLock;
 use tower_lsp_server::lsp_types::Uri;
 
 use crate::{
-    analysis::{types::GenericsMap, SymbolTable},
+    analysis::{
+        incremental::ParsedDocument, query::FunctionAnalysis, snapshot::AnalysisSnapshot,
+        types::GenericsMap, SymbolTable,
+    },
+    analyzer::AmberVersion,
+    diagnostics::Diagnostic,
     fs::FS,
     grammar::{Grammar, Spanned, SpannedSemanticToken},
     paths::{FileId, PathInterner},
-    utils::FastDashMap,
+    utils::{FastDashMap, FastDashSet},
 };
 
 #[derive(Copy, Debug, Clone, PartialEq, Eq, Hash)]
@@ -36,16 +44,30 @@ impl FileVersion {
 pub struct Files {
     paths: PathInterner,
     file_versions: FastDashMap<FileId, FileVersion>,
-    file_dependencies: FastDashMap<(FileId, FileVersion), Vec<FileId>>,
+    analyzed_versions: FastDashMap<FileId, FileVersion>,
+    file_dependencies: FastDashMap<(FileId, FileVersion), Vec<Spanned<FileId>>>,
+    open_files: FastDashSet<FileId>,
+    indexed_files: FastDashSet<FileId>,
     pub analyze_lock: FastDashMap<(FileId, FileVersion), Arc<RwLock<bool>>>,
     pub fs: Arc<dyn FS>,
     pub ast_map: FastDashMap<(FileId, FileVersion), Grammar>,
-    pub errors: FastDashMap<(FileId, FileVersion), Vec<Spanned<String>>>,
-    pub warnings: FastDashMap<(FileId, FileVersion), Vec<Spanned<String>>>,
+    pub diagnostics: FastDashMap<(FileId, FileVersion), Vec<Spanned<Diagnostic>>>,
     pub document_map: FastDashMap<(FileId, FileVersion), Rope>,
     pub semantic_token_map: FastDashMap<(FileId, FileVersion), Vec<SpannedSemanticToken>>,
-    pub symbol_table: FastDashMap<(FileId, FileVersion), SymbolTable>,
+    /// Symbol tables are shared with readers, and copied only when written while shared.
+    /// They are not shared with [`AnalysisSnapshot`]s, so that retargeting them doesn't copy them.
+    pub symbol_table: FastDashMap<(FileId, FileVersion), Arc<SymbolTable>>,
     pub generic_types: GenericsMap,
+    /// Version of Amber, that each file is analyzed with.
+    pub amber_versions: FastDashMap<FileId, AmberVersion>,
+    /// Analysis of the last version of each file, that could be parsed.
+    pub last_good_analysis: FastDashMap<FileId, AnalysisSnapshot>,
+    /// Syntax of the last parsed text of each file.
+    pub parse_cache: FastDashMap<FileId, ParsedDocument>,
+    /// Fingerprint of the public interface of the last analyzed version of each file.
+    pub interfaces: FastDashMap<FileId, (FileVersion, u64)>,
+    /// Results of the analysis of each function, by file and name of the function.
+    pub function_cache: FastDashMap<(FileId, String), FunctionAnalysis>,
 }
 
 pub const DEFAULT_VERSION: FileVersion = FileVersion(1);
@@ -56,15 +78,22 @@ impl Files {
             paths: PathInterner::default(),
             fs,
             file_versions: FastDashMap::default(),
+            analyzed_versions: FastDashMap::default(),
             file_dependencies: FastDashMap::default(),
+            open_files: FastDashSet::default(),
+            indexed_files: FastDashSet::default(),
             ast_map: FastDashMap::default(),
-            errors: FastDashMap::default(),
-            warnings: FastDashMap::default(),
+            diagnostics: FastDashMap::default(),
             document_map: FastDashMap::default(),
             semantic_token_map: FastDashMap::default(),
             symbol_table: FastDashMap::default(),
             generic_types: GenericsMap::new(),
             analyze_lock: FastDashMap::default(),
+            amber_versions: FastDashMap::default(),
+            last_good_analysis: FastDashMap::default(),
+            parse_cache: FastDashMap::default(),
+            interfaces: FastDashMap::default(),
+            function_cache: FastDashMap::default(),
         }
     }
 
@@ -79,8 +108,11 @@ impl Files {
         self.paths.lookup(file_id)
     }
 
+    /// Returns the id of a file, unless it is unknown or all of its state was dropped.
     pub fn get(&self, uri: &Uri) -> Option<FileId> {
-        self.paths.get(uri)
+        self.paths
+            .get(uri)
+            .filter(|file_id| self.file_versions.contains_key(file_id))
     }
 
     #[tracing::instrument(skip_all)]
@@ -98,18 +130,144 @@ impl Files {
 
     #[tracing::instrument(skip_all)]
     fn remove_file_version(&self, file_id: FileId, version: FileVersion) {
-        self.ast_map.remove(&(file_id, version));
-        self.errors.remove(&(file_id, version));
-        self.warnings.remove(&(file_id, version));
+        self.invalidate(&(file_id, version));
         self.document_map.remove(&(file_id, version));
-        self.semantic_token_map.remove(&(file_id, version));
-        self.symbol_table.remove(&(file_id, version));
-        self.generic_types.clean(file_id, version);
-        self.file_dependencies.remove(&(file_id, version));
+    }
+
+    /// Drops the analysis results of `file` while keeping its document,
+    /// so that it can be analyzed again (e.g. after one of its imports changed).
+    #[tracing::instrument(skip_all)]
+    pub fn invalidate(&self, file: &(FileId, FileVersion)) {
+        self.analyze_lock.remove(file);
+        self.ast_map.remove(file);
+        self.diagnostics.remove(file);
+        self.semantic_token_map.remove(file);
+        // The snapshot of the version takes over its symbol table, with its generic types
+        let mut snapshot = self
+            .last_good_analysis
+            .get_mut(&file.0)
+            .filter(|snapshot| snapshot.version == file.1);
+        if let Some((_, symbol_table)) = self.symbol_table.remove(file) {
+            if let Some(snapshot) = snapshot.as_mut() {
+                snapshot.symbol_table = Some(symbol_table);
+            }
+        }
+        if snapshot.is_none() {
+            self.generic_types.clean(file.0, file.1);
+        }
+        self.file_dependencies.remove(file);
+    }
+
+    /// Drops the latest version of a file, that is not open in the editor,
+    /// so that it is read from the disk again on the next import.
+    #[tracing::instrument(skip_all)]
+    pub fn unload(&self, file_id: FileId) {
+        let version = self.get_latest_version(file_id);
+
+        self.remove_file_version(file_id, version);
+    }
+
+    /// Drops all versions of a file, along with their analysis results.
+    #[tracing::instrument(skip_all)]
+    pub fn remove_file(&self, file_id: FileId) {
+        let versions = self
+            .document_map
+            .iter()
+            .map(|entry| *entry.key())
+            .chain(self.analyze_lock.iter().map(|entry| *entry.key()))
+            .chain(self.symbol_table.iter().map(|entry| *entry.key()))
+            .filter(|(id, _)| *id == file_id)
+            .map(|(_, version)| version)
+            .collect::<HashSet<_>>();
+
+        if let Some((_, snapshot)) = self.last_good_analysis.remove(&file_id) {
+            self.generic_types.clean(file_id, snapshot.version);
+        }
+
+        for version in versions {
+            self.remove_file_version(file_id, version);
+        }
+
+        self.file_versions.remove(&file_id);
+        self.analyzed_versions.remove(&file_id);
+        self.amber_versions.remove(&file_id);
+        self.parse_cache.remove(&file_id);
+        self.interfaces.remove(&file_id);
+        self.function_cache.retain(|(id, _), _| *id != file_id);
+    }
+
+    /// Returns files, that are open, indexed as part of the workspace,
+    /// or imported (directly or not) by one of those.
+    pub fn get_reachable_files(&self) -> HashSet<FileId> {
+        let mut reachable = HashSet::new();
+        let mut stack = self
+            .open_files
+            .iter()
+            .chain(self.indexed_files.iter())
+            .map(|file_id| *file_id)
+            .collect::<Vec<_>>();
+
+        while let Some(file_id) = stack.pop() {
+            if !reachable.insert(file_id) {
+                continue;
+            }
+
+            let file = (file_id, self.get_latest_version(file_id));
+            if let Some(dependencies) = self.file_dependencies.get(&file) {
+                stack.extend(dependencies.iter().map(|(dep, _)| *dep));
+            }
+        }
+
+        reachable
+    }
+
+    /// Drops state of all files, that are not reachable from open or indexed files.
+    #[tracing::instrument(skip_all)]
+    pub fn collect_garbage(&self) {
+        let reachable = self.get_reachable_files();
+
+        let unreachable = self
+            .file_versions
+            .iter()
+            .map(|entry| *entry.key())
+            .filter(|file_id| !reachable.contains(file_id))
+            .collect::<Vec<_>>();
+
+        for file_id in unreachable {
+            self.remove_file(file_id);
+        }
+    }
+
+    pub fn mark_as_open(&self, file_id: FileId) {
+        self.open_files.insert(file_id);
+    }
+
+    pub fn mark_as_closed(&self, file_id: FileId) {
+        self.open_files.remove(&file_id);
+    }
+
+    pub fn mark_as_indexed(&self, file_id: FileId) {
+        self.indexed_files.insert(file_id);
+    }
+
+    /// Removes files inside of the given folder from the workspace index.
+    pub fn remove_from_index(&self, folder: &Uri) {
+        let prefix = format!("{}/", folder.as_str().trim_end_matches('/'));
+
+        self.indexed_files
+            .retain(|file_id| !self.lookup(file_id).as_str().starts_with(&prefix));
+    }
+
+    /// Returns true if the file is managed by the editor, rather than read from the disk.
+    pub fn is_open(&self, file_id: FileId) -> bool {
+        self.open_files.contains(&file_id)
     }
 
     pub fn get_latest_version(&self, file_id: FileId) -> FileVersion {
-        *self.file_versions.get(&file_id).unwrap()
+        self.file_versions
+            .get(&file_id)
+            .map(|version| *version)
+            .unwrap_or(DEFAULT_VERSION)
     }
 
     pub fn get_document_latest_version(&self, file_id: FileId) -> Option<(Rope, FileVersion)> {
@@ -120,14 +278,57 @@ impl Files {
             .map(|document| (document.clone(), file_version))
     }
 
-    pub fn report_error(&self, file: &(FileId, FileVersion), msg: &str, span: SimpleSpan) {
-        let mut errors = self.errors.entry(*file).or_default();
-        errors.push((msg.to_string(), span));
+    /// Whether a newer version of the file was received,
+    /// in which case analysis of this version can be abandoned.
+    pub fn is_outdated(&self, file: &(FileId, FileVersion)) -> bool {
+        self.get_latest_version(file.0).0 > file.1 .0
     }
 
-    pub fn report_warning(&self, file: &(FileId, FileVersion), msg: &str, span: SimpleSpan) {
-        let mut warnings = self.warnings.entry(*file).or_default();
-        warnings.push((msg.to_string(), span));
+    /// Records, that analysis of the file version completed.
+    pub fn mark_as_analyzed(&self, file: (FileId, FileVersion)) {
+        let mut version = self.analyzed_versions.entry(file.0).or_insert(file.1);
+
+        if version.0 < file.1 .0 {
+            *version = file.1;
+        }
+    }
+
+    /// Returns the latest version of a file, whose analysis completed.
+    ///
+    /// Waits for the analysis of the latest version, if it's running.
+    /// If it was cancelled, or didn't start yet, the version analyzed before is returned.
+    pub async fn get_analyzed_version(&self, file_id: FileId) -> Option<FileVersion> {
+        let latest_version = self.get_latest_version(file_id);
+
+        if self.is_file_analyzed(&(file_id, latest_version)).await {
+            return Some(latest_version);
+        }
+
+        let version = self
+            .analyzed_versions
+            .get(&file_id)
+            .map(|version| *version)?;
+
+        self.is_file_analyzed(&(file_id, version))
+            .await
+            .then_some(version)
+    }
+
+    /// Returns the document of the latest version of a file, whose analysis completed.
+    pub async fn get_document_analyzed_version(
+        &self,
+        file_id: FileId,
+    ) -> Option<(Rope, FileVersion)> {
+        let file_version = self.get_analyzed_version(file_id).await?;
+
+        self.document_map
+            .get(&(file_id, file_version))
+            .map(|document| (document.clone(), file_version))
+    }
+
+    pub fn report(&self, file: &(FileId, FileVersion), diagnostic: Diagnostic, span: SimpleSpan) {
+        let mut diagnostics = self.diagnostics.entry(*file).or_default();
+        diagnostics.push((diagnostic, span));
     }
 
     #[tracing::instrument(skip_all)]
@@ -142,40 +343,247 @@ impl Files {
         }
     }
 
-    pub fn get_files_dependant_on(&self, file_id: FileId) -> Vec<(FileId, FileVersion)> {
-        let mut dependant_files = self
+    /// Returns the latest version of files, that depend on the file directly or through
+    /// other files, grouped in the order they should be analyzed again.
+    ///
+    /// Files of a group only import the file and files of the groups before it,
+    /// so files of the same group can be analyzed at the same time. Files of an import cycle
+    /// are put in the same group. Each file appears only once.
+    pub fn get_dependants_in_order(&self, file_id: FileId) -> Vec<Vec<(FileId, FileVersion)>> {
+        // Imports of the latest version of each file
+        let imports = self
             .file_dependencies
             .iter()
-            .filter_map(|file_ref| {
-                let file = file_ref.key();
-                let file_deps = file_ref.value();
+            .filter(|entry| entry.key().1 == self.get_latest_version(entry.key().0))
+            .map(|entry| {
+                let imports = entry
+                    .value()
+                    .iter()
+                    .map(|(dep, _)| *dep)
+                    .filter(|dep| *dep != entry.key().0)
+                    .collect::<HashSet<_>>();
 
-                if file_deps.contains(&file_id) {
-                    Some(*file)
-                } else {
-                    None
+                (entry.key().0, imports)
+            })
+            .collect::<HashMap<_, _>>();
+
+        let mut dependants = HashSet::new();
+        let mut stack = vec![file_id];
+
+        while let Some(dependency) = stack.pop() {
+            for (file, file_imports) in imports.iter() {
+                if *file != file_id
+                    && file_imports.contains(&dependency)
+                    && dependants.insert(*file)
+                {
+                    stack.push(*file);
                 }
+            }
+        }
+
+        // Files importing each other are analyzed together, after the files they import
+        let components = strongly_connected_components(&dependants, &imports);
+        let component_of = components
+            .iter()
+            .enumerate()
+            .flat_map(|(index, files)| files.iter().map(move |file| (*file, index)))
+            .collect::<HashMap<_, _>>();
+
+        let component_imports = components
+            .iter()
+            .enumerate()
+            .map(|(index, files)| {
+                files
+                    .iter()
+                    .flat_map(|file| imports[file].iter())
+                    .filter_map(|dep| component_of.get(dep).copied())
+                    .filter(|dep| *dep != index)
+                    .collect::<HashSet<_>>()
             })
             .collect::<Vec<_>>();
 
-        dependant_files.dedup_by_key(|(file_id, _)| *file_id);
+        // Number of components, that each component still waits for
+        let mut pending_imports = component_imports
+            .iter()
+            .map(HashSet::len)
+            .enumerate()
+            .collect::<HashMap<_, _>>();
+
+        let mut groups = vec![];
+
+        while !pending_imports.is_empty() {
+            // Components don't import each other, so some of them are always ready
+            let ready = pending_imports
+                .iter()
+                .filter(|(_, count)| **count == 0)
+                .map(|(component, _)| *component)
+                .collect::<Vec<_>>();
 
-        dependant_files
+            for component in ready.iter() {
+                pending_imports.remove(component);
+            }
+
+            for (component, count) in pending_imports.iter_mut() {
+                *count -= component_imports[*component]
+                    .iter()
+                    .filter(|dep| ready.contains(dep))
+                    .count();
+            }
+
+            let mut group = ready
+                .iter()
+                .flat_map(|component| components[*component].iter().copied())
+                .collect::<Vec<_>>();
+
+            group.sort_by_key(|file| file.0);
+
+            groups.push(
+                group
+                    .into_iter()
+                    .map(|file| (file, self.get_latest_version(file)))
+                    .collect(),
+            );
+        }
+
+        groups
     }
 
-    pub fn add_file_dependency(&self, file: &(FileId, FileVersion), dependency: FileId) {
+    pub fn add_file_dependency(
+        &self,
+        file: &(FileId, FileVersion),
+        dependency: FileId,
+        import_span: SimpleSpan,
+    ) {
         let mut dependencies = self.file_dependencies.entry(*file).or_default();
 
-        dependencies.push(dependency);
+        dependencies.push((dependency, import_span));
+    }
+
+    pub fn get_file_dependencies(&self, file: &(FileId, FileVersion)) -> Vec<Spanned<FileId>> {
+        self.file_dependencies
+            .get(file)
+            .map(|dependencies| dependencies.clone())
+            .unwrap_or_default()
     }
 
     pub fn is_depending_on(&self, file: &(FileId, FileVersion), dependency: FileId) -> bool {
-        match self.file_dependencies.get(file) {
-            Some(deps) => deps.iter().any(|dep| {
-                *dep == dependency
-                    || self.is_depending_on(&(*dep, self.get_latest_version(*dep)), dependency)
-            }),
-            None => false,
+        self.get_dependency_path(file, dependency).is_some()
+    }
+
+    /// Finds a chain of imports, through which `file` depends on `dependency`.
+    ///
+    /// Each element of the chain is a file together with the span of its import,
+    /// that leads to the next file in the chain.
+    pub fn get_dependency_path(
+        &self,
+        file: &(FileId, FileVersion),
+        dependency: FileId,
+    ) -> Option<Vec<((FileId, FileVersion), SimpleSpan)>> {
+        self.find_dependency_path(file, dependency, &mut HashSet::new())
+    }
+
+    fn find_dependency_path(
+        &self,
+        file: &(FileId, FileVersion),
+        dependency: FileId,
+        visited: &mut HashSet<FileId>,
+    ) -> Option<Vec<((FileId, FileVersion), SimpleSpan)>> {
+        if !visited.insert(file.0) {
+            return None;
+        }
+
+        let dependencies = self.file_dependencies.get(file)?.clone();
+
+        for (dep, import_span) in dependencies {
+            if dep == dependency {
+                return Some(vec![(*file, import_span)]);
+            }
+
+            let dep_file = (dep, self.get_latest_version(dep));
+            if let Some(mut path) = self.find_dependency_path(&dep_file, dependency, visited) {
+                path.insert(0, (*file, import_span));
+                return Some(path);
+            }
+        }
+
+        None
+    }
+}
+
+/// Groups the files, that import each other directly or through other files of the set,
+/// using Tarjan's algorithm.
+fn strongly_connected_components(
+    files: &HashSet<FileId>,
+    imports: &HashMap<FileId, HashSet<FileId>>,
+) -> Vec<Vec<FileId>> {
+    struct Tarjan<'a> {
+        files: &'a HashSet<FileId>,
+        imports: &'a HashMap<FileId, HashSet<FileId>>,
+        indices: HashMap<FileId, usize>,
+        low_links: HashMap<FileId, usize>,
+        stack: Vec<FileId>,
+        on_stack: HashSet<FileId>,
+        components: Vec<Vec<FileId>>,
+    }
+
+    impl Tarjan<'_> {
+        fn visit(&mut self, file: FileId) {
+            let index = self.indices.len();
+            self.indices.insert(file, index);
+            self.low_links.insert(file, index);
+            self.stack.push(file);
+            self.on_stack.insert(file);
+
+            let (files, imports) = (self.files, self.imports);
+            let deps = imports.get(&file).into_iter().flatten();
+
+            for dep in deps.filter(|dep| files.contains(dep)) {
+                let low_link = if !self.indices.contains_key(dep) {
+                    self.visit(*dep);
+                    self.low_links[dep]
+                } else if self.on_stack.contains(dep) {
+                    self.indices[dep]
+                } else {
+                    continue;
+                };
+
+                if low_link < self.low_links[&file] {
+                    self.low_links.insert(file, low_link);
+                }
+            }
+
+            if self.low_links[&file] == index {
+                let mut component = vec![];
+
+                while let Some(member) = self.stack.pop() {
+                    self.on_stack.remove(&member);
+                    component.push(member);
+
+                    if member == file {
+                        break;
+                    }
+                }
+
+                self.components.push(component);
+            }
         }
     }
+
+    let mut tarjan = Tarjan {
+        files,
+        imports,
+        indices: HashMap::new(),
+        low_links: HashMap::new(),
+        stack: vec![],
+        on_stack: HashSet::new(),
+        components: vec![],
+    };
+
+    for file in files {
+        if !tarjan.indices.contains_key(file) {
+            tarjan.visit(*file);
+        }
+    }
+
+    tarjan.components
 }
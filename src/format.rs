@@ -0,0 +1,151 @@
+use similar::TextDiff;
+
+/// Indentation used when the editor doesn't specify one.
+pub const DEFAULT_INDENT: &str = "    ";
+
+enum Frame {
+    /// Code, with the number of braces opened inside of it.
+    Code(usize),
+    Text,
+    Command,
+}
+
+/// Tracks the nesting of brackets, strings and commands across lines.
+struct Scanner {
+    stack: Vec<Frame>,
+    /// Number of brackets open in the top level code.
+    depth: usize,
+}
+
+impl Scanner {
+    fn new() -> Self {
+        Scanner {
+            stack: vec![Frame::Code(0)],
+            depth: 0,
+        }
+    }
+
+    /// Whether the scanned text ends inside of a string or a command.
+    fn is_in_literal(&self) -> bool {
+        self.stack.len() > 1
+    }
+
+    fn scan(&mut self, line: &str) {
+        let mut chars = line.chars().peekable();
+
+        while let Some(char) = chars.next() {
+            let top_level = self.stack.len() == 1;
+
+            match self.stack.last_mut() {
+                Some(Frame::Code(braces)) => match char {
+                    '/' if chars.peek() == Some(&'/') => break,
+                    '"' => self.stack.push(Frame::Text),
+                    '$' => self.stack.push(Frame::Command),
+                    '(' | '[' | '{' if top_level => self.depth += 1,
+                    ')' | ']' | '}' if top_level => self.depth = self.depth.saturating_sub(1),
+                    '{' => *braces += 1,
+                    '}' if *braces == 0 => {
+                        self.stack.pop();
+                    }
+                    '}' => *braces -= 1,
+                    _ => {}
+                },
+                Some(Frame::Text) => match char {
+                    '\\' => {
+                        chars.next();
+                    }
+                    '"' => {
+                        self.stack.pop();
+                    }
+                    '{' => self.stack.push(Frame::Code(0)),
+                    _ => {}
+                },
+                Some(Frame::Command) => match char {
+                    '\\' => {
+                        chars.next();
+                    }
+                    '$' => {
+                        self.stack.pop();
+                    }
+                    '{' => self.stack.push(Frame::Code(0)),
+                    _ => {}
+                },
+                None => break,
+            }
+        }
+    }
+}
+
+/// Formats Amber source code.
+///
+/// Only the whitespace is changed, so the code doesn't have to be valid:
+/// lines are indented by the brackets they are nested in, trailing whitespace is removed,
+/// consecutive empty lines are merged and the file ends with a single new line.
+/// Lines inside multiline strings and commands are left untouched.
+pub fn format_document(text: &str, indent: &str) -> String {
+    let line_ending = if text.contains("\r\n") { "\r\n" } else { "\n" };
+
+    let mut scanner = Scanner::new();
+    let mut lines: Vec<String> = vec![];
+    let mut has_empty_line = false;
+
+    for (index, line) in text.lines().enumerate() {
+        if scanner.is_in_literal() {
+            scanner.scan(line);
+            lines.push(line.to_string());
+            continue;
+        }
+
+        let trimmed = line.trim_start();
+
+        if trimmed.trim_end().is_empty() {
+            has_empty_line = !lines.is_empty();
+            continue;
+        }
+
+        if has_empty_line {
+            lines.push(String::new());
+            has_empty_line = false;
+        }
+
+        if index == 0 && trimmed.starts_with("#!") {
+            lines.push(trimmed.trim_end().to_string());
+            continue;
+        }
+
+        let closing_brackets = trimmed
+            .chars()
+            .take_while(|char| matches!(char, ')' | ']' | '}'))
+            .count();
+        let level = scanner.depth.saturating_sub(closing_brackets);
+
+        scanner.scan(trimmed);
+
+        // Trailing whitespace of an unclosed string is a part of it
+        let content = if scanner.is_in_literal() {
+            trimmed
+        } else {
+            trimmed.trim_end()
+        };
+
+        lines.push(format!("{}{content}", indent.repeat(level)));
+    }
+
+    if lines.is_empty() {
+        return String::new();
+    }
+
+    let mut formatted = lines.join(line_ending);
+    formatted.push_str(line_ending);
+
+    formatted
+}
+
+/// Returns a unified diff of the changes made by the formatter.
+pub fn format_diff(path: &str, original: &str, formatted: &str) -> String {
+    TextDiff::from_lines(original, formatted)
+        .unified_diff()
+        .context_radius(3)
+        .header(path, path)
+        .to_string()
+}
@@ -2,13 +2,13 @@ use std::{
     collections::HashMap,
     fmt::Debug,
     future::Future,
-    io::Result,
+    io::{Error, ErrorKind, Result},
     path::{Path, PathBuf},
     pin::Pin,
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, PoisonError},
 };
 
-use tokio::fs::{create_dir_all, metadata, read_dir, read_to_string, write};
+use tokio::fs::{create_dir_all, metadata, read_dir, read_to_string, symlink_metadata, write};
 
 pub trait FS: Sync + Send + Debug {
     fn read<'a>(
@@ -21,6 +21,8 @@ pub trait FS: Sync + Send + Debug {
         content: &'a str,
     ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>;
     fn exists<'a>(&'a self, path: &'a Path) -> Pin<Box<dyn Future<Output = bool> + Send + 'a>>;
+    /// Returns true if the path is a directory. Symbolic links are not followed.
+    fn is_dir<'a>(&'a self, path: &'a Path) -> Pin<Box<dyn Future<Output = bool> + Send + 'a>>;
     fn read_dir<'a>(
         &'a self,
         path: &'a Path,
@@ -56,8 +58,12 @@ impl FS for MemoryFS {
         path: &'a Path,
     ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>> {
         Box::pin(async move {
-            let files = self.files.lock().unwrap();
-            Ok(files.get(path.to_str().unwrap()).unwrap().clone())
+            let files = self.files.lock().unwrap_or_else(PoisonError::into_inner);
+
+            match files.get(path.to_string_lossy().as_ref()) {
+                Some(content) => Ok(content.clone()),
+                None => Err(Error::from(ErrorKind::NotFound)),
+            }
         })
     }
 
@@ -67,7 +73,7 @@ impl FS for MemoryFS {
         content: &'a str,
     ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
         Box::pin(async move {
-            let mut files = self.files.lock().unwrap();
+            let mut files = self.files.lock().unwrap_or_else(PoisonError::into_inner);
             files.insert(path.to_string_lossy().to_string(), content.to_string());
 
             Ok(())
@@ -76,8 +82,20 @@ impl FS for MemoryFS {
 
     fn exists<'a>(&'a self, path: &'a Path) -> Pin<Box<dyn Future<Output = bool> + Send + 'a>> {
         Box::pin(async move {
-            let files = self.files.lock().unwrap();
-            files.contains_key(path.to_str().unwrap())
+            let files = self.files.lock().unwrap_or_else(PoisonError::into_inner);
+            files.contains_key(path.to_string_lossy().as_ref())
+        })
+    }
+
+    fn is_dir<'a>(&'a self, path: &'a Path) -> Pin<Box<dyn Future<Output = bool> + Send + 'a>> {
+        Box::pin(async move {
+            let files = self.files.lock().unwrap_or_else(PoisonError::into_inner);
+
+            files.keys().any(|file| {
+                Path::new(file)
+                    .strip_prefix(path)
+                    .is_ok_and(|rest| rest.components().next().is_some())
+            })
         })
     }
 
@@ -86,14 +104,23 @@ impl FS for MemoryFS {
         path: &'a Path,
     ) -> Pin<Box<dyn Future<Output = Vec<PathBuf>> + Send + 'a>> {
         Box::pin(async move {
-            let files = self.files.lock().unwrap();
+            let files = self.files.lock().unwrap_or_else(PoisonError::into_inner);
 
-            let mut entries = Vec::new();
-            for (file, _) in files.iter() {
-                if file.starts_with(path.to_str().unwrap()) {
-                    entries.push(PathBuf::from(file));
-                }
-            }
+            let mut entries = files
+                .keys()
+                .filter_map(|file| {
+                    let child = Path::new(file)
+                        .strip_prefix(path)
+                        .ok()?
+                        .components()
+                        .next()?;
+
+                    Some(path.join(child))
+                })
+                .collect::<Vec<_>>();
+
+            entries.sort();
+            entries.dedup();
 
             entries
         })
@@ -142,6 +169,14 @@ impl FS for LocalFs {
         Box::pin(async move { metadata(path).await.is_ok() })
     }
 
+    fn is_dir<'a>(&'a self, path: &'a Path) -> Pin<Box<dyn Future<Output = bool> + Send + 'a>> {
+        Box::pin(async move {
+            symlink_metadata(path)
+                .await
+                .is_ok_and(|metadata| metadata.is_dir())
+        })
+    }
+
     fn read_dir<'a>(
         &'a self,
         path: &'a Path,
@@ -168,3 +203,33 @@ impl FS for LocalFs {
         Box::pin(async move { create_dir_all(path).await })
     }
 }
+
+/// Recursively collects all Amber source files under `root`.
+///
+/// Hidden directories (e.g. `.git`) and symlinked directories are skipped.
+pub async fn find_amber_files(fs: &dyn FS, root: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        for entry in fs.read_dir(&dir).await {
+            if entry.extension().is_some_and(|ext| ext == "ab") {
+                files.push(entry);
+                continue;
+            }
+
+            let is_hidden = entry
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+
+            if !is_hidden && fs.is_dir(&entry).await {
+                dirs.push(entry);
+            }
+        }
+    }
+
+    files.sort();
+    files.dedup();
+
+    files
+}
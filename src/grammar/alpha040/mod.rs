@@ -1,12 +1,13 @@
 use crate::analysis::types::DataType;
 
 pub use super::Spanned;
-use super::{CommandModifier, CompilerFlag, Grammar, LSPAnalysis, ParserResponse, Span};
+use super::{
+    lex, CommandModifier, CompilerFlag, Grammar, LSPAnalysis, LexerResponse, ParserResponse, Span,
+};
 use chumsky::{
     error::Rich,
     extra::Err,
     input::{Input, SpannedInput},
-    span::SimpleSpan,
     Parser,
 };
 use heraclitus_compiler::prelude::*;
@@ -270,23 +271,8 @@ impl AmberCompiler {
 
 impl LSPAnalysis for AmberCompiler {
     #[tracing::instrument(skip_all)]
-    fn tokenize(&self, input: &str) -> Vec<Spanned<Token>> {
-        // It should never fail
-        self.lexer
-            .tokenize(&input.replace("\r\n", "\n").replace("\r", "\n"))
-            .expect("Failed to tokenize input")
-            .iter()
-            .filter_map(|t| {
-                if t.word == "\n" {
-                    return None;
-                }
-
-                Some((
-                    Token(t.word.clone()),
-                    SimpleSpan::new(t.start, t.start + t.word.chars().count()),
-                ))
-            })
-            .collect()
+    fn lex(&self, input: &str) -> LexerResponse {
+        lex(&self.lexer, input)
     }
 
     #[tracing::instrument(skip_all)]
@@ -1,4 +1,6 @@
 use chumsky::{error::Rich, span::SimpleSpan};
+use heraclitus_compiler::prelude::*;
+use prelude::lexer::Lexer;
 use std::fmt::{self, Debug, Display};
 
 pub mod alpha034;
@@ -44,9 +46,107 @@ pub struct ParserResponse<'a> {
     pub semantic_tokens: Vec<SpannedSemanticToken>,
 }
 
+pub struct LexerResponse {
+    pub tokens: Vec<Spanned<Token>>,
+    pub errors: Vec<Spanned<String>>,
+}
+
 pub trait LSPAnalysis: Sync + Send + Debug {
-    fn tokenize(&self, input: &str) -> Vec<Spanned<Token>>;
+    /// Tokenizes the input, reporting errors of the lexer instead of failing.
+    fn lex(&self, input: &str) -> LexerResponse;
     fn parse<'a>(&self, input: &'a [Spanned<Token>]) -> ParserResponse<'a>;
+
+    fn tokenize(&self, input: &str) -> Vec<Spanned<Token>> {
+        self.lex(input).tokens
+    }
+}
+
+/// Returns the character offset of a position reported by the lexer.
+fn lexer_error_offset(input: &str, position: &PositionInfo) -> usize {
+    let (row, col) = match position.position {
+        Position::Pos(row, col) => (row, col),
+        Position::EOF => return input.chars().count(),
+    };
+
+    let mut offset = 0;
+
+    for (index, line) in input.split('\n').enumerate() {
+        let line_length = line.chars().count();
+
+        if index + 1 == row {
+            return offset + col.saturating_sub(1).min(line_length);
+        }
+
+        offset += line_length + 1;
+    }
+
+    input.chars().count()
+}
+
+/// Tokenizes the input with the lexer of a grammar version.
+///
+/// When the lexer fails, the error is reported and lexing resumes on the next line,
+/// so the rest of the file still gets tokens and can be parsed.
+pub fn lex(lexer: &Lexer, input: &str) -> LexerResponse {
+    let input = input.replace("\r\n", "\n").replace('\r', "\n");
+
+    let mut tokens = vec![];
+    let mut errors = vec![];
+    // Character offset of the part of the input, that is not lexed yet
+    let mut start = 0;
+
+    loop {
+        let rest_start = input
+            .char_indices()
+            .nth(start)
+            .map(|(index, _)| index)
+            .unwrap_or(input.len());
+        let rest = &input[rest_start..];
+
+        let (lexed, error) = match lexer.tokenize(rest) {
+            Ok(lexed) => (lexed, None),
+            Err((error_type, position)) => {
+                let error_start = lexer_error_offset(rest, &position);
+                let lexed = lexer
+                    .tokenize(&rest.chars().take(error_start).collect::<String>())
+                    .unwrap_or_default();
+
+                (lexed, Some((error_type, error_start, position.len)))
+            }
+        };
+
+        tokens.extend(lexed.iter().filter(|t| t.word != "\n").map(|t| {
+            (
+                Token(t.word.clone()),
+                SimpleSpan::new(start + t.start, start + t.start + t.word.chars().count()),
+            )
+        }));
+
+        let Some((error_type, error_start, length)) = error else {
+            break;
+        };
+
+        let message = match error_type {
+            LexerErrorType::Singleline => "Region can't span multiple lines",
+            LexerErrorType::Unclosed => "Unclosed region",
+        };
+        let error_start = start + error_start;
+        errors.push((
+            message.to_string(),
+            SimpleSpan::new(error_start, error_start + length.max(1)),
+        ));
+
+        match input
+            .chars()
+            .skip(error_start)
+            .position(|char| char == '\n')
+        {
+            Some(line_end) => start = error_start + line_end + 1,
+            None => break,
+        }
+    }
+
+    LexerResponse { tokens, errors }
 }
 
 #[derive(PartialEq)]
@@ -63,6 +163,16 @@ pub enum CommandModifier {
     Silent,
 }
 
+impl fmt::Display for CommandModifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandModifier::Unsafe => write!(f, "unsafe"),
+            CommandModifier::Trust => write!(f, "trust"),
+            CommandModifier::Silent => write!(f, "silent"),
+        }
+    }
+}
+
 #[derive(PartialEq, Debug, Clone, Eq)]
 pub enum CompilerFlag {
     AllowNestedIfElse,
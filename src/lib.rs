@@ -1,8 +1,17 @@
 pub mod analysis;
+pub mod analyzer;
 pub mod backend;
+pub mod check;
+pub mod config;
+pub mod diagnostics;
 pub mod files;
+pub mod format;
 pub mod fs;
 pub mod grammar;
+pub mod panic;
 pub mod paths;
+pub mod project;
+pub mod record;
 pub mod stdlib;
+pub mod trace;
 pub mod utils;
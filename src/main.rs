@@ -1,13 +1,32 @@
 use std::{
-    env::temp_dir,
-    process::{Command, Stdio},
+    env::{current_dir, temp_dir},
+    io::{self, stdin, Read},
+    net::Ipv4Addr,
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+    sync::Arc,
 };
 
-use amber_lsp::backend::{AmberVersion, Backend};
-use clap::{builder::PossibleValue, Parser, ValueEnum};
-use tower_lsp_server::{LspService, Server};
-use tracing::subscriber;
-use tracing_subscriber::fmt::format::FmtSpan;
+use amber_lsp::{
+    analyzer::{AmberVersion, Analyzer},
+    backend::Backend,
+    check::{check_paths, format_report, has_errors, OutputFormat},
+    format::{format_diff, format_document, DEFAULT_INDENT},
+    fs::{find_amber_files, LocalFs, MemoryFS, FS},
+    panic::{install_panic_hook, CatchPanic},
+    record::{read_recording, replay, Recorder, RecordingReader, RecordingWriter},
+    stdlib::find_installed_stdlib,
+    trace::{LogTraceLayer, TraceSessions},
+};
+use clap::{builder::PossibleValue, Parser, Subcommand, ValueEnum};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, TcpStream},
+};
+use tower_lsp_server::{Client, LspService, Server};
+use tracing_subscriber::{
+    fmt::format::FmtSpan, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter,
+};
 
 #[derive(Clone, Debug, PartialEq)]
 enum CliAmberVersion {
@@ -20,7 +39,7 @@ enum CliAmberVersion {
 impl From<CliAmberVersion> for AmberVersion {
     fn from(val: CliAmberVersion) -> Self {
         match val {
-            CliAmberVersion::Auto => AmberVersion::Alpha034,
+            CliAmberVersion::Auto => detect_amber_version(),
             CliAmberVersion::Alpha034 => AmberVersion::Alpha034,
             CliAmberVersion::Alpha035 => AmberVersion::Alpha035,
             CliAmberVersion::Alpha040 => AmberVersion::Alpha040,
@@ -52,18 +71,117 @@ impl ValueEnum for CliAmberVersion {
 #[command(version, about, long_about = None)]
 struct Args {
     /// Version of the Amber language to use.
-    #[arg(value_enum, long, short, default_value = "auto")]
+    #[arg(value_enum, long, short, default_value = "auto", global = true)]
     amber_version: CliAmberVersion,
+
+    /// Listen for clients on a TCP port of the local host, instead of using the standard input
+    /// and output. Each connection gets its own session, sharing the analysis of files.
+    #[arg(long, value_name = "PORT", conflicts_with_all = ["connect", "pipe"])]
+    listen: Option<u16>,
+
+    /// Connect to a client listening on a TCP address.
+    #[arg(long, value_name = "HOST:PORT", conflicts_with = "pipe")]
+    connect: Option<String>,
+
+    /// Connect to a client listening on a Unix domain socket, or a named pipe on Windows.
+    #[arg(long, value_name = "PATH")]
+    pipe: Option<PathBuf>,
+
+    /// Record all messages of the session to a JSONL file, that can be replayed with the `replay`
+    /// command.
+    #[arg(long, value_name = "FILE", conflicts_with = "listen")]
+    record: Option<PathBuf>,
+
+    /// Level of logged events, or filter directives in the format of `RUST_LOG`,
+    /// which is used when the option is not given.
+    #[arg(long, value_name = "LEVEL", global = true)]
+    log_level: Option<String>,
+
+    /// File to write logs to, instead of an hourly rotated file in the temporary directory.
+    #[arg(long, value_name = "PATH", global = true)]
+    log_file: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Analyze files without starting the language server, and exit with an error code if any
+    /// of them has errors.
+    Check {
+        /// Files to check, or directories to search for Amber files.
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Format of the reported diagnostics.
+        #[arg(value_enum, long, default_value = "text")]
+        format: OutputFormat,
+    },
+    /// Format files in place, or the standard input when no files are given.
+    Fmt {
+        /// Files to format, or directories to search for Amber files.
+        paths: Vec<PathBuf>,
+
+        /// Don't write the files, print a diff and exit with an error code if any of them
+        /// is not formatted.
+        #[arg(long)]
+        check: bool,
+    },
+    /// Replay a session recorded with `--record`, and exit with an error code if any response
+    /// differs from the recorded one.
+    Replay {
+        /// Recording to replay.
+        file: PathBuf,
+    },
+}
+
+/// Settings shared by the sessions of the server.
+#[derive(Clone)]
+struct ServerOptions {
+    amber_version: AmberVersion,
+    is_detected: bool,
+    trace_sessions: TraceSessions,
+    recorder: Option<Arc<Recorder>>,
+}
+
+fn create_analyzer(amber_version: AmberVersion, is_detected: bool) -> Analyzer {
+    let analyzer = Analyzer::new(amber_version, Arc::new(LocalFs::new()))
+        .with_installed_stdlib(find_installed_stdlib());
+
+    if is_detected {
+        analyzer.with_detected_version()
+    } else {
+        analyzer
+    }
 }
 
 #[tokio::main]
 async fn main() {
-    let cache_dir = temp_dir().join("amber-lsp");
-    let file_appender = tracing_appender::rolling::hourly(cache_dir, "amber-lsp.log");
+    let args = Args::parse();
+
+    let file_appender = match &args.log_file {
+        Some(path) => tracing_appender::rolling::never(
+            path.parent().unwrap_or(Path::new(".")),
+            path.file_name().unwrap_or("amber-lsp.log".as_ref()),
+        ),
+        None => tracing_appender::rolling::hourly(temp_dir().join("amber-lsp"), "amber-lsp.log"),
+    };
     let (non_blocking_writer, _guard) = tracing_appender::non_blocking(file_appender);
 
-    // construct a subscriber that prints formatted traces to stdout
-    let subscriber = tracing_subscriber::fmt()
+    // `--log-level` takes precedence over `RUST_LOG`
+    let filter = match &args.log_level {
+        Some(level) => EnvFilter::try_new(level).unwrap_or_else(|err| {
+            eprintln!("Invalid log level `{level}`: {err}");
+            process::exit(2);
+        }),
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+    };
+
+    let trace_sessions = TraceSessions::new();
+
+    // construct a layer that prints formatted traces to the log file
+    let fmt_layer = tracing_subscriber::fmt::layer()
         // Use a more compact, abbreviated log format
         .compact()
         // Display source code file paths
@@ -79,26 +197,276 @@ async fn main() {
         // log to a file
         .with_writer(non_blocking_writer)
         // Disabled ANSI color codes for better compatibility with some terminals
-        .with_ansi(false)
-        // Build the subscriber
-        .finish();
+        .with_ansi(false);
 
     // use that subscriber to process traces emitted after this point
-    subscriber::set_global_default(subscriber).expect("Could not set global default subscriber");
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt_layer)
+        // forward traces to clients, that asked for them with `$/setTrace`
+        .with(LogTraceLayer::new(trace_sessions.clone()))
+        .try_init()
+        .expect("Could not set global default subscriber");
 
-    let args = Args::parse();
+    install_panic_hook();
 
-    let stdin = tokio::io::stdin();
-    let stdout = tokio::io::stdout();
+    let is_detected = args.amber_version == CliAmberVersion::Auto;
 
-    let amber_version = if args.amber_version == CliAmberVersion::Auto {
-        detect_amber_version()
-    } else {
-        args.amber_version.into()
+    match args.command {
+        Some(Commands::Check { paths, format }) => {
+            if !check(&paths, format, args.amber_version.into(), is_detected).await {
+                process::exit(1);
+            }
+        }
+        Some(Commands::Fmt { paths, check }) => {
+            if !fmt(&paths, check).await {
+                process::exit(1);
+            }
+        }
+        Some(Commands::Replay { file }) => {
+            if !replay_file(&file, args.amber_version.into()).await {
+                process::exit(1);
+            }
+        }
+        None => {
+            let recorder = match args.record.as_deref().map(Recorder::create).transpose() {
+                Ok(recorder) => recorder.map(Arc::new),
+                Err(err) => {
+                    eprintln!("Could not create the recording: {err}");
+                    process::exit(1);
+                }
+            };
+
+            let options = ServerOptions {
+                amber_version: args.amber_version.into(),
+                is_detected,
+                trace_sessions,
+                recorder,
+            };
+
+            let result = if let Some(port) = args.listen {
+                listen(port, &options).await
+            } else if let Some(address) = args.connect {
+                connect(&address, &options).await
+            } else if let Some(path) = args.pipe {
+                connect_pipe(&path, &options).await
+            } else {
+                serve(
+                    tokio::io::stdin(),
+                    tokio::io::stdout(),
+                    new_analyzer(&options),
+                    &options,
+                )
+                .await;
+                Ok(())
+            };
+
+            if let Err(err) = result {
+                eprintln!("{err}");
+                process::exit(1);
+            }
+        }
+    }
+}
+
+/// Creates the analyzer with the settings of the server.
+fn new_analyzer(options: &ServerOptions) -> Arc<Analyzer> {
+    Arc::new(create_analyzer(
+        options.amber_version.clone(),
+        options.is_detected,
+    ))
+}
+
+fn create_backend(client: Client, analyzer: Arc<Analyzer>, options: &ServerOptions) -> Backend {
+    let backend = Backend::with_analyzer(client, analyzer);
+    options.trace_sessions.register(&backend.trace);
+
+    backend
+}
+
+async fn serve<I, O>(input: I, output: O, analyzer: Arc<Analyzer>, options: &ServerOptions)
+where
+    I: AsyncRead + Unpin,
+    O: AsyncWrite + Unpin,
+{
+    let (service, socket) = LspService::build(|client| create_backend(client, analyzer, options))
+        .custom_method("amber/stdlibContent", Backend::stdlib_content)
+        .custom_method("$/setTrace", Backend::set_trace)
+        .finish();
+
+    let input = RecordingReader::new(input, options.recorder.clone());
+    let output = RecordingWriter::new(output, options.recorder.clone());
+
+    Server::new(input, output, socket)
+        .serve(CatchPanic::new(service))
+        .await;
+}
+
+/// Serves every client connecting to the port, until the process is stopped.
+///
+/// Clients share the analyzer, so that files opened in one window are analyzed once for all of them.
+async fn listen(port: u16, options: &ServerOptions) -> io::Result<()> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port)).await?;
+    let analyzer = new_analyzer(options);
+
+    eprintln!("Listening on {}", listener.local_addr()?);
+
+    loop {
+        let (stream, address) = listener.accept().await?;
+        let analyzer = analyzer.clone();
+        let options = options.clone();
+
+        tracing::info!("Client connected from {address}");
+
+        tokio::spawn(async move {
+            let (input, output) = tokio::io::split(stream);
+            serve(input, output, analyzer, &options).await;
+
+            tracing::info!("Client from {address} disconnected");
+        });
+    }
+}
+
+async fn connect(address: &str, options: &ServerOptions) -> io::Result<()> {
+    let stream = TcpStream::connect(address).await?;
+    let (input, output) = tokio::io::split(stream);
+
+    serve(input, output, new_analyzer(options), options).await;
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn connect_pipe(path: &Path, options: &ServerOptions) -> io::Result<()> {
+    let stream = tokio::net::UnixStream::connect(path).await?;
+    let (input, output) = tokio::io::split(stream);
+
+    serve(input, output, new_analyzer(options), options).await;
+    Ok(())
+}
+
+#[cfg(windows)]
+async fn connect_pipe(path: &Path, options: &ServerOptions) -> io::Result<()> {
+    let pipe = tokio::net::windows::named_pipe::ClientOptions::new().open(path)?;
+    let (input, output) = tokio::io::split(pipe);
+
+    serve(input, output, new_analyzer(options), options).await;
+    Ok(())
+}
+
+/// Replays a recorded session with files in memory. Returns `false` if any response differs.
+async fn replay_file(file: &Path, amber_version: AmberVersion) -> bool {
+    let messages = match read_recording(file) {
+        Ok(messages) => messages,
+        Err(err) => {
+            eprintln!("Could not read the recording: {err}");
+            return false;
+        }
     };
 
-    let (service, socket) = LspService::new(|client| Backend::new(client, amber_version, None));
-    Server::new(stdin, stdout, socket).serve(service).await;
+    let mismatches = replay(&messages, |client| {
+        Backend::new(client, amber_version, Some(Arc::new(MemoryFS::new())))
+    })
+    .await;
+
+    for mismatch in mismatches.iter() {
+        println!(
+            "Response to {} (id {}) differs\n  expected: {}\n  actual:   {}",
+            mismatch.method, mismatch.id, mismatch.expected, mismatch.actual
+        );
+    }
+
+    println!(
+        "Replayed {} messages, {} responses differ",
+        messages.len(),
+        mismatches.len()
+    );
+
+    mismatches.is_empty()
+}
+
+/// Prints diagnostics of the files. Returns `false` if any of them has errors.
+async fn check(
+    paths: &[PathBuf],
+    format: OutputFormat,
+    amber_version: AmberVersion,
+    is_detected: bool,
+) -> bool {
+    let analyzer = Arc::new(create_analyzer(amber_version, is_detected));
+
+    if let Ok(dir) = current_dir() {
+        analyzer.load_workspace_config(&dir).await;
+    }
+
+    let files = check_paths(&analyzer, paths).await;
+    print!("{}", format_report(&files, format));
+
+    !has_errors(&files)
+}
+
+/// Formats the files, or the standard input. Returns `false` if a file couldn't be formatted,
+/// or if it isn't formatted in the check mode.
+async fn fmt(paths: &[PathBuf], check: bool) -> bool {
+    if paths.is_empty() {
+        let mut text = String::new();
+
+        if let Err(err) = stdin().read_to_string(&mut text) {
+            eprintln!("Could not read the standard input: {err}");
+            return false;
+        }
+
+        let formatted = format_document(&text, DEFAULT_INDENT);
+
+        if !check {
+            print!("{formatted}");
+            return true;
+        }
+
+        print!("{}", format_diff("<stdin>", &text, &formatted));
+        return formatted == text;
+    }
+
+    let fs = LocalFs::new();
+    let mut files = vec![];
+
+    for path in paths {
+        if fs.is_dir(path).await {
+            files.extend(find_amber_files(&fs, path).await);
+        } else {
+            files.push(path.clone());
+        }
+    }
+
+    let mut success = true;
+
+    for file in files {
+        let text = match fs.read(&file).await {
+            Ok(text) => text,
+            Err(err) => {
+                eprintln!("Could not read {}: {err}", file.display());
+                success = false;
+                continue;
+            }
+        };
+
+        let formatted = format_document(&text, DEFAULT_INDENT);
+
+        if formatted == text {
+            continue;
+        }
+
+        if check {
+            print!(
+                "{}",
+                format_diff(&file.to_string_lossy(), &text, &formatted)
+            );
+            success = false;
+        } else if let Err(err) = fs.write(&file, &formatted).await {
+            eprintln!("Could not write {}: {err}", file.display());
+            success = false;
+        }
+    }
+
+    success
 }
 
 #[tracing::instrument(skip_all)]
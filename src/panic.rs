@@ -0,0 +1,121 @@
+use std::{
+    any::Any,
+    backtrace::Backtrace,
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    str::FromStr,
+    task::{Context, Poll},
+};
+
+use futures::FutureExt;
+use serde_json::Value;
+use tower_lsp_server::{
+    jsonrpc::{Error, ErrorCode, Request, Response},
+    lsp_types::Uri,
+    ExitedError, LspService,
+};
+use tower_service::Service;
+
+use crate::backend::Backend;
+
+/// Logs panics with their backtrace, before they are caught by [`CatchPanic`].
+pub fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+
+    panic::set_hook(Box::new(move |info| {
+        tracing::error!("{info}\n{}", Backtrace::force_capture());
+        default_hook(info);
+    }));
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
+
+/// Language server service, that turns panics of request handlers into internal errors,
+/// so a single failing request doesn't stop the server.
+pub struct CatchPanic {
+    service: LspService<Backend>,
+}
+
+impl CatchPanic {
+    pub fn new(service: LspService<Backend>) -> Self {
+        CatchPanic { service }
+    }
+
+    pub fn inner(&self) -> &Backend {
+        self.service.inner()
+    }
+
+    /// Describes the document a request is about, with the version known to the server.
+    fn describe_document(&self, request: &Request) -> Option<String> {
+        let uri = request
+            .params()?
+            .get("textDocument")?
+            .get("uri")
+            .and_then(Value::as_str)?;
+
+        let files = &self.inner().analyzer.files;
+        let version = Uri::from_str(uri)
+            .ok()
+            .and_then(|uri| files.get(&uri))
+            .map(|file_id| files.get_latest_version(file_id));
+
+        Some(match version {
+            Some(version) => format!("{uri} (version {})", version.0),
+            None => uri.to_string(),
+        })
+    }
+}
+
+impl Service<Request> for CatchPanic {
+    type Response = Option<Response>;
+    type Error = ExitedError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let id = request.id().cloned();
+        let method = request.method().to_string();
+        let document = self.describe_document(&request);
+
+        let response = AssertUnwindSafe(self.service.call(request)).catch_unwind();
+
+        Box::pin(async move {
+            match response.await {
+                Ok(response) => response,
+                Err(panic) => {
+                    let message = panic_message(panic.as_ref());
+
+                    match &document {
+                        Some(document) => {
+                            tracing::error!("Handler of {method} panicked on {document}: {message}")
+                        }
+                        None => tracing::error!("Handler of {method} panicked: {message}"),
+                    }
+
+                    Ok(id.map(|id| {
+                        Response::from_error(
+                            id,
+                            Error {
+                                code: ErrorCode::InternalError,
+                                message: format!("Internal error in {method}: {message}").into(),
+                                data: None,
+                            },
+                        )
+                    }))
+                }
+            }
+        })
+    }
+}
@@ -1,4 +1,4 @@
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, PoisonError};
 
 use indexmap::IndexSet;
 use tower_lsp_server::lsp_types::Uri;
@@ -16,7 +16,7 @@ impl PathInterner {
     ///
     /// If `path` does not exists in `self`, returns [`None`].
     pub fn get(&self, path: &Uri) -> Option<FileId> {
-        let map = self.map.lock().unwrap();
+        let map = self.map.lock().unwrap_or_else(PoisonError::into_inner);
         map.get_index_of(path).map(FileId)
     }
 
@@ -25,7 +25,7 @@ impl PathInterner {
     /// - If `path` already exists in `self`, returns its associated id;
     /// - Else, returns a newly allocated id.
     pub fn insert(&self, path: Uri) -> FileId {
-        let mut map = self.map.lock().unwrap();
+        let mut map = self.map.lock().unwrap_or_else(PoisonError::into_inner);
         let (id, _added) = map.insert_full(path);
         FileId(id)
     }
@@ -36,7 +36,7 @@ impl PathInterner {
     ///
     /// Panics if `id` does not exists in `self`.
     pub fn lookup(&self, id: &FileId) -> Uri {
-        let map = self.map.lock().unwrap();
+        let map = self.map.lock().unwrap_or_else(PoisonError::into_inner);
         map.get_index(id.0).unwrap().clone()
     }
 }
@@ -0,0 +1,165 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    analyzer::AmberVersion,
+    config::{parse_amber_version, LintSeverity},
+    fs::FS,
+    utils::{FastDashMap, FastDashSet},
+};
+
+/// Names of project configuration files, in order of precedence.
+pub const PROJECT_CONFIG_FILES: [&str; 2] = ["amber.toml", ".amber-lsp.toml"];
+
+/// Settings of a project, checked into its repository.
+///
+/// ```toml
+/// version = "0.4.0-alpha"
+/// entrypoints = ["bin/install"]
+///
+/// [imports]
+/// paths = ["vendor"]
+/// aliases = { lib = "src/lib" }
+///
+/// [lints]
+/// type-mismatch = "warning"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case", default, deny_unknown_fields)]
+pub struct ProjectConfig {
+    /// Version of Amber used by the project.
+    pub version: Option<String>,
+    /// Scripts of the project. They are analyzed, even when their names don't end with `.ab`.
+    pub entrypoints: Vec<PathBuf>,
+    pub imports: ImportsConfig,
+    /// Lint levels, keyed by diagnostic code.
+    pub lints: HashMap<String, LintSeverity>,
+    /// Directory of the configuration file. Paths in the configuration are relative to it.
+    #[serde(skip)]
+    pub root: PathBuf,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case", default, deny_unknown_fields)]
+pub struct ImportsConfig {
+    /// Directories, where imports are looked up, when they can't be found relative to the importing file.
+    pub paths: Vec<PathBuf>,
+    /// Import path prefixes, that are replaced with the given directories.
+    pub aliases: HashMap<String, PathBuf>,
+}
+
+impl ProjectConfig {
+    pub fn parse(text: &str, root: PathBuf) -> Result<Self, toml::de::Error> {
+        let config = toml::from_str::<ProjectConfig>(text)?;
+
+        Ok(ProjectConfig { root, ..config })
+    }
+
+    pub fn amber_version(&self) -> Option<AmberVersion> {
+        self.version.as_deref().and_then(parse_amber_version)
+    }
+
+    pub fn entrypoints(&self) -> Vec<PathBuf> {
+        self.entrypoints
+            .iter()
+            .map(|path| self.root.join(path))
+            .collect()
+    }
+
+    /// Resolves an import path, that starts with one of the aliases.
+    pub fn resolve_alias(&self, path: &str) -> Option<PathBuf> {
+        let (alias, rest) = match path.split_once('/') {
+            Some((alias, rest)) => (alias, rest),
+            None => (path, ""),
+        };
+
+        self.imports
+            .aliases
+            .get(alias)
+            .map(|target| self.root.join(target).join(rest))
+    }
+
+    /// Returns locations in the import search paths, where the imported file could be.
+    pub fn import_candidates(&self, path: &str) -> Vec<PathBuf> {
+        self.imports
+            .paths
+            .iter()
+            .map(|import_path| self.root.join(import_path).join(path))
+            .collect()
+    }
+}
+
+/// Cache of project configuration files, keyed by their path.
+///
+/// Configuration files, that failed to parse, are stored as `None`.
+#[derive(Debug, Default)]
+pub struct ProjectConfigs {
+    configs: FastDashMap<PathBuf, Option<Arc<ProjectConfig>>>,
+    /// Paths, where a configuration file was looked up, but didn't exist.
+    missing: FastDashSet<PathBuf>,
+}
+
+impl ProjectConfigs {
+    /// Finds the configuration file closest to `dir`, walking up the directory tree.
+    pub async fn find(&self, fs: &dyn FS, dir: &Path) -> Option<PathBuf> {
+        for dir in dir.ancestors() {
+            for name in PROJECT_CONFIG_FILES {
+                let path = dir.join(name);
+
+                if self.configs.contains_key(&path) {
+                    return Some(path);
+                }
+
+                if self.missing.contains(&path) {
+                    continue;
+                }
+
+                if fs.exists(&path).await {
+                    return Some(path);
+                }
+
+                self.missing.insert(path);
+            }
+        }
+
+        None
+    }
+
+    /// Returns the loaded configuration closest to `dir`, without accessing the file system.
+    pub fn get_cached(&self, dir: &Path) -> Option<Arc<ProjectConfig>> {
+        for dir in dir.ancestors() {
+            for name in PROJECT_CONFIG_FILES {
+                if let Some(config) = self.configs.get(&dir.join(name)) {
+                    return config.clone();
+                }
+            }
+        }
+
+        None
+    }
+
+    pub fn get(&self, path: &Path) -> Option<Option<Arc<ProjectConfig>>> {
+        self.configs.get(path).map(|config| config.clone())
+    }
+
+    pub fn insert(&self, path: PathBuf, config: Option<Arc<ProjectConfig>>) {
+        self.configs.insert(path, config);
+    }
+
+    /// Forgets a configuration file, e.g. after it was created, changed or deleted.
+    pub fn remove(&self, path: &Path) {
+        self.configs.remove(path);
+        self.missing.remove(path);
+    }
+}
+
+pub fn is_project_config_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| PROJECT_CONFIG_FILES.contains(&name))
+}
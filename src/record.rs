@@ -0,0 +1,374 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    future::poll_fn,
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError},
+    task::{Context, Poll},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tower_lsp_server::{
+    jsonrpc::{Request, Response},
+    Client, LspService,
+};
+use tower_service::Service;
+
+use crate::{backend::Backend, panic::CatchPanic};
+
+/// Side of the connection, that sent a message.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Sender {
+    Client,
+    Server,
+}
+
+/// A line of a recording.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RecordedMessage {
+    /// Milliseconds since the start of the recording.
+    pub time: u64,
+    pub from: Sender,
+    pub message: Value,
+}
+
+/// Writes JSON-RPC messages of a session to a JSONL file.
+#[derive(Debug)]
+pub struct Recorder {
+    file: Mutex<File>,
+    start: Instant,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut file = File::create(path)?;
+
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis())
+            .unwrap_or_default();
+        writeln!(
+            file,
+            "{}",
+            serde_json::json!({
+                "started_at": started_at,
+                "version": env!("CARGO_PKG_VERSION"),
+            })
+        )?;
+
+        Ok(Recorder {
+            file: Mutex::new(file),
+            start: Instant::now(),
+        })
+    }
+
+    fn record(&self, from: Sender, body: &[u8]) {
+        let message = serde_json::from_slice(body)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(body).to_string()));
+        let line = RecordedMessage {
+            time: self.start.elapsed().as_millis() as u64,
+            from,
+            message,
+        };
+
+        let mut file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if let Err(err) = write_line(&mut file, &line) {
+            tracing::error!("Could not record a message: {err}");
+        }
+    }
+}
+
+/// Writes a line of the recording. The file is flushed after every line,
+/// so recordings of crashed sessions are complete.
+fn write_line(file: &mut File, line: &RecordedMessage) -> io::Result<()> {
+    serde_json::to_writer(&mut *file, line)?;
+    file.write_all(b"\n")?;
+    file.flush()
+}
+
+/// Splits a byte stream into bodies of `Content-Length` framed messages.
+#[derive(Debug, Default)]
+struct MessageFramer {
+    buffer: Vec<u8>,
+}
+
+impl MessageFramer {
+    fn push(&mut self, bytes: &[u8]) -> Vec<Vec<u8>> {
+        self.buffer.extend_from_slice(bytes);
+
+        let mut messages = vec![];
+
+        while let Some(headers_end) = self
+            .buffer
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+        {
+            let headers = String::from_utf8_lossy(&self.buffer[..headers_end]);
+            let content_length = headers.lines().find_map(|header| {
+                let (name, value) = header.split_once(':')?;
+
+                if !name.trim().eq_ignore_ascii_case("Content-Length") {
+                    return None;
+                }
+
+                value.trim().parse::<usize>().ok()
+            });
+
+            let body_start = headers_end + 4;
+
+            let Some(content_length) = content_length else {
+                // Not a message header, skip it
+                self.buffer.drain(..body_start);
+                continue;
+            };
+
+            if self.buffer.len() < body_start + content_length {
+                break;
+            }
+
+            messages.push(self.buffer[body_start..body_start + content_length].to_vec());
+            self.buffer.drain(..body_start + content_length);
+        }
+
+        messages
+    }
+}
+
+/// Reader, that records messages read from the client.
+pub struct RecordingReader<R> {
+    inner: R,
+    recorder: Option<Arc<Recorder>>,
+    framer: MessageFramer,
+}
+
+impl<R> RecordingReader<R> {
+    pub fn new(inner: R, recorder: Option<Arc<Recorder>>) -> Self {
+        RecordingReader {
+            inner,
+            recorder,
+            framer: MessageFramer::default(),
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for RecordingReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+
+        let poll = Pin::new(&mut this.inner).poll_read(cx, buf);
+
+        if let (Poll::Ready(Ok(())), Some(recorder)) = (&poll, &this.recorder) {
+            for message in this.framer.push(&buf.filled()[filled..]) {
+                recorder.record(Sender::Client, &message);
+            }
+        }
+
+        poll
+    }
+}
+
+/// Writer, that records messages sent to the client.
+pub struct RecordingWriter<W> {
+    inner: W,
+    recorder: Option<Arc<Recorder>>,
+    framer: MessageFramer,
+}
+
+impl<W> RecordingWriter<W> {
+    pub fn new(inner: W, recorder: Option<Arc<Recorder>>) -> Self {
+        RecordingWriter {
+            inner,
+            recorder,
+            framer: MessageFramer::default(),
+        }
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for RecordingWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        let poll = Pin::new(&mut this.inner).poll_write(cx, buf);
+
+        if let (Poll::Ready(Ok(written)), Some(recorder)) = (&poll, &this.recorder) {
+            for message in this.framer.push(&buf[..*written]) {
+                recorder.record(Sender::Server, &message);
+            }
+        }
+
+        poll
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+/// Reads messages of a recording, skipping its header.
+pub fn read_recording(path: &Path) -> io::Result<Vec<RecordedMessage>> {
+    let file = BufReader::new(File::open(path)?);
+    let mut messages = vec![];
+
+    for line in file.lines() {
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if let Ok(message) = serde_json::from_str::<RecordedMessage>(&line) {
+            messages.push(message);
+        }
+    }
+
+    Ok(messages)
+}
+
+/// Response of the replayed server, that differs from the recorded one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayMismatch {
+    pub id: Value,
+    pub method: String,
+    pub expected: Value,
+    pub actual: Value,
+}
+
+/// Result and error of a response, without the fields that don't depend on the server.
+fn response_outcome(response: &Value) -> Value {
+    serde_json::json!({
+        "result": response.get("result").cloned().unwrap_or(Value::Null),
+        "error": response.get("error").cloned().unwrap_or(Value::Null),
+    })
+}
+
+/// Sends messages of the client from a recording to a new server,
+/// and compares its responses to the recorded ones.
+///
+/// Requests of the server are answered with the recorded responses of the client.
+pub async fn replay<F>(messages: &[RecordedMessage], create_backend: F) -> Vec<ReplayMismatch>
+where
+    F: FnOnce(Client) -> Backend,
+{
+    let (service, socket) = LspService::build(create_backend)
+        .custom_method("amber/stdlibContent", Backend::stdlib_content)
+        .custom_method("$/setTrace", Backend::set_trace)
+        .finish();
+    let mut service = CatchPanic::new(service);
+
+    let client_responses = messages
+        .iter()
+        .filter(|message| message.from == Sender::Client)
+        .filter(|message| message.message.get("method").is_none())
+        .filter_map(|message| {
+            Some((
+                message.message.get("id")?.to_string(),
+                message.message.clone(),
+            ))
+        })
+        .collect::<HashMap<String, Value>>();
+
+    let server_responses = messages
+        .iter()
+        .filter(|message| message.from == Sender::Server)
+        .filter(|message| message.message.get("method").is_none())
+        .filter_map(|message| {
+            Some((
+                message.message.get("id")?.to_string(),
+                message.message.clone(),
+            ))
+        })
+        .collect::<HashMap<String, Value>>();
+
+    let (mut requests, mut responses) = socket.split();
+
+    let client = tokio::spawn(async move {
+        while let Some(request) = requests.next().await {
+            let Some(id) = request.id().cloned() else {
+                continue;
+            };
+
+            let response = serde_json::to_string(&id)
+                .ok()
+                .and_then(|id| client_responses.get(&id))
+                .and_then(|response| serde_json::from_value::<Response>(response.clone()).ok())
+                .unwrap_or_else(|| Response::from_ok(id, Value::Null));
+
+            if responses.send(response).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut mismatches = vec![];
+
+    for message in messages
+        .iter()
+        .filter(|message| message.from == Sender::Client)
+    {
+        let Some(method) = message.message.get("method").and_then(Value::as_str) else {
+            continue;
+        };
+
+        let Ok(request) = serde_json::from_value::<Request>(message.message.clone()) else {
+            tracing::warn!("Could not replay a message: {}", message.message);
+            continue;
+        };
+
+        if poll_fn(|cx| service.poll_ready(cx)).await.is_err() {
+            break;
+        }
+
+        let response = service.call(request).await;
+
+        let Some(id) = message.message.get("id") else {
+            continue;
+        };
+
+        let actual = match response {
+            Ok(Some(response)) => serde_json::to_value(&response).unwrap_or(Value::Null),
+            _ => Value::Null,
+        };
+
+        let Some(expected) = server_responses.get(&id.to_string()) else {
+            continue;
+        };
+
+        if response_outcome(expected) != response_outcome(&actual) {
+            mismatches.push(ReplayMismatch {
+                id: id.clone(),
+                method: method.to_string(),
+                expected: expected.clone(),
+                actual,
+            });
+        }
+
+        if method == "shutdown" {
+            break;
+        }
+    }
+
+    client.abort();
+
+    mismatches
+}
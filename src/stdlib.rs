@@ -1,142 +1,299 @@
 use std::{
-    env::current_exe,
-    future::Future,
+    env,
+    fmt::{self, Display},
     path::{Path, PathBuf},
-    pin::Pin,
+    process::{Command, Stdio},
+    str::FromStr,
+    sync::PoisonError,
 };
 
 use clap::builder::OsStr;
-use include_dir::{include_dir, Dir, DirEntry};
+use include_dir::{include_dir, Dir};
 use tower_lsp_server::{lsp_types::Uri, UriExt};
 use tracing::warn;
 
-use crate::backend::{AmberVersion, Backend};
+use crate::{
+    analyzer::{AmberVersion, Analyzer},
+    config::{amber_version_name, parse_amber_version},
+};
 
 pub const STDLIB: Dir = include_dir!("$CARGO_MANIFEST_DIR/resources/");
 
-fn get_stdlib_dir(amber_version: AmberVersion) -> Result<PathBuf, std::io::Error> {
-    let amber_subdir = match amber_version {
+/// Scheme of URIs of the bundled standard library, e.g. `amber-std:/alpha040/std/text.ab`.
+///
+/// Contents of these files are served by the `amber/stdlibContent` request.
+pub const STDLIB_SCHEME: &str = "amber-std";
+
+const AMBER_VERSIONS: [AmberVersion; 3] = [
+    AmberVersion::Alpha034,
+    AmberVersion::Alpha035,
+    AmberVersion::Alpha040,
+];
+
+fn get_stdlib_subdir(amber_version: &AmberVersion) -> &'static str {
+    match amber_version {
         AmberVersion::Alpha034 => "alpha034",
         AmberVersion::Alpha035 => "alpha035",
         AmberVersion::Alpha040 => "alpha040",
-    };
+    }
+}
 
-    Ok(current_exe()?
-        .parent()
-        .unwrap()
-        .to_path_buf()
-        .join("amber-lsp-resources")
-        .join(amber_subdir))
+/// Standard library shipped with the `amber` binary found on `PATH`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstalledStdlib {
+    pub amber_version: AmberVersion,
+    /// Directory, that contains the `std` directory.
+    pub dir: PathBuf,
 }
 
-#[tracing::instrument]
-pub fn is_builtin_file(uri: &Uri, amber_version: AmberVersion) -> bool {
-    let builtin_file_path = match get_stdlib_dir(amber_version) {
-        Ok(dir) => dir,
-        Err(_) => {
-            return false;
+/// Where files of the standard library are read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StdlibSource {
+    /// Directory set with the `stdlibPath` setting.
+    Configured(PathBuf),
+    /// Standard library of the installed compiler.
+    Installed(PathBuf),
+    /// Copy embedded in the server.
+    Bundled(AmberVersion),
+}
+
+impl StdlibSource {
+    pub fn dir(&self) -> Option<&Path> {
+        match self {
+            StdlibSource::Configured(dir) | StdlibSource::Installed(dir) => Some(dir),
+            StdlibSource::Bundled(_) => None,
         }
     }
-    .join("builtin.ab")
-    .canonicalize();
+}
 
-    let file_path = match uri.to_file_path() {
-        Some(path) => path.canonicalize(),
-        None => {
-            return false;
+impl Display for StdlibSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StdlibSource::Configured(dir) => {
+                write!(f, "Standard library from `stdlibPath`: `{}`", dir.display())
+            }
+            StdlibSource::Installed(dir) => {
+                write!(
+                    f,
+                    "Standard library of the installed Amber: `{}`",
+                    dir.display()
+                )
+            }
+            StdlibSource::Bundled(amber_version) => write!(
+                f,
+                "Standard library bundled with the server (Amber {})",
+                amber_version_name(amber_version)
+            ),
         }
-    };
-
-    if builtin_file_path.is_err() || file_path.is_err() {
-        return false;
     }
+}
 
-    file_path.unwrap() == builtin_file_path.unwrap()
+/// Finds the standard library, that belongs to the `amber` binary on `PATH`.
+#[tracing::instrument]
+pub fn find_installed_stdlib() -> Option<InstalledStdlib> {
+    let binary = env::split_paths(&env::var_os("PATH")?)
+        .flat_map(|dir| [dir.join("amber"), dir.join("amber.exe")])
+        .find(|path| path.is_file())?
+        .canonicalize()
+        .ok()?;
+
+    let output = Command::new(&binary)
+        .arg("-V")
+        .stdout(Stdio::piped())
+        .output()
+        .ok()?;
+    let amber_version = parse_amber_version(
+        String::from_utf8_lossy(&output.stdout)
+            .split_whitespace()
+            .last()?,
+    )?;
+
+    let binary_dir = binary.parent()?;
+    let dir = [
+        binary_dir.to_path_buf(),
+        binary_dir.join("..").join("lib").join("amber"),
+        binary_dir.join("..").join("share").join("amber"),
+    ]
+    .into_iter()
+    .find(|dir| dir.join("std").is_dir())?;
+
+    Some(InstalledStdlib {
+        amber_version,
+        dir: dir.canonicalize().unwrap_or(dir),
+    })
 }
 
-#[tracing::instrument(skip_all)]
-pub async fn save_resources(backend: &Backend) -> PathBuf {
-    let stdlib_dir = get_stdlib_dir(backend.amber_version.clone()).unwrap();
+/// Returns where the standard library of the Amber version is read from.
+pub fn get_stdlib_source(analyzer: &Analyzer, amber_version: &AmberVersion) -> StdlibSource {
+    if let Some(stdlib_path) = analyzer
+        .config
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .stdlib_path
+        .clone()
+    {
+        return StdlibSource::Configured(stdlib_path);
+    }
 
-    let binary_stdlib_dir = match backend.amber_version {
-        AmberVersion::Alpha034 => "alpha034/".to_string(),
-        AmberVersion::Alpha035 => "alpha035/".to_string(),
-        AmberVersion::Alpha040 => "alpha040/".to_string(),
-    };
+    match &analyzer.installed_stdlib {
+        Some(installed) if installed.amber_version == *amber_version => {
+            StdlibSource::Installed(installed.dir.clone())
+        }
+        _ => StdlibSource::Bundled(amber_version.clone()),
+    }
+}
 
-    let _ = backend.files.fs.create_dir_all(&stdlib_dir).await;
+/// Returns where the file of the standard library comes from,
+/// or `None` if the file is not part of the standard library.
+pub fn get_file_stdlib_source(analyzer: &Analyzer, uri: &Uri) -> Option<StdlibSource> {
+    if let Some(amber_version) = get_bundled_version(uri) {
+        return Some(StdlibSource::Bundled(amber_version));
+    }
 
-    if let Some(dir) = STDLIB.get_dir(binary_stdlib_dir) {
-        for entry in dir.entries() {
-            save_entry(backend, &stdlib_dir, entry).await;
+    let path = uri.to_file_path()?;
+
+    if let Some(stdlib_path) = analyzer
+        .config
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .stdlib_path
+        .clone()
+    {
+        if path.starts_with(&stdlib_path) {
+            return Some(StdlibSource::Configured(stdlib_path));
         }
     }
 
-    stdlib_dir
+    match &analyzer.installed_stdlib {
+        Some(installed) if path.starts_with(&installed.dir) => {
+            Some(StdlibSource::Installed(installed.dir.clone()))
+        }
+        _ => None,
+    }
 }
 
-fn save_entry<'a>(
-    backend: &'a Backend,
-    current_path: &'a Path,
-    entry: &'a DirEntry<'a>,
-) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>> {
-    Box::pin(async move {
-        match entry {
-            DirEntry::Dir(dir) => {
-                let path = current_path.join(dir.path().file_name().unwrap());
+pub fn is_stdlib_uri(uri: &Uri) -> bool {
+    uri.scheme().as_str() == STDLIB_SCHEME
+}
 
-                let _ = backend.files.fs.create_dir_all(&path).await;
-                for entry in dir.entries() {
-                    save_entry(backend, &path, entry).await;
-                }
-            }
-            DirEntry::File(file) => {
-                let path = current_path.join(file.path().file_name().unwrap());
+/// Returns the path of a bundled file inside [`STDLIB`].
+fn get_stdlib_path(uri: &Uri) -> Option<String> {
+    if !is_stdlib_uri(uri) {
+        return None;
+    }
 
-                if path.exists() {
-                    return;
-                }
+    Some(uri.path().as_str().trim_start_matches('/').to_string())
+}
 
-                let contents = file.contents_utf8().unwrap().to_string();
+fn make_stdlib_uri(path: &str) -> Option<Uri> {
+    Uri::from_str(&format!("{STDLIB_SCHEME}:/{path}")).ok()
+}
+
+/// Returns the contents of a file of the bundled standard library.
+pub fn read_stdlib_file(uri: &Uri) -> Option<&'static str> {
+    STDLIB.get_file(get_stdlib_path(uri)?)?.contents_utf8()
+}
 
-                backend.files.fs.write(&path, &contents).await.unwrap();
+/// Resolves a path relative to a file of the bundled standard library.
+pub fn join_stdlib_uri(uri: &Uri, path: &str) -> Option<Uri> {
+    let mut parts = get_stdlib_path(uri)?
+        .split('/')
+        .map(|part| part.to_string())
+        .collect::<Vec<String>>();
+    parts.pop();
+
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
             }
+            _ => parts.push(part.to_string()),
         }
-    })
+    }
+
+    make_stdlib_uri(&parts.join("/"))
 }
 
-#[tracing::instrument(skip_all)]
-pub async fn resolve(backend: &Backend, path: String) -> Option<Uri> {
-    let file_path = path + ".ab";
+fn get_bundled_version(uri: &Uri) -> Option<AmberVersion> {
+    let path = get_stdlib_path(uri)?;
+    let subdir = path.split('/').next()?;
+
+    AMBER_VERSIONS
+        .into_iter()
+        .find(|amber_version| get_stdlib_subdir(amber_version) == subdir)
+}
 
-    let memory_path = match backend.amber_version {
-        AmberVersion::Alpha034 => PathBuf::from("alpha034"),
-        AmberVersion::Alpha035 => PathBuf::from("alpha035"),
-        AmberVersion::Alpha040 => PathBuf::from("alpha040"),
+/// Returns the version of Amber, whose standard library contains the file.
+/// Files of the directory set with `stdlibPath` don't belong to any version.
+pub fn get_stdlib_version(analyzer: &Analyzer, uri: &Uri) -> Option<AmberVersion> {
+    match get_file_stdlib_source(analyzer, uri)? {
+        StdlibSource::Bundled(amber_version) => Some(amber_version),
+        StdlibSource::Installed(_) => analyzer
+            .installed_stdlib
+            .as_ref()
+            .map(|installed| installed.amber_version.clone()),
+        StdlibSource::Configured(_) => None,
     }
-    .join(file_path.clone());
+}
 
-    if !STDLIB.contains(memory_path.clone()) {
-        warn!(
-            "File not found in stdlib: {}",
-            memory_path.clone().to_str().unwrap()
-        );
-        return None;
+#[tracing::instrument(skip(analyzer))]
+pub fn is_builtin_file(uri: &Uri, analyzer: &Analyzer, amber_version: &AmberVersion) -> bool {
+    if get_stdlib_path(uri) == Some(format!("{}/builtin.ab", get_stdlib_subdir(amber_version))) {
+        return true;
     }
 
-    let base_path = save_resources(backend).await;
+    let stdlib_dir = match get_stdlib_source(analyzer, amber_version).dir() {
+        Some(dir) => dir.to_path_buf(),
+        None => return false,
+    };
+
+    let Ok(builtin_file_path) = stdlib_dir.join("builtin.ab").canonicalize() else {
+        return false;
+    };
+
+    let Some(Ok(file_path)) = uri.to_file_path().map(|path| path.canonicalize()) else {
+        return false;
+    };
+
+    file_path == builtin_file_path
+}
+
+#[tracing::instrument(skip(analyzer))]
+pub async fn resolve(
+    analyzer: &Analyzer,
+    path: String,
+    amber_version: &AmberVersion,
+) -> Option<Uri> {
+    let file_path = path + ".ab";
+
+    // The bundled standard library is used for files missing in the preferred one
+    if let Some(stdlib_dir) = get_stdlib_source(analyzer, amber_version).dir() {
+        let stdlib_file_path = stdlib_dir.join(&file_path);
+
+        if analyzer.files.fs.exists(&stdlib_file_path).await {
+            return Uri::from_file_path(stdlib_file_path);
+        }
+    }
 
-    let file_path = base_path.join(file_path);
+    let memory_path = format!("{}/{}", get_stdlib_subdir(amber_version), file_path);
 
-    warn!("File found in resources: {}", file_path.to_str().unwrap());
+    if !STDLIB.contains(&memory_path) {
+        warn!("File not found in stdlib: {}", memory_path);
+        return None;
+    }
 
-    Uri::from_file_path(file_path)
+    make_stdlib_uri(&memory_path)
 }
 
-pub async fn find_in_stdlib(backend: &Backend, path: &str) -> Vec<String> {
+pub async fn find_in_stdlib(
+    analyzer: &Analyzer,
+    path: &str,
+    amber_version: &AmberVersion,
+) -> Vec<String> {
     let parts = path.split('/').collect::<Vec<&str>>();
 
-    match backend.amber_version {
+    match amber_version {
         AmberVersion::Alpha034 => {
             vec!["std".to_string()]
         }
@@ -149,30 +306,50 @@ pub async fn find_in_stdlib(backend: &Backend, path: &str) -> Vec<String> {
                 return vec![];
             }
 
-            let stdlib_dir = save_resources(backend).await;
+            let stdlib_dir = get_stdlib_source(analyzer, amber_version)
+                .dir()
+                .map(|dir| dir.to_path_buf());
+            let mut entries = vec![];
+            if let Some(stdlib_dir) = &stdlib_dir {
+                let fs = &analyzer.files.fs;
 
-            let path_in_std = stdlib_dir.clone().join(parts.join("/"));
+                for path in fs.read_dir(&stdlib_dir.join(parts.join("/"))).await {
+                    entries.push((fs.is_dir(&path).await, path));
+                }
+            }
+
+            let (stdlib_dir, entries) = match stdlib_dir {
+                Some(stdlib_dir) if !entries.is_empty() => (stdlib_dir, entries),
+                _ => {
+                    let stdlib_dir = PathBuf::from(get_stdlib_subdir(amber_version));
+                    let entries = match STDLIB.get_dir(stdlib_dir.join(parts.join("/"))) {
+                        Some(dir) => dir
+                            .entries()
+                            .iter()
+                            .map(|entry| (entry.as_dir().is_some(), entry.path().to_path_buf()))
+                            .collect(),
+                        None => vec![],
+                    };
+
+                    (stdlib_dir, entries)
+                }
+            };
 
-            backend
-                .files
-                .fs
-                .read_dir(&path_in_std)
-                .await
-                .iter()
-                .filter(|path| path.is_dir() || (path.extension() == Some(&OsStr::from("ab"))))
-                .map(|path| {
+            entries
+                .into_iter()
+                .filter(|(is_dir, path)| *is_dir || (path.extension() == Some(&OsStr::from("ab"))))
+                .filter_map(|(is_dir, path)| {
                     let base_path = path
-                        .strip_prefix(stdlib_dir.clone().join("std"))
-                        .unwrap()
-                        .to_str()
-                        .unwrap()
+                        .strip_prefix(stdlib_dir.join("std"))
+                        .ok()?
+                        .to_str()?
                         .to_string();
 
-                    if path.is_file() {
-                        return base_path.strip_suffix(".ab").unwrap().to_string();
+                    if !is_dir {
+                        return base_path.strip_suffix(".ab").map(|path| path.to_string());
                     }
 
-                    base_path
+                    Some(base_path)
                 })
                 .collect()
         }
@@ -0,0 +1,141 @@
+use std::{
+    fmt::{self, Write},
+    sync::{Arc, Mutex, PoisonError, RwLock as SyncRwLock, Weak},
+};
+
+use tokio::runtime::Handle;
+use tower_lsp_server::{
+    lsp_types::{notification::LogTrace, LogTraceParams, TraceValue},
+    Client,
+};
+use tracing::{
+    field::{Field, Visit},
+    Event, Subscriber,
+};
+use tracing_subscriber::{layer::Context, Layer};
+
+/// Trace setting of a client, changed with `$/setTrace`.
+#[derive(Debug)]
+pub struct TraceSession {
+    client: Client,
+    value: SyncRwLock<TraceValue>,
+}
+
+impl TraceSession {
+    pub fn new(client: Client) -> Self {
+        TraceSession {
+            client,
+            value: SyncRwLock::new(TraceValue::Off),
+        }
+    }
+
+    pub fn value(&self) -> TraceValue {
+        *self.value.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn set_value(&self, value: TraceValue) {
+        *self.value.write().unwrap_or_else(PoisonError::into_inner) = value;
+    }
+}
+
+/// Sessions of all connected clients, that log events are forwarded to.
+#[derive(Debug, Clone, Default)]
+pub struct TraceSessions {
+    sessions: Arc<Mutex<Vec<Weak<TraceSession>>>>,
+}
+
+impl TraceSessions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts forwarding events to the session, until it's dropped.
+    pub fn register(&self, session: &Arc<TraceSession>) {
+        let mut sessions = self.sessions.lock().unwrap_or_else(PoisonError::into_inner);
+
+        sessions.retain(|session| session.strong_count() > 0);
+        sessions.push(Arc::downgrade(session));
+    }
+
+    fn active(&self) -> Vec<Arc<TraceSession>> {
+        self.sessions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .filter_map(Weak::upgrade)
+            .filter(|session| session.value() != TraceValue::Off)
+            .collect()
+    }
+}
+
+/// Layer forwarding log events of the server to the clients with `$/logTrace` notifications.
+///
+/// Only events of this crate are forwarded, as sending the notification logs events itself.
+pub struct LogTraceLayer {
+    sessions: TraceSessions,
+}
+
+impl LogTraceLayer {
+    pub fn new(sessions: TraceSessions) -> Self {
+        LogTraceLayer { sessions }
+    }
+}
+
+#[derive(Default)]
+struct EventVisitor {
+    message: String,
+    fields: String,
+}
+
+impl Visit for EventVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{value:?}");
+        } else {
+            let _ = write!(self.fields, " {}={value:?}", field.name());
+        }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for LogTraceLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+
+        if !metadata.target().starts_with(env!("CARGO_CRATE_NAME")) {
+            return;
+        }
+
+        let sessions = self.sessions.active();
+
+        if sessions.is_empty() {
+            return;
+        }
+
+        let Ok(handle) = Handle::try_current() else {
+            return;
+        };
+
+        let mut visitor = EventVisitor::default();
+        event.record(&mut visitor);
+
+        let message = format!("[{}] {}", metadata.level(), visitor.message);
+        let verbose = format!(
+            "{}:{}{}",
+            metadata.file().unwrap_or(metadata.target()),
+            metadata.line().unwrap_or_default(),
+            visitor.fields
+        );
+
+        for session in sessions {
+            let client = session.client.clone();
+            let params = LogTraceParams {
+                message: message.clone(),
+                verbose: (session.value() == TraceValue::Verbose).then(|| verbose.clone()),
+            };
+
+            handle.spawn(async move {
+                client.send_notification::<LogTrace>(params).await;
+            });
+        }
+    }
+}
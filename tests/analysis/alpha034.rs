@@ -2,26 +2,21 @@ use std::{path::Path, sync::Arc};
 
 use amber_lsp::{
     analysis::{FunctionSymbol, SymbolType},
-    backend::{AmberVersion, Backend},
+    analyzer::{AmberVersion, Analyzer},
     fs::MemoryFS,
 };
 use insta::assert_debug_snapshot;
 use tokio::test;
-use tower_lsp_server::{lsp_types::Uri, LspService, UriExt};
+use tower_lsp_server::{lsp_types::Uri, UriExt};
 
 #[test]
 async fn test_function_definition() {
-    let (service, _) = LspService::new(|client| {
-        Backend::new(
-            client,
-            AmberVersion::Alpha034,
-            Some(Arc::new(MemoryFS::new())),
-        )
-    });
+    let analyzer = Arc::new(Analyzer::new(
+        AmberVersion::Alpha034,
+        Arc::new(MemoryFS::new()),
+    ));
 
-    let backend = service.inner();
-
-    let vfs = &backend.files.fs;
+    let vfs = &analyzer.files.fs;
 
     let file = {
         #[cfg(windows)]
@@ -50,9 +45,9 @@ async fn test_function_definition() {
     .await
     .unwrap();
 
-    let file_id = backend.open_document(&uri).await.unwrap();
+    let file_id = analyzer.open_document(&uri).await.unwrap();
 
-    let symbol_table = backend.files.symbol_table.get(&file_id).unwrap();
+    let symbol_table = analyzer.files.symbol_table.get(&file_id).unwrap();
 
     let foo_defs = symbol_table.definitions.get("foo").unwrap();
     let a_defs = symbol_table.definitions.get("a").unwrap();
@@ -73,17 +68,12 @@ async fn test_function_definition() {
 
 #[test]
 async fn test_variable_definition() {
-    let (service, _) = LspService::new(|client| {
-        Backend::new(
-            client,
-            AmberVersion::Alpha034,
-            Some(Arc::new(MemoryFS::new())),
-        )
-    });
-
-    let backend = service.inner();
+    let analyzer = Arc::new(Analyzer::new(
+        AmberVersion::Alpha034,
+        Arc::new(MemoryFS::new()),
+    ));
 
-    let vfs = &backend.files.fs;
+    let vfs = &analyzer.files.fs;
 
     let file = {
         #[cfg(windows)]
@@ -110,9 +100,9 @@ async fn test_variable_definition() {
     .await
     .unwrap();
 
-    let file_id = backend.open_document(&uri).await.unwrap();
+    let file_id = analyzer.open_document(&uri).await.unwrap();
 
-    let symbol_table = backend.files.symbol_table.get(&file_id).unwrap();
+    let symbol_table = analyzer.files.symbol_table.get(&file_id).unwrap();
 
     let a_defs = symbol_table.definitions.get("a").unwrap();
 
@@ -122,17 +112,12 @@ async fn test_variable_definition() {
 
 #[test]
 async fn test_variable_scope() {
-    let (service, _) = LspService::new(|client| {
-        Backend::new(
-            client,
-            AmberVersion::Alpha034,
-            Some(Arc::new(MemoryFS::new())),
-        )
-    });
-
-    let backend = service.inner();
+    let analyzer = Arc::new(Analyzer::new(
+        AmberVersion::Alpha034,
+        Arc::new(MemoryFS::new()),
+    ));
 
-    let vfs = &backend.files.fs;
+    let vfs = &analyzer.files.fs;
 
     let file = {
         #[cfg(windows)]
@@ -187,9 +172,9 @@ async fn test_variable_scope() {
     .await
     .unwrap();
 
-    let file_id = backend.open_document(&uri).await.unwrap();
+    let file_id = analyzer.open_document(&uri).await.unwrap();
 
-    let symbol_table = backend.files.symbol_table.get(&file_id).unwrap();
+    let symbol_table = analyzer.files.symbol_table.get(&file_id).unwrap();
 
     let a_defs = symbol_table.definitions.get("a").unwrap();
 
@@ -206,17 +191,12 @@ async fn test_variable_scope() {
 
 #[test]
 async fn test_symbol_reference_in_expression() {
-    let (service, _) = LspService::new(|client| {
-        Backend::new(
-            client,
-            AmberVersion::Alpha034,
-            Some(Arc::new(MemoryFS::new())),
-        )
-    });
+    let analyzer = Arc::new(Analyzer::new(
+        AmberVersion::Alpha034,
+        Arc::new(MemoryFS::new()),
+    ));
 
-    let backend = service.inner();
-
-    let vfs = &backend.files.fs;
+    let vfs = &analyzer.files.fs;
 
     let file = {
         #[cfg(windows)]
@@ -254,9 +234,9 @@ async fn test_symbol_reference_in_expression() {
     .await
     .unwrap();
 
-    let file_id = backend.open_document(&uri).await.unwrap();
+    let file_id = analyzer.open_document(&uri).await.unwrap();
 
-    let symbol_table = backend.files.symbol_table.get(&file_id).unwrap();
+    let symbol_table = analyzer.files.symbol_table.get(&file_id).unwrap();
 
     let a_refs = symbol_table.references.get("a").unwrap();
     let b_refs = symbol_table.references.get("b").unwrap();
@@ -267,17 +247,12 @@ async fn test_symbol_reference_in_expression() {
 
 #[test]
 async fn test_public_definitions() {
-    let (service, _) = LspService::new(|client| {
-        Backend::new(
-            client,
-            AmberVersion::Alpha034,
-            Some(Arc::new(MemoryFS::new())),
-        )
-    });
-
-    let backend = service.inner();
+    let analyzer = Arc::new(Analyzer::new(
+        AmberVersion::Alpha034,
+        Arc::new(MemoryFS::new()),
+    ));
 
-    let vfs = &backend.files.fs;
+    let vfs = &analyzer.files.fs;
 
     let file = {
         #[cfg(windows)]
@@ -295,9 +270,9 @@ async fn test_public_definitions() {
         .await
         .unwrap();
 
-    let file_id = backend.open_document(&uri).await.unwrap();
+    let file_id = analyzer.open_document(&uri).await.unwrap();
 
-    let symbol_table = backend.files.symbol_table.get(&file_id).unwrap();
+    let symbol_table = analyzer.files.symbol_table.get(&file_id).unwrap();
 
     let foo_def = symbol_table.definitions.get("foo").unwrap();
 
@@ -307,17 +282,12 @@ async fn test_public_definitions() {
 
 #[test]
 async fn test_import_specific_symbols() {
-    let (service, _) = LspService::new(|client| {
-        Backend::new(
-            client,
-            AmberVersion::Alpha034,
-            Some(Arc::new(MemoryFS::new())),
-        )
-    });
+    let analyzer = Arc::new(Analyzer::new(
+        AmberVersion::Alpha034,
+        Arc::new(MemoryFS::new()),
+    ));
 
-    let backend = service.inner();
-
-    let vfs = &backend.files.fs;
+    let vfs = &analyzer.files.fs;
 
     let (src_file, main_file) = {
         #[cfg(windows)]
@@ -351,12 +321,12 @@ async fn test_import_specific_symbols() {
     .await
     .unwrap();
 
-    let src_file_id = backend.open_document(&src_uri).await.unwrap();
+    let src_file_id = analyzer.open_document(&src_uri).await.unwrap();
 
-    let main_file_id = backend.open_document(&main_uri).await.unwrap();
+    let main_file_id = analyzer.open_document(&main_uri).await.unwrap();
 
-    let src_symbol_table = backend.files.symbol_table.get(&src_file_id).unwrap();
-    let main_symbol_table = backend.files.symbol_table.get(&main_file_id).unwrap();
+    let src_symbol_table = analyzer.files.symbol_table.get(&src_file_id).unwrap();
+    let main_symbol_table = analyzer.files.symbol_table.get(&main_file_id).unwrap();
 
     let foo_def = src_symbol_table.definitions.get("foo").unwrap();
     let foo_def1 = main_symbol_table.definitions.get("foo").unwrap();
@@ -369,17 +339,12 @@ async fn test_import_specific_symbols() {
 
 #[test]
 async fn test_import_all_symbols() {
-    let (service, _) = LspService::new(|client| {
-        Backend::new(
-            client,
-            AmberVersion::Alpha034,
-            Some(Arc::new(MemoryFS::new())),
-        )
-    });
-
-    let backend = service.inner();
+    let analyzer = Arc::new(Analyzer::new(
+        AmberVersion::Alpha034,
+        Arc::new(MemoryFS::new()),
+    ));
 
-    let vfs = &backend.files.fs;
+    let vfs = &analyzer.files.fs;
 
     let (src_file, main_file) = {
         #[cfg(windows)]
@@ -408,12 +373,12 @@ async fn test_import_all_symbols() {
     .await
     .unwrap();
 
-    let src_file_id = backend.open_document(&src_uri).await.unwrap();
+    let src_file_id = analyzer.open_document(&src_uri).await.unwrap();
 
-    let main_file_id = backend.open_document(&main_uri).await.unwrap();
+    let main_file_id = analyzer.open_document(&main_uri).await.unwrap();
 
-    let src_symbol_table = backend.files.symbol_table.get(&src_file_id).unwrap();
-    let main_symbol_table = backend.files.symbol_table.get(&main_file_id).unwrap();
+    let src_symbol_table = analyzer.files.symbol_table.get(&src_file_id).unwrap();
+    let main_symbol_table = analyzer.files.symbol_table.get(&main_file_id).unwrap();
 
     let foo_def = src_symbol_table.definitions.get("foo").unwrap();
     let foo_def1 = main_symbol_table.definitions.get("foo").unwrap();
@@ -426,17 +391,12 @@ async fn test_import_all_symbols() {
 
 #[test]
 async fn test_generic_type_inference() {
-    let (service, _) = LspService::new(|client| {
-        Backend::new(
-            client,
-            AmberVersion::Alpha034,
-            Some(Arc::new(MemoryFS::new())),
-        )
-    });
+    let analyzer = Arc::new(Analyzer::new(
+        AmberVersion::Alpha034,
+        Arc::new(MemoryFS::new()),
+    ));
 
-    let backend = service.inner();
-
-    let vfs = &backend.files.fs;
+    let vfs = &analyzer.files.fs;
 
     let file = {
         #[cfg(windows)]
@@ -469,40 +429,37 @@ async fn test_generic_type_inference() {
     .await
     .unwrap();
 
-    let file_id = backend.open_document(&file_uri).await.unwrap();
+    let file_id = analyzer.open_document(&file_uri).await.unwrap();
 
-    let symbol_table = backend.files.symbol_table.get(&file_id).unwrap();
+    let symbol_table = analyzer.files.symbol_table.get(&file_id).unwrap();
     let foo_symbol = symbol_table.symbols.get(&10).unwrap();
 
-    assert_debug_snapshot!(backend.files.generic_types.to_string());
+    assert_debug_snapshot!(analyzer.files.generic_types.to_string());
     match &foo_symbol.symbol_type {
         SymbolType::Function(FunctionSymbol { arguments, .. }) => {
             assert_debug_snapshot!(arguments
                 .iter()
                 .map(|(arg, _)| (
                     arg.name.clone(),
-                    arg.data_type.to_string(&backend.files.generic_types)
+                    arg.data_type.to_string(&analyzer.files.generic_types)
                 ))
                 .collect::<Vec<_>>());
         }
         _ => panic!("Expected function symbol"),
     }
-    assert_debug_snapshot!(foo_symbol.data_type.to_string(&backend.files.generic_types));
+    assert_debug_snapshot!(foo_symbol
+        .data_type
+        .to_string(&analyzer.files.generic_types));
 }
 
 #[test]
 async fn test_generics_reference() {
-    let (service, _) = LspService::new(|client| {
-        Backend::new(
-            client,
-            AmberVersion::Alpha034,
-            Some(Arc::new(MemoryFS::new())),
-        )
-    });
-
-    let backend = service.inner();
+    let analyzer = Arc::new(Analyzer::new(
+        AmberVersion::Alpha034,
+        Arc::new(MemoryFS::new()),
+    ));
 
-    let vfs = &backend.files.fs;
+    let vfs = &analyzer.files.fs;
 
     let file = {
         #[cfg(windows)]
@@ -532,25 +489,27 @@ async fn test_generics_reference() {
     .await
     .unwrap();
 
-    let file_id = backend.open_document(&file_uri).await.unwrap();
+    let file_id = analyzer.open_document(&file_uri).await.unwrap();
 
-    let symbol_table = backend.files.symbol_table.get(&file_id).unwrap();
+    let symbol_table = analyzer.files.symbol_table.get(&file_id).unwrap();
     let foo_symbol = symbol_table.symbols.get(&10).unwrap();
 
-    assert_debug_snapshot!(backend.files.generic_types.to_string());
+    assert_debug_snapshot!(analyzer.files.generic_types.to_string());
     match &foo_symbol.symbol_type {
         SymbolType::Function(FunctionSymbol { arguments, .. }) => {
             assert_debug_snapshot!(arguments
                 .iter()
                 .map(|(arg, _)| (
                     arg.name.clone(),
-                    arg.data_type.to_string(&backend.files.generic_types)
+                    arg.data_type.to_string(&analyzer.files.generic_types)
                 ))
                 .collect::<Vec<_>>());
         }
         _ => panic!("Expected function symbol"),
     }
-    assert_debug_snapshot!(foo_symbol.data_type.to_string(&backend.files.generic_types));
+    assert_debug_snapshot!(foo_symbol
+        .data_type
+        .to_string(&analyzer.files.generic_types));
 
     assert_debug_snapshot!(symbol_table.symbols);
 }
@@ -0,0 +1,108 @@
+use std::{path::Path, sync::Arc};
+
+use amber_lsp::{
+    analysis::{
+        types::{DataType, GenericsMap},
+        FunctionSymbol, SymbolType,
+    },
+    analyzer::{AmberVersion, Analyzer},
+    files::FileVersion,
+    fs::MemoryFS,
+    paths::FileId,
+};
+use tokio::test;
+
+#[test]
+async fn test_generics_unify_within_file_version() {
+    let generics = GenericsMap::new();
+    let file = (FileId(0), FileVersion(1));
+
+    let a = generics.new_generic_id(file);
+    let b = generics.new_generic_id(file);
+    assert_eq!((a.index, b.index), (0, 1));
+
+    // Ids don't depend on other maps
+    assert_eq!(GenericsMap::new().new_generic_id(file).index, 0);
+
+    generics.constrain_generic_type(a, DataType::Generic(b));
+    generics.constrain_generic_type(b, DataType::Number);
+
+    assert_eq!(generics.get_recursive(a), DataType::Number);
+    assert_eq!(generics.to_string(), "Num\nNum\n");
+
+    generics.clean(file.0, file.1);
+
+    assert_eq!(generics.get(a), DataType::Any);
+    assert_eq!(generics.to_string(), "");
+}
+
+#[test]
+async fn test_inferred_generics_narrow_only_in_scope() {
+    let generics = GenericsMap::new();
+    let file = (FileId(0), FileVersion(1));
+
+    let id = generics.new_generic_id(file);
+    let union = DataType::Union(vec![DataType::Number, DataType::Text]);
+    generics.constrain_generic_type(id, union.clone());
+    generics.mark_as_inferred(id);
+
+    let scope = generics.scoped();
+    scope.constrain_generic_type(id, DataType::Number);
+
+    assert_eq!(scope.get(id), DataType::Number);
+    assert_eq!(generics.get(id), union);
+    assert_eq!(generics.scoped().get(id), union);
+}
+
+#[test]
+async fn test_imported_signature_is_exported_with_inferred_types() {
+    let analyzer = Arc::new(Analyzer::new(
+        AmberVersion::Alpha034,
+        Arc::new(MemoryFS::new()),
+    ));
+
+    let vfs = &analyzer.files.fs;
+    let project = {
+        #[cfg(windows)]
+        {
+            Path::new("C:\\project")
+        }
+        #[cfg(unix)]
+        {
+            Path::new("/project")
+        }
+    };
+
+    vfs.write(
+        &project.join("lib.ab"),
+        "pub fun foo(a) {\n    return a + 1\n}\n",
+    )
+    .await
+    .unwrap();
+    vfs.write(
+        &project.join("main.ab"),
+        "import { foo } from \"lib.ab\"\nfoo(1)\n",
+    )
+    .await
+    .unwrap();
+
+    let (main, version) = analyzer
+        .analyze_path(&project.join("main.ab"))
+        .await
+        .unwrap();
+
+    let symbol_table = analyzer
+        .files
+        .symbol_table
+        .get(&(main, version))
+        .map(|symbol_table| symbol_table.clone())
+        .unwrap();
+    let foo = symbol_table.symbols.get(&9).unwrap();
+
+    match &foo.symbol_type {
+        SymbolType::Function(FunctionSymbol { arguments, .. }) => {
+            assert!(!matches!(arguments[0].0.data_type, DataType::Generic(_)));
+        }
+        _ => panic!("Expected function symbol"),
+    }
+}
@@ -1 +1,4 @@
 pub mod alpha034;
+pub mod generics;
+pub mod query;
+pub mod snapshot;
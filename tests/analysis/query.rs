@@ -0,0 +1,144 @@
+use std::sync::Arc;
+
+use amber_lsp::{
+    analysis::query::FunctionQuery,
+    analyzer::{AmberVersion, Analyzer},
+    diagnostics::Diagnostic,
+    files::FileVersion,
+    fs::MemoryFS,
+    grammar::Span,
+    paths::FileId,
+};
+use tokio::test;
+
+use crate::main_file;
+
+/// Symbols and diagnostics of a file version, comparable between analyzers.
+fn summarize(analyzer: &Analyzer, file: (FileId, FileVersion)) -> Vec<String> {
+    let symbol_table = analyzer.files.symbol_table.get(&file).unwrap().clone();
+
+    let symbols = symbol_table.symbols.iter().map(|(range, symbol_info)| {
+        format!(
+            "{range:?} {}",
+            symbol_info.to_string(&analyzer.files.generic_types)
+        )
+    });
+
+    let mut definitions = symbol_table
+        .definitions
+        .iter()
+        .flat_map(|(name, definitions)| {
+            definitions.iter().map(move |(scope, location)| {
+                format!("{name} {scope:?} {}..{}", location.start, location.end)
+            })
+        })
+        .collect::<Vec<_>>();
+    definitions.sort();
+
+    let diagnostics = analyzer
+        .get_diagnostics(file.0, file.1)
+        .into_iter()
+        .map(|diagnostic| format!("{:?} {}", diagnostic.span, diagnostic.diagnostic.message()));
+
+    symbols.chain(definitions).chain(diagnostics).collect()
+}
+
+async fn analyze_fresh(text: &str) -> Vec<String> {
+    let analyzer = Arc::new(Analyzer::new(
+        AmberVersion::Alpha040,
+        Arc::new(MemoryFS::new()),
+    ));
+
+    let file = analyzer.analyze_source(main_file(), text).await.unwrap();
+
+    summarize(&analyzer, file)
+}
+
+#[test]
+async fn test_unchanged_functions_are_reused() {
+    let analyzer = Arc::new(Analyzer::new(
+        AmberVersion::Alpha040,
+        Arc::new(MemoryFS::new()),
+    ));
+
+    let rest = "fun bar(b: Num): Num {\n    let c = foo(b)\n    return c + \"1\"\n}\n\n\
+                fun baz(d) {\n    return d\n}\n";
+
+    analyzer
+        .analyze_source(
+            main_file(),
+            &format!("fun foo(a: Num): Num {{\n    return a\n}}\n\n{rest}"),
+        )
+        .await
+        .unwrap();
+
+    // Editing the body of `foo` keeps its signature
+    let text = format!("fun foo(a: Num): Num {{\n    let b = 1\n    return a + b\n}}\n\n{rest}");
+    let (file_id, version) = analyzer.analyze_source(main_file(), &text).await.unwrap();
+
+    assert_eq!(version, FileVersion(2));
+    for name in ["bar", "baz"] {
+        assert_eq!(
+            analyzer
+                .files
+                .function_cache
+                .get(&(file_id, name.to_string()))
+                .unwrap()
+                .file(),
+            (file_id, version)
+        );
+    }
+    assert_eq!(
+        summarize(&analyzer, (file_id, version)),
+        analyze_fresh(&text).await
+    );
+
+    let baz_start = text.find("fun baz").unwrap();
+    let query = FunctionQuery::new(
+        &analyzer.files,
+        (file_id, version),
+        "baz",
+        Span::new(baz_start, text.trim_end().len()),
+        None,
+    );
+    assert!(query.reuse(&analyzer.files));
+}
+
+#[test]
+async fn test_call_sites_are_checked_when_signature_changes() {
+    let analyzer = Arc::new(Analyzer::new(
+        AmberVersion::Alpha040,
+        Arc::new(MemoryFS::new()),
+    ));
+
+    let bar = "fun bar(): Num {\n    return foo()\n}\n";
+
+    let (file_id, version) = analyzer
+        .analyze_source(
+            main_file(),
+            &format!("fun foo(): Num {{\n    return 1\n}}\n\n{bar}"),
+        )
+        .await
+        .unwrap();
+
+    assert!(!analyzer
+        .get_diagnostics(file_id, version)
+        .iter()
+        .any(|diagnostic| matches!(diagnostic.diagnostic, Diagnostic::TypeMismatch { .. })));
+
+    let text = format!("fun foo(): Text {{\n    return \"a\"\n}}\n\n{bar}");
+    let (file_id, version) = analyzer.analyze_source(main_file(), &text).await.unwrap();
+
+    let bar_start = text.find("fun bar").unwrap();
+    assert!(analyzer
+        .get_diagnostics(file_id, version)
+        .iter()
+        .any(|diagnostic| {
+            matches!(diagnostic.diagnostic, Diagnostic::TypeMismatch { .. })
+                && diagnostic.span.start >= bar_start
+        }));
+    assert_eq!(
+        summarize(&analyzer, (file_id, version)),
+        analyze_fresh(&text).await
+    );
+}
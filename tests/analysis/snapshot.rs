@@ -0,0 +1,235 @@
+use std::sync::Arc;
+
+use amber_lsp::{
+    analysis::{snapshot::TextChange, types::DataType},
+    analyzer::{AmberVersion, Analyzer},
+    files::FileVersion,
+    fs::MemoryFS,
+};
+use ropey::Rope;
+use tokio::test;
+
+use crate::main_file;
+
+#[test]
+async fn test_text_change_between() {
+    let change = TextChange::between(
+        &Rope::from_str("let foo = 1"),
+        &Rope::from_str("let foobar = 1"),
+    );
+    assert_eq!(
+        change,
+        TextChange {
+            start: 7,
+            old_end: 7,
+            new_end: 10,
+        }
+    );
+
+    let change = TextChange::between(&Rope::from_str("aaaa"), &Rope::from_str("aa"));
+    assert_eq!(
+        change,
+        TextChange {
+            start: 2,
+            old_end: 4,
+            new_end: 2,
+        }
+    );
+
+    let change = TextChange::between(&Rope::from_str("same"), &Rope::from_str("same"));
+    assert_eq!(
+        change,
+        TextChange {
+            start: 4,
+            old_end: 4,
+            new_end: 4,
+        }
+    );
+}
+
+#[test]
+async fn test_text_change_moves_spans_and_scopes() {
+    // "ab" replaced with "xyz" at offset 10
+    let change = TextChange {
+        start: 10,
+        old_end: 12,
+        new_end: 13,
+    };
+
+    assert_eq!(change.map_span(2, 5), Some((2, 5)));
+    assert_eq!(change.map_span(5, 10), Some((5, 10)));
+    assert_eq!(change.map_span(12, 15), Some((13, 16)));
+    assert_eq!(change.map_span(8, 11), None);
+    assert_eq!(change.map_span(11, 14), None);
+
+    assert_eq!(change.map_scope(&(0..=20)), Some(0..=21));
+    assert_eq!(change.map_scope(&(11..=usize::MAX)), Some(10..=usize::MAX));
+    assert_eq!(change.map_scope(&(0..=11)), Some(0..=13));
+}
+
+#[test]
+async fn test_snapshot_follows_edits() {
+    let analyzer = Arc::new(Analyzer::new(
+        AmberVersion::Alpha040,
+        Arc::new(MemoryFS::new()),
+    ));
+
+    let (file_id, version) = analyzer
+        .analyze_source(main_file(), "let foo = 1\necho foo\n")
+        .await
+        .unwrap();
+
+    let snapshot = analyzer
+        .files
+        .last_good_analysis
+        .get(&file_id)
+        .map(|snapshot| snapshot.clone())
+        .unwrap();
+    assert_eq!(snapshot.version, version);
+
+    let symbol_table = snapshot.symbol_table(&analyzer.files, file_id).unwrap();
+    assert_eq!(symbol_table.symbols.get(&17).unwrap().name, "foo");
+
+    let new_version = FileVersion(version.0 + 1);
+    let remapped = snapshot.remap(
+        &symbol_table,
+        (file_id, new_version),
+        &Rope::from_str("// comment\nlet foo = 1\necho foo\n"),
+    );
+
+    assert_eq!(remapped.version, new_version);
+
+    let remapped_table = remapped.symbol_table.unwrap();
+    let reference = remapped_table.symbols.get(&28).unwrap();
+    assert_eq!(reference.name, "foo");
+    assert_eq!(reference.span.start, 28);
+
+    let definition = remapped_table
+        .definitions
+        .get("foo")
+        .and_then(|scopes| scopes.get(&28))
+        .unwrap();
+    assert_eq!(definition.file, (file_id, new_version));
+    assert_eq!(definition.start, 15);
+
+    // Symbols, whose text was edited, are dropped
+    let remapped = snapshot.remap(
+        &symbol_table,
+        (file_id, new_version),
+        &Rope::from_str("let foo = 1\necho fo\n"),
+    );
+
+    let remapped_table = remapped.symbol_table.unwrap();
+    assert!(remapped_table.symbols.get(&17).is_none());
+    assert!(remapped_table.symbols.get(&4).is_some());
+}
+
+#[test]
+async fn test_snapshot_takes_symbol_table_when_dropped() {
+    let analyzer = Arc::new(Analyzer::new(
+        AmberVersion::Alpha040,
+        Arc::new(MemoryFS::new()),
+    ));
+
+    let (file_id, version) = analyzer
+        .analyze_source(main_file(), "let foo = 1\necho foo\n")
+        .await
+        .unwrap();
+
+    // While the version is live, its table isn't shared with the snapshot
+    assert!(analyzer
+        .files
+        .last_good_analysis
+        .get(&file_id)
+        .unwrap()
+        .symbol_table
+        .is_none());
+    assert_eq!(
+        Arc::strong_count(
+            &analyzer
+                .files
+                .symbol_table
+                .get(&(file_id, version))
+                .unwrap()
+        ),
+        1
+    );
+
+    analyzer.files.invalidate(&(file_id, version));
+
+    let snapshot = analyzer
+        .files
+        .last_good_analysis
+        .get(&file_id)
+        .map(|snapshot| snapshot.clone())
+        .unwrap();
+    assert!(snapshot
+        .symbol_table
+        .as_ref()
+        .is_some_and(|symbol_table| symbol_table.symbols.get(&17).is_some()));
+
+    // Following versions are still served from the dropped table
+    let symbol_table = snapshot.symbol_table(&analyzer.files, file_id).unwrap();
+    let remapped = snapshot.remap(
+        &symbol_table,
+        (file_id, FileVersion(version.0 + 1)),
+        &Rope::from_str("// comment\nlet foo = 1\necho foo\n"),
+    );
+
+    assert_eq!(
+        remapped
+            .symbol_table
+            .unwrap()
+            .symbols
+            .get(&28)
+            .unwrap()
+            .name,
+        "foo"
+    );
+}
+
+#[test]
+async fn test_snapshot_keeps_generic_types_when_dropped() {
+    let analyzer = Arc::new(Analyzer::new(
+        AmberVersion::Alpha040,
+        Arc::new(MemoryFS::new()),
+    ));
+
+    let (file_id, version) = analyzer
+        .analyze_source(main_file(), "fun foo(a) {\n    return a + 1\n}\n")
+        .await
+        .unwrap();
+
+    let generics = analyzer
+        .files
+        .symbol_table
+        .get(&(file_id, version))
+        .unwrap()
+        .symbols
+        .iter()
+        .filter_map(|(_, symbol_info)| match symbol_info.data_type {
+            DataType::Generic(id) => Some((id, analyzer.files.generic_types.get(id))),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert!(!generics.is_empty());
+    assert!(generics
+        .iter()
+        .all(|(_, data_type)| *data_type != DataType::Any));
+
+    analyzer.files.invalidate(&(file_id, version));
+
+    for (id, data_type) in generics.iter() {
+        assert_eq!(analyzer.files.generic_types.get(*id), *data_type);
+    }
+
+    // Types are dropped with the snapshot, once a newer version is analyzed
+    analyzer
+        .analyze_source(main_file(), "fun foo(a) {\n    return a + 2\n}\n")
+        .await
+        .unwrap();
+
+    for (id, _) in generics.iter() {
+        assert_eq!(analyzer.files.generic_types.get(*id), DataType::Any);
+    }
+}
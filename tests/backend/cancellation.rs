@@ -0,0 +1,328 @@
+use std::{future::poll_fn, sync::Arc, time::Duration};
+
+use amber_lsp::{
+    analyzer::{AmberVersion, Analyzer},
+    backend::Backend,
+    files::FileVersion,
+    fs::MemoryFS,
+};
+use futures::StreamExt;
+use ropey::Rope;
+use serde_json::json;
+use tokio::{test, time::timeout};
+use tower_lsp_server::{
+    jsonrpc::Request,
+    lsp_types::{
+        DidChangeTextDocumentParams, DidOpenTextDocumentParams, HoverParams, Position, Range,
+        TextDocumentContentChangeEvent, TextDocumentIdentifier, TextDocumentItem,
+        TextDocumentPositionParams, Uri, VersionedTextDocumentIdentifier,
+    },
+    LanguageServer, LspService, UriExt,
+};
+use tower_service::Service;
+
+use crate::main_file;
+
+#[test]
+async fn test_outdated_version_is_not_analyzed() {
+    let analyzer = Arc::new(Analyzer::new(
+        AmberVersion::Alpha040,
+        Arc::new(MemoryFS::new()),
+    ));
+
+    let uri = Uri::from_file_path(main_file()).unwrap();
+    let file_id = analyzer.files.insert(uri, FileVersion(1));
+    analyzer
+        .files
+        .document_map
+        .insert((file_id, FileVersion(1)), Rope::from_str("let a = 1\n"));
+
+    // A newer version arrives, before the analysis of the first one starts
+    analyzer.files.add_new_file_version(file_id, FileVersion(2));
+    analyzer
+        .files
+        .document_map
+        .insert((file_id, FileVersion(2)), Rope::from_str("let b = 2\n"));
+
+    assert!(analyzer.files.is_outdated(&(file_id, FileVersion(1))));
+    assert!(!analyzer.files.is_outdated(&(file_id, FileVersion(2))));
+
+    analyzer.analyze_document(file_id, FileVersion(1)).await;
+
+    assert!(
+        !analyzer
+            .files
+            .is_file_analyzed(&(file_id, FileVersion(1)))
+            .await
+    );
+    assert!(analyzer
+        .files
+        .symbol_table
+        .get(&(file_id, FileVersion(1)))
+        .is_none());
+    assert_eq!(analyzer.files.get_analyzed_version(file_id).await, None);
+
+    analyzer.analyze_document(file_id, FileVersion(2)).await;
+
+    assert_eq!(
+        analyzer.files.get_analyzed_version(file_id).await,
+        Some(FileVersion(2))
+    );
+}
+
+#[test(flavor = "multi_thread")]
+async fn test_version_is_analyzed_once() {
+    let analyzer = Arc::new(Analyzer::new(
+        AmberVersion::Alpha040,
+        Arc::new(MemoryFS::new()),
+    ));
+
+    let uri = Uri::from_file_path(main_file()).unwrap();
+    let file_id = analyzer.files.insert(uri, FileVersion(1));
+    analyzer
+        .files
+        .document_map
+        .insert((file_id, FileVersion(1)), Rope::from_str("echo foo\n"));
+
+    tokio::join!(
+        analyzer.analyze_document(file_id, FileVersion(1)),
+        analyzer.analyze_document(file_id, FileVersion(1)),
+    );
+
+    assert!(
+        analyzer
+            .files
+            .is_file_analyzed(&(file_id, FileVersion(1)))
+            .await
+    );
+    assert_eq!(analyzer.get_diagnostics(file_id, FileVersion(1)).len(), 1);
+}
+
+#[test]
+async fn test_requests_use_latest_analyzed_version() {
+    let (service, _) = LspService::new(|client| {
+        Backend::new(
+            client,
+            AmberVersion::Alpha040,
+            Some(Arc::new(MemoryFS::new())),
+        )
+    });
+
+    let backend = service.inner();
+    let uri = Uri::from_file_path(main_file()).unwrap();
+
+    backend
+        .did_open(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem {
+                uri: uri.clone(),
+                language_id: "amber".to_string(),
+                version: 1,
+                text: "let foo = 1\necho foo\n".to_string(),
+            },
+        })
+        .await;
+
+    // The second version is received, but not analyzed yet
+    let file_id = backend.analyzer.files.get(&uri).unwrap();
+    backend
+        .analyzer
+        .files
+        .add_new_file_version(file_id, FileVersion(2));
+    backend.analyzer.files.document_map.insert(
+        (file_id, FileVersion(2)),
+        Rope::from_str("let foo = 1\necho foo\n\n"),
+    );
+
+    assert_eq!(
+        backend.analyzer.files.get_analyzed_version(file_id).await,
+        Some(FileVersion(1))
+    );
+
+    let hover = backend
+        .hover(HoverParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position: Position::new(1, 6),
+            },
+            work_done_progress_params: Default::default(),
+        })
+        .await
+        .unwrap();
+
+    assert!(hover.is_some());
+}
+
+#[test]
+async fn test_pending_publish_is_replaced() {
+    let (mut service, mut socket) = LspService::new(|client| {
+        Backend::new(
+            client,
+            AmberVersion::Alpha040,
+            Some(Arc::new(MemoryFS::new())),
+        )
+    });
+
+    // Notifications are only sent to initialized clients
+    let initialize = Request::build("initialize")
+        .id(1)
+        .params(json!({ "capabilities": {} }))
+        .finish();
+    poll_fn(|cx| service.poll_ready(cx)).await.unwrap();
+    service.call(initialize).await.unwrap();
+
+    let published = tokio::spawn(async move {
+        let mut versions = vec![];
+
+        while let Ok(Some(request)) = timeout(Duration::from_secs(1), socket.next()).await {
+            if request.method() == "textDocument/publishDiagnostics" {
+                versions.push(request.params().unwrap()["version"].as_i64().unwrap());
+            }
+        }
+
+        versions
+    });
+
+    let backend = service.inner();
+    let uri = Uri::from_file_path(main_file()).unwrap();
+
+    backend
+        .did_open(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem {
+                uri: uri.clone(),
+                language_id: "amber".to_string(),
+                version: 1,
+                text: "let foo = 1\n".to_string(),
+            },
+        })
+        .await;
+
+    for version in [2, 3] {
+        backend
+            .did_change(DidChangeTextDocumentParams {
+                text_document: VersionedTextDocumentIdentifier {
+                    uri: uri.clone(),
+                    version,
+                },
+                content_changes: vec![TextDocumentContentChangeEvent {
+                    range: None,
+                    range_length: None,
+                    text: format!("let foo = {version}\n"),
+                }],
+            })
+            .await;
+    }
+
+    // Publishing the same version again replaces its pending publish
+    let file_id = backend.analyzer.files.get(&uri).unwrap();
+    backend.publish_issues_after_delay(file_id, FileVersion(3));
+
+    assert_eq!(published.await.unwrap(), vec![1, 3]);
+}
+
+#[test]
+async fn test_rejected_change_keeps_version() {
+    let (service, _) = LspService::new(|client| {
+        Backend::new(
+            client,
+            AmberVersion::Alpha040,
+            Some(Arc::new(MemoryFS::new())),
+        )
+    });
+
+    let backend = service.inner();
+    let uri = Uri::from_file_path(main_file()).unwrap();
+
+    backend
+        .did_open(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem {
+                uri: uri.clone(),
+                language_id: "amber".to_string(),
+                version: 1,
+                text: "let foo = 1\n".to_string(),
+            },
+        })
+        .await;
+
+    backend
+        .did_change(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier {
+                uri: uri.clone(),
+                version: 2,
+            },
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: Some(Range::new(Position::new(0, 4), Position::new(0, 7))),
+                range_length: Some(3),
+                text: "bar".to_string(),
+            }],
+        })
+        .await;
+
+    let file_id = backend.analyzer.files.get(&uri).unwrap();
+    let (document, version) = backend
+        .analyzer
+        .files
+        .get_document_latest_version(file_id)
+        .unwrap();
+
+    assert_eq!(version, FileVersion(1));
+    assert_eq!(document.to_string(), "let foo = 1\n");
+    assert!(!backend
+        .analyzer
+        .files
+        .is_outdated(&(file_id, FileVersion(1))));
+}
+
+#[test]
+async fn test_out_of_range_change_keeps_version() {
+    let (service, _) = LspService::new(|client| {
+        Backend::new(
+            client,
+            AmberVersion::Alpha040,
+            Some(Arc::new(MemoryFS::new())),
+        )
+    });
+
+    let backend = service.inner();
+    let uri = Uri::from_file_path(main_file()).unwrap();
+
+    backend
+        .did_open(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem {
+                uri: uri.clone(),
+                language_id: "amber".to_string(),
+                version: 1,
+                text: "let foo = 1\n".to_string(),
+            },
+        })
+        .await;
+
+    for range in [
+        Range::new(Position::new(5, 0), Position::new(5, 1)),
+        Range::new(Position::new(0, 4), Position::new(0, 40)),
+        Range::new(Position::new(0, 7), Position::new(0, 4)),
+    ] {
+        backend
+            .did_change(DidChangeTextDocumentParams {
+                text_document: VersionedTextDocumentIdentifier {
+                    uri: uri.clone(),
+                    version: 2,
+                },
+                content_changes: vec![TextDocumentContentChangeEvent {
+                    range: Some(range),
+                    range_length: None,
+                    text: "bar".to_string(),
+                }],
+            })
+            .await;
+    }
+
+    let file_id = backend.analyzer.files.get(&uri).unwrap();
+    let (document, version) = backend
+        .analyzer
+        .files
+        .get_document_latest_version(file_id)
+        .unwrap();
+
+    assert_eq!(version, FileVersion(1));
+    assert_eq!(document.to_string(), "let foo = 1\n");
+}
@@ -0,0 +1,172 @@
+use std::{path::Path, sync::Arc};
+
+use amber_lsp::{
+    backend::{AmberVersion, Backend},
+    fs::MemoryFS,
+};
+use tokio::test;
+use tower_lsp_server::{
+    lsp_types::{
+        DidCloseTextDocumentParams, DidOpenTextDocumentParams, TextDocumentIdentifier,
+        TextDocumentItem, Uri,
+    },
+    LanguageServer, LspService, UriExt,
+};
+
+async fn open(backend: &Backend, uri: &Uri, text: &str) {
+    backend
+        .did_open(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem {
+                uri: uri.clone(),
+                language_id: "amber".to_string(),
+                version: 1,
+                text: text.to_string(),
+            },
+        })
+        .await;
+}
+
+async fn close(backend: &Backend, uri: &Uri) {
+    backend
+        .did_close(DidCloseTextDocumentParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+        })
+        .await;
+}
+
+#[test]
+async fn test_close_drops_file_state() {
+    let (service, _) = LspService::new(|client| {
+        Backend::new(
+            client,
+            AmberVersion::Alpha034,
+            Some(Arc::new(MemoryFS::new())),
+        )
+    });
+
+    let backend = service.inner();
+
+    let vfs = &backend.analyzer.files.fs;
+
+    let (lib_file, main_file) = {
+        #[cfg(windows)]
+        {
+            (Path::new("C:\\lib.ab"), Path::new("C:\\main.ab"))
+        }
+        #[cfg(unix)]
+        {
+            (Path::new("/lib.ab"), Path::new("/main.ab"))
+        }
+    };
+    let lib_uri = Uri::from_file_path(lib_file).unwrap();
+    let main_uri = Uri::from_file_path(main_file).unwrap();
+
+    vfs.write(lib_file, "pub fun foo() {}").await.unwrap();
+
+    open(
+        backend,
+        &main_uri,
+        r#"
+    import { foo } from "lib.ab"
+
+    foo()
+    "#,
+    )
+    .await;
+
+    let main_file_id = backend.analyzer.files.get(&main_uri).unwrap();
+    let lib_file_id = backend.analyzer.files.get(&lib_uri).unwrap();
+
+    close(backend, &main_uri).await;
+
+    assert!(backend.analyzer.files.get(&main_uri).is_none());
+    assert!(backend.analyzer.files.get(&lib_uri).is_none());
+
+    for file_id in [main_file_id, lib_file_id] {
+        assert!(!backend
+            .analyzer
+            .files
+            .document_map
+            .iter()
+            .any(|entry| entry.key().0 == file_id));
+        assert!(!backend
+            .analyzer
+            .files
+            .symbol_table
+            .iter()
+            .any(|entry| entry.key().0 == file_id));
+        assert!(!backend
+            .analyzer
+            .files
+            .analyze_lock
+            .iter()
+            .any(|entry| entry.key().0 == file_id));
+    }
+}
+
+#[test]
+async fn test_closed_imported_file_falls_back_to_disk() {
+    let (service, _) = LspService::new(|client| {
+        Backend::new(
+            client,
+            AmberVersion::Alpha034,
+            Some(Arc::new(MemoryFS::new())),
+        )
+    });
+
+    let backend = service.inner();
+
+    let vfs = &backend.analyzer.files.fs;
+
+    let (lib_file, main_file) = {
+        #[cfg(windows)]
+        {
+            (Path::new("C:\\lib.ab"), Path::new("C:\\main.ab"))
+        }
+        #[cfg(unix)]
+        {
+            (Path::new("/lib.ab"), Path::new("/main.ab"))
+        }
+    };
+    let lib_uri = Uri::from_file_path(lib_file).unwrap();
+    let main_uri = Uri::from_file_path(main_file).unwrap();
+
+    vfs.write(lib_file, "pub fun foo() {}").await.unwrap();
+
+    // Unsaved changes in the editor
+    open(backend, &lib_uri, "pub fun bar() {}").await;
+    open(
+        backend,
+        &main_uri,
+        r#"
+    import { foo } from "lib.ab"
+
+    foo()
+    "#,
+    )
+    .await;
+
+    let is_unresolved = |backend: &Backend| {
+        let file_id = backend.analyzer.files.get(&main_uri).unwrap();
+        let version = backend.analyzer.files.get_latest_version(file_id);
+
+        backend
+            .get_diagnostics(file_id, version)
+            .iter()
+            .any(|diagnostic| diagnostic.message == "Could not resolve 'foo'")
+    };
+
+    assert!(is_unresolved(backend));
+
+    close(backend, &lib_uri).await;
+
+    assert!(!is_unresolved(backend));
+
+    let lib_file_id = backend.analyzer.files.get(&lib_uri).unwrap();
+    let (document, _) = backend
+        .analyzer
+        .files
+        .get_document_latest_version(lib_file_id)
+        .unwrap();
+    assert_eq!(document.to_string(), "pub fun foo() {}");
+}
@@ -0,0 +1,168 @@
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use amber_lsp::{
+    backend::{AmberVersion, Backend},
+    config::{InlayHintsConfig, LintSeverity, ServerConfig},
+    fs::MemoryFS,
+};
+use tokio::test;
+use tower_lsp_server::{
+    lsp_types::{
+        DiagnosticSeverity, DidChangeConfigurationParams, DidOpenTextDocumentParams,
+        TextDocumentItem, Uri,
+    },
+    LanguageServer, LspService, UriExt,
+};
+
+async fn open_file(backend: &Backend) -> Uri {
+    let file = {
+        #[cfg(windows)]
+        {
+            Path::new("C:\\main.ab")
+        }
+        #[cfg(unix)]
+        {
+            Path::new("/main.ab")
+        }
+    };
+    let uri = Uri::from_file_path(file).unwrap();
+
+    backend
+        .did_open(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem {
+                uri: uri.clone(),
+                language_id: "amber".to_string(),
+                version: 1,
+                text: "let x = y\nlet z = w\n".to_string(),
+            },
+        })
+        .await;
+
+    uri
+}
+
+fn undefined_symbols(backend: &Backend, uri: &Uri) -> Vec<Option<DiagnosticSeverity>> {
+    let file_id = backend.analyzer.files.get(uri).unwrap();
+    let version = backend.analyzer.files.get_latest_version(file_id);
+
+    backend
+        .get_diagnostics(file_id, version)
+        .iter()
+        .filter(|diagnostic| diagnostic.message.ends_with("is not defined"))
+        .map(|diagnostic| diagnostic.severity)
+        .collect()
+}
+
+#[test]
+async fn test_config_lints_and_problem_limit() {
+    let (service, _) = LspService::new(|client| {
+        Backend::new(
+            client,
+            AmberVersion::Alpha034,
+            Some(Arc::new(MemoryFS::new())),
+        )
+    });
+
+    let backend = service.inner();
+
+    let uri = open_file(backend).await;
+
+    assert_eq!(
+        undefined_symbols(backend, &uri),
+        vec![Some(DiagnosticSeverity::ERROR); 2]
+    );
+
+    backend
+        .apply_config(ServerConfig {
+            lints: HashMap::from([("undefined-symbol".to_string(), LintSeverity::Warning)]),
+            ..ServerConfig::default()
+        })
+        .await;
+
+    assert_eq!(
+        undefined_symbols(backend, &uri),
+        vec![Some(DiagnosticSeverity::WARNING); 2]
+    );
+
+    backend
+        .apply_config(ServerConfig {
+            max_number_of_problems: 1,
+            ..ServerConfig::default()
+        })
+        .await;
+
+    assert_eq!(
+        undefined_symbols(backend, &uri),
+        vec![Some(DiagnosticSeverity::ERROR)]
+    );
+
+    backend
+        .apply_config(ServerConfig {
+            lints: HashMap::from([("undefined-symbol".to_string(), LintSeverity::Off)]),
+            ..ServerConfig::default()
+        })
+        .await;
+
+    assert!(undefined_symbols(backend, &uri).is_empty());
+}
+
+#[test]
+async fn test_config_pushed_by_client_changes_version() {
+    let (service, _) = LspService::new(|client| {
+        Backend::new(
+            client,
+            AmberVersion::Alpha034,
+            Some(Arc::new(MemoryFS::new())),
+        )
+    });
+
+    let backend = service.inner();
+
+    let uri = open_file(backend).await;
+
+    backend
+        .did_change_configuration(DidChangeConfigurationParams {
+            settings: serde_json::json!({
+                "amber-lsp": {
+                    "version": "0.3.5-alpha",
+                    "maxNumberOfProblems": 10,
+                }
+            }),
+        })
+        .await;
+
+    assert_eq!(backend.analyzer.amber_version(), AmberVersion::Alpha035);
+    assert_eq!(
+        backend
+            .analyzer
+            .config
+            .read()
+            .unwrap()
+            .max_number_of_problems,
+        10
+    );
+    assert_eq!(undefined_symbols(backend, &uri).len(), 2);
+}
+
+#[test]
+async fn test_config_inlay_hints() {
+    assert_eq!(
+        ServerConfig::default().inlay_hints,
+        InlayHintsConfig {
+            variable_types: true,
+            parameter_names: true,
+        }
+    );
+
+    let config = ServerConfig::from_value(serde_json::json!({
+        "inlayHints": { "variableTypes": false },
+    }));
+
+    assert_eq!(
+        config.inlay_hints,
+        InlayHintsConfig {
+            variable_types: false,
+            parameter_names: true,
+        }
+    );
+}
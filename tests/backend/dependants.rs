@@ -0,0 +1,302 @@
+use std::{collections::HashSet, sync::Arc};
+
+use amber_lsp::{
+    analyzer::{AmberVersion, AnalysisEvent, Analyzer},
+    fs::MemoryFS,
+    paths::FileId,
+};
+use tokio::test;
+use tower_lsp_server::{lsp_types::Uri, UriExt};
+
+use crate::project_dir;
+
+#[test]
+async fn test_dependants_are_analyzed_once_in_import_order() {
+    let analyzer = Arc::new(Analyzer::new(
+        AmberVersion::Alpha034,
+        Arc::new(MemoryFS::new()),
+    ));
+
+    let vfs = &analyzer.files.fs;
+    let project = project_dir();
+
+    vfs.write(&project.join("lib.ab"), "pub fun foo() {}")
+        .await
+        .unwrap();
+    vfs.write(
+        &project.join("a.ab"),
+        "import { foo } from \"lib.ab\"\npub fun a() { foo() }",
+    )
+    .await
+    .unwrap();
+    vfs.write(
+        &project.join("b.ab"),
+        "import { foo } from \"lib.ab\"\nimport { a } from \"a.ab\"\npub fun b() { a() }",
+    )
+    .await
+    .unwrap();
+    vfs.write(&project.join("c.ab"), "import { b } from \"b.ab\"\nb()")
+        .await
+        .unwrap();
+    vfs.write(
+        &project.join("d.ab"),
+        "import { foo } from \"lib.ab\"\nfoo()",
+    )
+    .await
+    .unwrap();
+
+    let mut file_ids = vec![];
+
+    for name in ["a.ab", "b.ab", "c.ab", "d.ab"] {
+        let (file_id, _) = analyzer.analyze_path(&project.join(name)).await.unwrap();
+        file_ids.push(file_id);
+    }
+
+    let [a, b, c, d] = file_ids[..] else {
+        unreachable!()
+    };
+    let lib = analyzer
+        .files
+        .get(&Uri::from_file_path(project.join("lib.ab")).unwrap())
+        .unwrap();
+
+    let groups = analyzer
+        .files
+        .get_dependants_in_order(lib)
+        .into_iter()
+        .map(|group| {
+            group
+                .into_iter()
+                .map(|(file_id, _)| file_id)
+                .collect::<HashSet<FileId>>()
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        groups,
+        vec![
+            HashSet::from([a, d]),
+            HashSet::from([b]),
+            HashSet::from([c]),
+        ]
+    );
+
+    analyzer.take_events();
+    analyzer
+        .analyze_source(
+            &project.join("lib.ab"),
+            "pub fun foo() {}\npub fun bar() {}",
+        )
+        .await
+        .unwrap();
+
+    let analyzed = analyzer
+        .take_events()
+        .into_iter()
+        .filter_map(|event| match event {
+            AnalysisEvent::DependantAnalyzed(file_id, _) => Some(file_id),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(analyzed.len(), 4);
+    assert_eq!(
+        analyzed.iter().copied().collect::<HashSet<_>>(),
+        HashSet::from([a, b, c, d])
+    );
+    assert_eq!(analyzed[3], c);
+}
+
+#[test]
+async fn test_import_cycle_is_analyzed_together() {
+    let analyzer = Arc::new(Analyzer::new(
+        AmberVersion::Alpha034,
+        Arc::new(MemoryFS::new()),
+    ));
+
+    let vfs = &analyzer.files.fs;
+    let project = project_dir();
+
+    vfs.write(&project.join("lib.ab"), "pub fun foo() {}")
+        .await
+        .unwrap();
+    vfs.write(
+        &project.join("x.ab"),
+        "import { foo } from \"lib.ab\"\nimport * from \"y.ab\"\npub fun x() {}",
+    )
+    .await
+    .unwrap();
+    vfs.write(
+        &project.join("y.ab"),
+        "import * from \"x.ab\"\npub fun y() {}",
+    )
+    .await
+    .unwrap();
+
+    let (x, _) = analyzer.analyze_path(&project.join("x.ab")).await.unwrap();
+    let y = analyzer
+        .files
+        .get(&Uri::from_file_path(project.join("y.ab")).unwrap())
+        .unwrap();
+    let lib = analyzer
+        .files
+        .get(&Uri::from_file_path(project.join("lib.ab")).unwrap())
+        .unwrap();
+
+    let groups = analyzer.files.get_dependants_in_order(lib);
+
+    assert_eq!(groups.len(), 1);
+    assert_eq!(
+        groups[0]
+            .iter()
+            .map(|(file_id, _)| *file_id)
+            .collect::<HashSet<_>>(),
+        HashSet::from([x, y])
+    );
+}
+
+#[test]
+async fn test_files_importing_cycle_are_analyzed_after_it() {
+    let analyzer = Arc::new(Analyzer::new(
+        AmberVersion::Alpha034,
+        Arc::new(MemoryFS::new()),
+    ));
+
+    let vfs = &analyzer.files.fs;
+    let project = project_dir();
+
+    vfs.write(&project.join("lib.ab"), "pub fun foo() {}")
+        .await
+        .unwrap();
+    vfs.write(
+        &project.join("x.ab"),
+        "import { foo } from \"lib.ab\"\nimport * from \"y.ab\"\npub fun x() {}",
+    )
+    .await
+    .unwrap();
+    vfs.write(
+        &project.join("y.ab"),
+        "import * from \"x.ab\"\npub fun y() {}",
+    )
+    .await
+    .unwrap();
+    vfs.write(&project.join("z.ab"), "import { x } from \"x.ab\"\nx()")
+        .await
+        .unwrap();
+    vfs.write(
+        &project.join("w.ab"),
+        "import { foo } from \"lib.ab\"\nfoo()",
+    )
+    .await
+    .unwrap();
+
+    let mut file_ids = vec![];
+
+    for name in ["x.ab", "y.ab", "z.ab", "w.ab"] {
+        let (file_id, _) = analyzer.analyze_path(&project.join(name)).await.unwrap();
+        file_ids.push(file_id);
+    }
+
+    let [x, y, z, w] = file_ids[..] else {
+        unreachable!()
+    };
+    let lib = analyzer
+        .files
+        .get(&Uri::from_file_path(project.join("lib.ab")).unwrap())
+        .unwrap();
+
+    let groups = analyzer
+        .files
+        .get_dependants_in_order(lib)
+        .into_iter()
+        .map(|group| {
+            group
+                .into_iter()
+                .map(|(file_id, _)| file_id)
+                .collect::<HashSet<FileId>>()
+        })
+        .collect::<Vec<_>>();
+
+    // Only the members of the cycle are grouped, the file importing it still comes after them
+    assert_eq!(groups, vec![HashSet::from([w, x, y]), HashSet::from([z])]);
+}
+
+#[test]
+async fn test_unchanged_interface_keeps_dependants() {
+    let analyzer = Arc::new(Analyzer::new(
+        AmberVersion::Alpha034,
+        Arc::new(MemoryFS::new()),
+    ));
+
+    let vfs = &analyzer.files.fs;
+    let project = project_dir();
+    let lib_file = project.join("lib.ab");
+
+    vfs.write(&lib_file, "pub fun foo(a: Num): Num {\n    return a\n}\n")
+        .await
+        .unwrap();
+    vfs.write(
+        &project.join("main.ab"),
+        "import { foo } from \"lib.ab\"\nlet x = foo(1)\n",
+    )
+    .await
+    .unwrap();
+
+    let (main, main_version) = analyzer
+        .analyze_path(&project.join("main.ab"))
+        .await
+        .unwrap();
+    let lib = analyzer
+        .files
+        .get(&Uri::from_file_path(&lib_file).unwrap())
+        .unwrap();
+
+    let parsed = analyzer.files.parse_cache.get(&main).unwrap().clone();
+    let main_document = analyzer
+        .files
+        .document_map
+        .get(&(main, main_version))
+        .unwrap()
+        .clone();
+    assert!(parsed.is_parse_of(&main_document, &AmberVersion::Alpha034));
+
+    // Changing the body of the function doesn't change its signature
+    analyzer.take_events();
+    let (_, lib_version) = analyzer
+        .analyze_source(
+            &lib_file,
+            "pub fun foo(a: Num): Num {\n    let b = a\n    return b\n}\n",
+        )
+        .await
+        .unwrap();
+
+    assert!(!analyzer
+        .take_events()
+        .iter()
+        .any(|event| matches!(event, AnalysisEvent::DependantAnalyzed(..))));
+
+    let definition = analyzer
+        .files
+        .symbol_table
+        .get(&(main, main_version))
+        .unwrap()
+        .definitions
+        .get("foo")
+        .unwrap()
+        .iter()
+        .map(|(_, location)| location.clone())
+        .next()
+        .unwrap();
+    assert_eq!(definition.file, (lib, lib_version));
+    assert_eq!(definition.start, 8);
+
+    // Changing the signature does
+    analyzer
+        .analyze_source(&lib_file, "pub fun foo(a: Text): Text {\n    return a\n}\n")
+        .await
+        .unwrap();
+
+    assert!(analyzer.take_events().iter().any(
+        |event| matches!(event, AnalysisEvent::DependantAnalyzed(file_id, _) if *file_id == main)
+    ));
+}
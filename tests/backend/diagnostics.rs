@@ -0,0 +1,311 @@
+use std::{path::Path, sync::Arc};
+
+use amber_lsp::{
+    analysis::types::DataType,
+    backend::{AmberVersion, Backend},
+    fs::MemoryFS,
+};
+use tokio::test;
+use tower_lsp_server::{
+    lsp_types::{
+        DidChangeTextDocumentParams, NumberOrString, Position, Range,
+        TextDocumentContentChangeEvent, Uri, VersionedTextDocumentIdentifier,
+    },
+    LanguageServer, LspService, UriExt,
+};
+
+#[test]
+async fn test_dependant_diagnostics_follow_imported_file() {
+    let (service, _) = LspService::new(|client| {
+        Backend::new(
+            client,
+            AmberVersion::Alpha034,
+            Some(Arc::new(MemoryFS::new())),
+        )
+    });
+
+    let backend = service.inner();
+
+    let vfs = &backend.analyzer.files.fs;
+
+    let (lib_file, main_file) = {
+        #[cfg(windows)]
+        {
+            (Path::new("C:\\lib.ab"), Path::new("C:\\main.ab"))
+        }
+        #[cfg(unix)]
+        {
+            (Path::new("/lib.ab"), Path::new("/main.ab"))
+        }
+    };
+    let lib_uri = Uri::from_file_path(lib_file).unwrap();
+    let main_uri = Uri::from_file_path(main_file).unwrap();
+
+    vfs.write(&lib_uri.to_file_path().unwrap(), "pub fun foo() {}")
+        .await
+        .unwrap();
+    vfs.write(
+        &main_uri.to_file_path().unwrap(),
+        r#"
+    import { foo } from "lib.ab"
+
+    foo()
+    "#,
+    )
+    .await
+    .unwrap();
+
+    let (main_file_id, main_version) = backend.open_document(&main_uri).await.unwrap();
+
+    let is_unresolved = |backend: &Backend| {
+        backend
+            .get_diagnostics(main_file_id, main_version)
+            .iter()
+            .any(|diagnostic| diagnostic.message == "Could not resolve 'foo'")
+    };
+
+    assert!(!is_unresolved(backend));
+
+    backend
+        .did_change(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier {
+                uri: lib_uri.clone(),
+                version: 2,
+            },
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: "pub fun bar() {}".to_string(),
+            }],
+        })
+        .await;
+
+    assert!(is_unresolved(backend));
+}
+
+#[test]
+async fn test_type_mismatch_points_to_definition() {
+    let (service, _) = LspService::new(|client| {
+        Backend::new(
+            client,
+            AmberVersion::Alpha034,
+            Some(Arc::new(MemoryFS::new())),
+        )
+    });
+
+    let backend = service.inner();
+
+    let vfs = &backend.analyzer.files.fs;
+
+    let file = {
+        #[cfg(windows)]
+        {
+            Path::new("C:\\main.ab")
+        }
+        #[cfg(unix)]
+        {
+            Path::new("/main.ab")
+        }
+    };
+    let uri = Uri::from_file_path(file).unwrap();
+
+    vfs.write(
+        &uri.to_file_path().unwrap(),
+        r#"fun foo(x: Num) {}
+let a = "text"
+foo(a)
+"#,
+    )
+    .await
+    .unwrap();
+
+    let (file_id, version) = backend.open_document(&uri).await.unwrap();
+
+    let diagnostics = backend.get_diagnostics(file_id, version);
+    let mismatch = diagnostics
+        .iter()
+        .find(|diagnostic| {
+            diagnostic.code == Some(NumberOrString::String("type-mismatch".to_string()))
+        })
+        .expect("type mismatch is reported");
+
+    assert_eq!(mismatch.source, Some("amber".to_string()));
+    assert!(mismatch.code_description.is_some());
+    assert_eq!(
+        mismatch.data,
+        Some(serde_json::json!({ "expected": "Num", "found": "Text" }))
+    );
+
+    let related = mismatch.related_information.as_ref().unwrap();
+    assert_eq!(related.len(), 1);
+    assert_eq!(related[0].location.uri, uri);
+    assert_eq!(
+        related[0].location.range,
+        Range::new(Position::new(1, 4), Position::new(1, 5))
+    );
+}
+
+#[test]
+async fn test_import_cycle_lists_every_import() {
+    let (service, _) = LspService::new(|client| {
+        Backend::new(
+            client,
+            AmberVersion::Alpha034,
+            Some(Arc::new(MemoryFS::new())),
+        )
+    });
+
+    let backend = service.inner();
+
+    let vfs = &backend.analyzer.files.fs;
+
+    let (a_file, b_file, c_file) = {
+        #[cfg(windows)]
+        {
+            (
+                Path::new("C:\\a.ab"),
+                Path::new("C:\\b.ab"),
+                Path::new("C:\\c.ab"),
+            )
+        }
+        #[cfg(unix)]
+        {
+            (Path::new("/a.ab"), Path::new("/b.ab"), Path::new("/c.ab"))
+        }
+    };
+    let a_uri = Uri::from_file_path(a_file).unwrap();
+    let b_uri = Uri::from_file_path(b_file).unwrap();
+    let c_uri = Uri::from_file_path(c_file).unwrap();
+
+    vfs.write(
+        &a_uri.to_file_path().unwrap(),
+        r#"import { foo } from "b.ab"
+let x = foo()
+"#,
+    )
+    .await
+    .unwrap();
+    vfs.write(
+        &b_uri.to_file_path().unwrap(),
+        r#"import * from "c.ab"
+pub fun foo() { return 1 }
+"#,
+    )
+    .await
+    .unwrap();
+    vfs.write(&c_uri.to_file_path().unwrap(), r#"import * from "a.ab""#)
+        .await
+        .unwrap();
+
+    let (a_file_id, a_version) = backend.open_document(&a_uri).await.unwrap();
+
+    let diagnostics = backend.get_diagnostics(a_file_id, a_version);
+    let cycle = diagnostics
+        .iter()
+        .find(|diagnostic| {
+            diagnostic.code == Some(NumberOrString::String("circular-dependency".to_string()))
+        })
+        .expect("circular dependency is reported");
+
+    assert_eq!(
+        cycle.message,
+        "Circular dependency: a.ab → b.ab → c.ab → a.ab"
+    );
+
+    let related = cycle.related_information.as_ref().unwrap();
+    assert_eq!(
+        related
+            .iter()
+            .map(|info| (info.location.uri.clone(), info.message.clone()))
+            .collect::<Vec<_>>(),
+        vec![
+            (a_uri.clone(), "`a.ab` imports `b.ab`".to_string()),
+            (b_uri.clone(), "`b.ab` imports `c.ab`".to_string()),
+            (c_uri.clone(), "`c.ab` imports `a.ab`".to_string()),
+        ]
+    );
+
+    // Symbols of the file in the cycle are still imported with their types
+    assert!(!diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.message == "Could not resolve 'foo'"));
+
+    let symbol_table = backend
+        .analyzer
+        .files
+        .symbol_table
+        .get(&(a_file_id, a_version))
+        .unwrap();
+    let x = symbol_table.symbols.get(&31).unwrap();
+    assert_eq!(x.name, "x");
+    assert_eq!(x.data_type, DataType::Number);
+}
+
+#[test]
+async fn test_semantic_errors_have_codes() {
+    let (service, _) = LspService::new(|client| {
+        Backend::new(
+            client,
+            AmberVersion::Alpha040,
+            Some(Arc::new(MemoryFS::new())),
+        )
+    });
+
+    let backend = service.inner();
+
+    let vfs = &backend.analyzer.files.fs;
+
+    let file = {
+        #[cfg(windows)]
+        {
+            Path::new("C:\\main.ab")
+        }
+        #[cfg(unix)]
+        {
+            Path::new("/main.ab")
+        }
+    };
+    let uri = Uri::from_file_path(file).unwrap();
+
+    vfs.write(
+        file,
+        r#"fun foo(a) {}
+const c = 1
+let t = true
+foo(c, 2)
+c()
+let f = foo
+c += 1
+t -= 1
+break
+"#,
+    )
+    .await
+    .unwrap();
+
+    let (file_id, version) = backend.open_document(&uri).await.unwrap();
+
+    let codes = backend
+        .get_diagnostics(file_id, version)
+        .into_iter()
+        .filter_map(|diagnostic| match diagnostic.code {
+            Some(NumberOrString::String(code)) => Some(code),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    for code in [
+        "too-many-arguments",
+        "not-a-function",
+        "function-as-value",
+        "constant-assignment",
+        "type-mismatch",
+        "misplaced-control-flow",
+    ] {
+        assert!(codes.iter().any(|c| c == code), "{code} is reported");
+    }
+    assert!(backend
+        .get_diagnostics(file_id, version)
+        .iter()
+        .all(|diagnostic| diagnostic.code.is_some()));
+}
@@ -0,0 +1,115 @@
+use std::{path::Path, sync::Arc, time::Duration};
+
+use amber_lsp::{
+    backend::{AmberVersion, Backend},
+    fs::MemoryFS,
+};
+use tokio::test;
+use tower_lsp_server::{
+    lsp_types::{
+        DidChangeWorkspaceFoldersParams, Uri, WorkspaceFolder, WorkspaceFoldersChangeEvent,
+    },
+    LanguageServer, LspService, UriExt,
+};
+
+#[test]
+async fn test_index_workspace_folders() {
+    let (service, _) = LspService::new(|client| {
+        Backend::new(
+            client,
+            AmberVersion::Alpha034,
+            Some(Arc::new(MemoryFS::new())),
+        )
+    });
+
+    let backend = service.inner();
+
+    let vfs = &backend.analyzer.files.fs;
+
+    let (workspace, main_file, lib_file, hidden_file) = {
+        #[cfg(windows)]
+        {
+            (
+                Path::new("C:\\workspace"),
+                Path::new("C:\\workspace\\main.ab"),
+                Path::new("C:\\workspace\\src\\lib.ab"),
+                Path::new("C:\\workspace\\.git\\hook.ab"),
+            )
+        }
+        #[cfg(unix)]
+        {
+            (
+                Path::new("/workspace"),
+                Path::new("/workspace/main.ab"),
+                Path::new("/workspace/src/lib.ab"),
+                Path::new("/workspace/.git/hook.ab"),
+            )
+        }
+    };
+    let workspace_uri = Uri::from_file_path(workspace).unwrap();
+    let main_uri = Uri::from_file_path(main_file).unwrap();
+    let lib_uri = Uri::from_file_path(lib_file).unwrap();
+    let hidden_uri = Uri::from_file_path(hidden_file).unwrap();
+
+    vfs.write(main_file, "let x = y").await.unwrap();
+    vfs.write(lib_file, "pub fun foo() {}").await.unwrap();
+    vfs.write(hidden_file, "pub fun bar() {}").await.unwrap();
+
+    backend
+        .did_change_workspace_folders(DidChangeWorkspaceFoldersParams {
+            event: WorkspaceFoldersChangeEvent {
+                added: vec![WorkspaceFolder {
+                    uri: workspace_uri.clone(),
+                    name: "workspace".to_string(),
+                }],
+                removed: vec![],
+            },
+        })
+        .await;
+
+    // Folders are indexed in the background
+    for _ in 0..100 {
+        let indexed = backend.analyzer.files.get_reachable_files();
+
+        if [&main_uri, &lib_uri].into_iter().all(|uri| {
+            backend
+                .analyzer
+                .files
+                .get(uri)
+                .is_some_and(|file_id| indexed.contains(&file_id))
+        }) {
+            break;
+        }
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    let main_file_id = backend
+        .analyzer
+        .files
+        .get(&main_uri)
+        .expect("main.ab is indexed");
+    assert!(backend.analyzer.files.get(&lib_uri).is_some());
+    assert!(backend.analyzer.files.get(&hidden_uri).is_none());
+
+    let version = backend.analyzer.files.get_latest_version(main_file_id);
+    assert!(backend
+        .get_diagnostics(main_file_id, version)
+        .iter()
+        .any(|diagnostic| diagnostic.message == "\"y\" is not defined"));
+
+    backend
+        .did_change_workspace_folders(DidChangeWorkspaceFoldersParams {
+            event: WorkspaceFoldersChangeEvent {
+                added: vec![],
+                removed: vec![WorkspaceFolder {
+                    uri: workspace_uri.clone(),
+                    name: "workspace".to_string(),
+                }],
+            },
+        })
+        .await;
+
+    assert!(backend.analyzer.files.get(&main_uri).is_none());
+    assert!(backend.analyzer.files.get(&lib_uri).is_none());
+}
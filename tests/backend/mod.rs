@@ -0,0 +1,11 @@
+pub mod cancellation;
+pub mod close;
+pub mod config;
+pub mod dependants;
+pub mod diagnostics;
+pub mod indexing;
+pub mod project;
+pub mod stdlib;
+pub mod trace;
+pub mod versions;
+pub mod watched_files;
@@ -0,0 +1,170 @@
+use std::sync::Arc;
+
+use amber_lsp::{
+    analyzer::{AmberVersion, Analyzer},
+    backend::Backend,
+    fs::MemoryFS,
+};
+use tokio::test;
+use tower_lsp_server::{
+    lsp_types::{
+        DiagnosticSeverity, DidChangeWatchedFilesParams, FileChangeType, FileEvent,
+        InitializedParams, Uri,
+    },
+    LanguageServer, LspService, UriExt,
+};
+
+use crate::project_dir;
+
+#[test]
+async fn test_project_config_aliases_and_lints() {
+    let (service, _) = LspService::new(|client| {
+        Backend::new(
+            client,
+            AmberVersion::Alpha034,
+            Some(Arc::new(MemoryFS::new())),
+        )
+    });
+
+    let backend = service.inner();
+
+    let vfs = &backend.analyzer.files.fs;
+
+    let project = project_dir();
+    let main_file = project.join("src").join("main.ab");
+
+    vfs.write(
+        &project.join("amber.toml"),
+        r#"
+[imports]
+aliases = { lib = "libs" }
+
+[lints]
+undefined-symbol = "warning"
+"#,
+    )
+    .await
+    .unwrap();
+    vfs.write(&project.join("libs").join("utils.ab"), "pub fun foo() {}")
+        .await
+        .unwrap();
+    vfs.write(
+        &main_file,
+        r#"import { foo } from "lib/utils.ab"
+foo()
+let x = y
+"#,
+    )
+    .await
+    .unwrap();
+
+    let uri = Uri::from_file_path(&main_file).unwrap();
+    let (file_id, version) = backend.open_document(&uri).await.unwrap();
+
+    let diagnostics = backend.get_diagnostics(file_id, version);
+
+    assert!(!diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.message == "File doesn't exist"));
+
+    let undefined = diagnostics
+        .iter()
+        .find(|diagnostic| diagnostic.message == "\"y\" is not defined")
+        .unwrap();
+    assert_eq!(undefined.severity, Some(DiagnosticSeverity::WARNING));
+}
+
+#[test]
+async fn test_invalid_project_config() {
+    let (service, _) = LspService::new(|client| {
+        Backend::new(
+            client,
+            AmberVersion::Alpha034,
+            Some(Arc::new(MemoryFS::new())),
+        )
+    });
+
+    let backend = service.inner();
+
+    let vfs = &backend.analyzer.files.fs;
+
+    let project = project_dir();
+    let config_file = project.join(".amber-lsp.toml");
+
+    vfs.write(&config_file, "version = ").await.unwrap();
+
+    assert!(backend.load_project_config(project).await.is_none());
+    assert_eq!(
+        backend.analyzer.project_configs.get(&config_file),
+        Some(None)
+    );
+}
+
+#[test]
+async fn test_project_config_pins_detected_version() {
+    let (service, _) = LspService::new(|client| {
+        Backend::with_analyzer(
+            client,
+            Arc::new(
+                Analyzer::new(AmberVersion::Alpha034, Arc::new(MemoryFS::new()))
+                    .with_detected_version(),
+            ),
+        )
+    });
+
+    let backend = service.inner();
+
+    let vfs = &backend.analyzer.files.fs;
+
+    let project = project_dir();
+
+    vfs.write(&project.join("amber.toml"), r#"version = "0.3.5-alpha""#)
+        .await
+        .unwrap();
+
+    backend
+        .workspace_folders
+        .insert(Uri::from_file_path(project).unwrap());
+
+    backend.initialized(InitializedParams {}).await;
+
+    assert_eq!(backend.analyzer.amber_version(), AmberVersion::Alpha035);
+}
+
+#[test]
+async fn test_missing_project_config_is_cached_until_created() {
+    let (service, _) = LspService::new(|client| {
+        Backend::new(
+            client,
+            AmberVersion::Alpha034,
+            Some(Arc::new(MemoryFS::new())),
+        )
+    });
+
+    let backend = service.inner();
+
+    let vfs = &backend.analyzer.files.fs;
+
+    let project = project_dir();
+    let config_file = project.join("amber.toml");
+
+    assert!(backend.load_project_config(project).await.is_none());
+
+    // The file system is not checked again for a missing configuration
+    vfs.write(&config_file, r#"version = "0.3.5-alpha""#)
+        .await
+        .unwrap();
+    assert!(backend.load_project_config(project).await.is_none());
+
+    backend
+        .did_change_watched_files(DidChangeWatchedFilesParams {
+            changes: vec![FileEvent {
+                uri: Uri::from_file_path(&config_file).unwrap(),
+                typ: FileChangeType::CREATED,
+            }],
+        })
+        .await;
+
+    let config = backend.load_project_config(project).await.unwrap();
+    assert_eq!(config.version, Some("0.3.5-alpha".to_string()));
+}
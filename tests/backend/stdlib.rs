@@ -0,0 +1,235 @@
+use std::{path::Path, str::FromStr, sync::Arc};
+
+use amber_lsp::{
+    analyzer::{AmberVersion, Analyzer},
+    backend::Backend,
+    fs::MemoryFS,
+    stdlib::{InstalledStdlib, STDLIB, STDLIB_SCHEME},
+};
+use tokio::test;
+use tower_lsp_server::{
+    lsp_types::{
+        HoverContents, HoverParams, InitializeParams, Position, SemanticTokensParams,
+        SemanticTokensServerCapabilities, TextDocumentIdentifier, TextDocumentPositionParams, Uri,
+    },
+    LanguageServer, LspService, UriExt,
+};
+
+#[test]
+async fn test_stdlib_is_served_from_virtual_uris() {
+    let (service, _) = LspService::new(|client| {
+        Backend::new(
+            client,
+            AmberVersion::Alpha040,
+            Some(Arc::new(MemoryFS::new())),
+        )
+    });
+
+    let backend = service.inner();
+
+    let vfs = &backend.analyzer.files.fs;
+
+    let file = {
+        #[cfg(windows)]
+        {
+            Path::new("C:\\main.ab")
+        }
+        #[cfg(unix)]
+        {
+            Path::new("/main.ab")
+        }
+    };
+    let uri = Uri::from_file_path(file).unwrap();
+
+    vfs.write(
+        file,
+        r#"import { split } from "std/text"
+let parts = split("a b", " ")
+"#,
+    )
+    .await
+    .unwrap();
+
+    let (file_id, version) = backend.open_document(&uri).await.unwrap();
+
+    assert!(!backend
+        .get_diagnostics(file_id, version)
+        .iter()
+        .any(|diagnostic| diagnostic.message == "File doesn't exist"
+            || diagnostic.message == "Could not resolve 'split'"));
+
+    let text_uri = Uri::from_str("amber-std:/alpha040/std/text.ab").unwrap();
+    let text_file_id = backend.analyzer.files.get(&text_uri).unwrap();
+
+    assert!(backend
+        .analyzer
+        .files
+        .is_depending_on(&(file_id, version), text_file_id));
+    assert_eq!(
+        backend.analyzer.get_file_amber_version(text_file_id),
+        AmberVersion::Alpha040
+    );
+
+    let content = backend
+        .stdlib_content(TextDocumentIdentifier { uri: text_uri })
+        .await
+        .unwrap();
+    assert_eq!(
+        content,
+        STDLIB
+            .get_file("alpha040/std/text.ab")
+            .unwrap()
+            .contents_utf8()
+            .unwrap()
+    );
+
+    assert!(backend
+        .stdlib_content(TextDocumentIdentifier { uri })
+        .await
+        .is_err());
+}
+
+#[test]
+async fn test_stdlib_has_semantic_tokens() {
+    let (service, _) = LspService::new(|client| {
+        Backend::new(
+            client,
+            AmberVersion::Alpha040,
+            Some(Arc::new(MemoryFS::new())),
+        )
+    });
+
+    let backend = service.inner();
+
+    let result = backend
+        .initialize(InitializeParams::default())
+        .await
+        .unwrap();
+    let Some(SemanticTokensServerCapabilities::SemanticTokensRegistrationOptions(options)) =
+        result.capabilities.semantic_tokens_provider
+    else {
+        panic!("Semantic tokens are not registered for documents");
+    };
+    assert!(options
+        .text_document_registration_options
+        .document_selector
+        .unwrap()
+        .iter()
+        .any(|filter| filter.scheme.as_deref() == Some(STDLIB_SCHEME)));
+
+    let text_uri = Uri::from_str("amber-std:/alpha040/std/text.ab").unwrap();
+    backend.open_document(&text_uri).await.unwrap();
+
+    let tokens = backend
+        .semantic_tokens_full(SemanticTokensParams {
+            text_document: TextDocumentIdentifier { uri: text_uri },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+        .await
+        .unwrap();
+    assert!(tokens.is_some());
+}
+
+#[test]
+async fn test_installed_stdlib_is_preferred() {
+    let installed_dir = {
+        #[cfg(windows)]
+        {
+            Path::new("C:\\amber")
+        }
+        #[cfg(unix)]
+        {
+            Path::new("/amber")
+        }
+    };
+
+    let (service, _) = LspService::new(|client| {
+        Backend::with_analyzer(
+            client,
+            Arc::new(
+                Analyzer::new(AmberVersion::Alpha040, Arc::new(MemoryFS::new()))
+                    .with_installed_stdlib(Some(InstalledStdlib {
+                        amber_version: AmberVersion::Alpha040,
+                        dir: installed_dir.to_path_buf(),
+                    })),
+            ),
+        )
+    });
+
+    let backend = service.inner();
+
+    let vfs = &backend.analyzer.files.fs;
+
+    let file = {
+        #[cfg(windows)]
+        {
+            Path::new("C:\\main.ab")
+        }
+        #[cfg(unix)]
+        {
+            Path::new("/main.ab")
+        }
+    };
+    let uri = Uri::from_file_path(file).unwrap();
+    let installed_text_file = installed_dir.join("std").join("text.ab");
+
+    vfs.write(
+        &installed_text_file,
+        "pub fun split(text: Text, delimiter: Text): [Text] {\n    return [text]\n}\n",
+    )
+    .await
+    .unwrap();
+    vfs.write(
+        file,
+        r#"import { split } from "std/text"
+import { math_floor } from "std/math"
+let parts = split("a b", " ")
+"#,
+    )
+    .await
+    .unwrap();
+
+    let (file_id, version) = backend.open_document(&uri).await.unwrap();
+
+    let installed_text_file_id = backend
+        .analyzer
+        .files
+        .get(&Uri::from_file_path(&installed_text_file).unwrap())
+        .unwrap();
+    assert!(backend
+        .analyzer
+        .files
+        .is_depending_on(&(file_id, version), installed_text_file_id));
+
+    // Files missing in the installed standard library are taken from the bundled one
+    let bundled_math_file_id = backend
+        .analyzer
+        .files
+        .get(&Uri::from_str("amber-std:/alpha040/std/math.ab").unwrap())
+        .unwrap();
+    assert!(backend
+        .analyzer
+        .files
+        .is_depending_on(&(file_id, version), bundled_math_file_id));
+
+    let hover = backend
+        .hover(HoverParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position: Position::new(2, 13),
+            },
+            work_done_progress_params: Default::default(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+    let HoverContents::Markup(contents) = hover.contents else {
+        panic!("hover contents are markdown");
+    };
+    assert!(contents.value.ends_with(&format!(
+        "Standard library of the installed Amber: `{}`",
+        installed_dir.display()
+    )));
+}
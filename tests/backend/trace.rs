@@ -0,0 +1,105 @@
+use std::{future::poll_fn, sync::Arc, time::Duration};
+
+use amber_lsp::{
+    backend::{AmberVersion, Backend},
+    fs::MemoryFS,
+    trace::{LogTraceLayer, TraceSessions},
+};
+use futures::StreamExt;
+use serde_json::json;
+use tokio::{test, time::timeout};
+use tower_lsp_server::{
+    jsonrpc::Request,
+    lsp_types::{InitializeParams, LogTraceParams, SetTraceParams, TraceValue},
+    LanguageServer, LspService,
+};
+use tower_service::Service;
+use tracing_subscriber::layer::SubscriberExt;
+
+#[test]
+async fn test_set_trace() {
+    let (service, _) = LspService::new(|client| {
+        Backend::new(
+            client,
+            AmberVersion::Alpha040,
+            Some(Arc::new(MemoryFS::new())),
+        )
+    });
+
+    let backend = service.inner();
+
+    assert_eq!(backend.trace.value(), TraceValue::Off);
+
+    backend
+        .initialize(InitializeParams {
+            trace: Some(TraceValue::Messages),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(backend.trace.value(), TraceValue::Messages);
+
+    backend
+        .set_trace(SetTraceParams {
+            value: TraceValue::Verbose,
+        })
+        .await;
+
+    assert_eq!(backend.trace.value(), TraceValue::Verbose);
+}
+
+#[test]
+async fn test_events_are_forwarded_to_tracing_clients() {
+    let (mut service, mut socket) = LspService::new(|client| {
+        Backend::new(
+            client,
+            AmberVersion::Alpha040,
+            Some(Arc::new(MemoryFS::new())),
+        )
+    });
+
+    // Notifications are only sent to initialized clients
+    let initialize = Request::build("initialize")
+        .id(1)
+        .params(json!({ "capabilities": {} }))
+        .finish();
+    poll_fn(|cx| service.poll_ready(cx)).await.unwrap();
+    service.call(initialize).await.unwrap();
+
+    let sessions = TraceSessions::new();
+    let session = service.inner().trace.clone();
+    sessions.register(&session);
+
+    let subscriber = tracing_subscriber::registry().with(LogTraceLayer::new(sessions));
+
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(target: "amber_lsp::backend", "not traced");
+
+        session.set_value(TraceValue::Messages);
+        tracing::info!(target: "amber_lsp::backend", "first");
+        tracing::info!(target: "tower_lsp_server", "not from the server");
+
+        session.set_value(TraceValue::Verbose);
+        tracing::warn!(target: "amber_lsp::backend", key = 1, "second");
+    });
+
+    let mut traces = vec![];
+
+    while let Ok(Some(request)) = timeout(Duration::from_secs(1), socket.next()).await {
+        if request.method() == "$/logTrace" {
+            let params: LogTraceParams =
+                serde_json::from_value(request.params().unwrap().clone()).unwrap();
+            traces.push(params);
+        }
+    }
+
+    assert_eq!(traces.len(), 2);
+    assert_eq!(traces[0].message, "[INFO] first");
+    assert_eq!(traces[0].verbose, None);
+    assert_eq!(traces[1].message, "[WARN] second");
+    assert!(traces[1]
+        .verbose
+        .as_ref()
+        .is_some_and(|verbose| verbose.contains("trace.rs:") && verbose.ends_with(" key=1")));
+}
@@ -0,0 +1,91 @@
+use std::sync::Arc;
+
+use amber_lsp::{
+    analyzer::{AmberVersion, Analyzer},
+    fs::MemoryFS,
+};
+use tokio::test;
+use tower_lsp_server::{lsp_types::Uri, UriExt};
+
+use crate::project_dir;
+
+#[test]
+async fn test_shebang_selects_file_version() {
+    let analyzer = Arc::new(Analyzer::new(
+        AmberVersion::Alpha034,
+        Arc::new(MemoryFS::new()),
+    ));
+
+    let vfs = &analyzer.files.fs;
+
+    let project = project_dir();
+    let legacy_file = project.join("legacy.ab");
+    let main_file = project.join("main.ab");
+
+    vfs.write(
+        &legacy_file,
+        "#!/usr/bin/env amber-0.3.5-alpha\necho \"legacy\"\n",
+    )
+    .await
+    .unwrap();
+    vfs.write(&main_file, "echo \"main\"\n").await.unwrap();
+
+    let (legacy_file_id, _) = analyzer
+        .open_document(&Uri::from_file_path(&legacy_file).unwrap())
+        .await
+        .unwrap();
+    let (main_file_id, _) = analyzer
+        .open_document(&Uri::from_file_path(&main_file).unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        analyzer.get_file_amber_version(legacy_file_id),
+        AmberVersion::Alpha035
+    );
+    assert_eq!(
+        analyzer.get_file_amber_version(main_file_id),
+        AmberVersion::Alpha034
+    );
+}
+
+#[test]
+async fn test_closest_project_config_selects_file_version() {
+    let analyzer = Arc::new(
+        Analyzer::new(AmberVersion::Alpha040, Arc::new(MemoryFS::new())).with_detected_version(),
+    );
+
+    let vfs = &analyzer.files.fs;
+
+    let project = project_dir();
+    let legacy_dir = project.join("legacy");
+    let main_file = project.join("main.ab");
+    let legacy_file = legacy_dir.join("main.ab");
+
+    vfs.write(&project.join("amber.toml"), r#"version = "0.3.4-alpha""#)
+        .await
+        .unwrap();
+    vfs.write(&legacy_dir.join("amber.toml"), r#"version = "0.3.5-alpha""#)
+        .await
+        .unwrap();
+    vfs.write(&main_file, "echo \"main\"\n").await.unwrap();
+    vfs.write(&legacy_file, "echo \"legacy\"\n").await.unwrap();
+
+    let (main_file_id, _) = analyzer
+        .open_document(&Uri::from_file_path(&main_file).unwrap())
+        .await
+        .unwrap();
+    let (legacy_file_id, _) = analyzer
+        .open_document(&Uri::from_file_path(&legacy_file).unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        analyzer.get_file_amber_version(main_file_id),
+        AmberVersion::Alpha034
+    );
+    assert_eq!(
+        analyzer.get_file_amber_version(legacy_file_id),
+        AmberVersion::Alpha035
+    );
+}
@@ -0,0 +1,128 @@
+use std::{path::Path, sync::Arc};
+
+use amber_lsp::{
+    backend::{AmberVersion, Backend},
+    fs::MemoryFS,
+};
+use tokio::test;
+use tower_lsp_server::{
+    lsp_types::{
+        DidChangeWatchedFilesParams, DidOpenTextDocumentParams, FileChangeType, FileEvent,
+        TextDocumentItem, Uri,
+    },
+    LanguageServer, LspService, UriExt,
+};
+
+fn paths() -> (&'static Path, &'static Path) {
+    #[cfg(windows)]
+    {
+        (Path::new("C:\\lib.ab"), Path::new("C:\\main.ab"))
+    }
+    #[cfg(unix)]
+    {
+        (Path::new("/lib.ab"), Path::new("/main.ab"))
+    }
+}
+
+async fn open_main(backend: &Backend, main_uri: &Uri) {
+    backend
+        .did_open(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem {
+                uri: main_uri.clone(),
+                language_id: "amber".to_string(),
+                version: 1,
+                text: r#"
+    import { foo } from "lib.ab"
+
+    foo()
+    "#
+                .to_string(),
+            },
+        })
+        .await;
+}
+
+fn has_error(backend: &Backend, uri: &Uri, message: &str) -> bool {
+    let file_id = backend.analyzer.files.get(uri).unwrap();
+    let version = backend.analyzer.files.get_latest_version(file_id);
+
+    backend
+        .get_diagnostics(file_id, version)
+        .iter()
+        .any(|diagnostic| diagnostic.message == message)
+}
+
+#[test]
+async fn test_changed_file_on_disk_updates_dependants() {
+    let (service, _) = LspService::new(|client| {
+        Backend::new(
+            client,
+            AmberVersion::Alpha034,
+            Some(Arc::new(MemoryFS::new())),
+        )
+    });
+
+    let backend = service.inner();
+
+    let vfs = &backend.analyzer.files.fs;
+
+    let (lib_file, main_file) = paths();
+    let lib_uri = Uri::from_file_path(lib_file).unwrap();
+    let main_uri = Uri::from_file_path(main_file).unwrap();
+
+    vfs.write(lib_file, "pub fun foo() {}").await.unwrap();
+
+    open_main(backend, &main_uri).await;
+
+    assert!(!has_error(backend, &main_uri, "Could not resolve 'foo'"));
+
+    vfs.write(lib_file, "pub fun bar() {}").await.unwrap();
+
+    backend
+        .did_change_watched_files(DidChangeWatchedFilesParams {
+            changes: vec![FileEvent {
+                uri: lib_uri.clone(),
+                typ: FileChangeType::CHANGED,
+            }],
+        })
+        .await;
+
+    assert!(has_error(backend, &main_uri, "Could not resolve 'foo'"));
+}
+
+#[test]
+async fn test_created_file_on_disk_resolves_import() {
+    let (service, _) = LspService::new(|client| {
+        Backend::new(
+            client,
+            AmberVersion::Alpha034,
+            Some(Arc::new(MemoryFS::new())),
+        )
+    });
+
+    let backend = service.inner();
+
+    let vfs = &backend.analyzer.files.fs;
+
+    let (lib_file, main_file) = paths();
+    let lib_uri = Uri::from_file_path(lib_file).unwrap();
+    let main_uri = Uri::from_file_path(main_file).unwrap();
+
+    open_main(backend, &main_uri).await;
+
+    assert!(has_error(backend, &main_uri, "File doesn't exist"));
+
+    vfs.write(lib_file, "pub fun foo() {}").await.unwrap();
+
+    backend
+        .did_change_watched_files(DidChangeWatchedFilesParams {
+            changes: vec![FileEvent {
+                uri: lib_uri.clone(),
+                typ: FileChangeType::CREATED,
+            }],
+        })
+        .await;
+
+    assert!(!has_error(backend, &main_uri, "File doesn't exist"));
+    assert!(!has_error(backend, &main_uri, "Could not resolve 'foo'"));
+}
@@ -0,0 +1,105 @@
+use std::sync::Arc;
+
+use amber_lsp::{
+    analyzer::{AmberVersion, Analyzer},
+    check::{check_paths, format_json, format_sarif, format_text, has_errors},
+    fs::MemoryFS,
+};
+use tokio::test;
+
+use crate::project_dir;
+
+#[test]
+async fn test_check_reports_diagnostics() {
+    let analyzer = Arc::new(Analyzer::new(
+        AmberVersion::Alpha034,
+        Arc::new(MemoryFS::new()),
+    ));
+
+    let vfs = &analyzer.files.fs;
+
+    let project = project_dir();
+    let main_file = project.join("main.ab");
+    let lib_file = project.join("lib.ab");
+
+    vfs.write(&main_file, "let a = 1\nlet c = b\n")
+        .await
+        .unwrap();
+    vfs.write(&lib_file, "pub fun foo() {}\n").await.unwrap();
+
+    let files = check_paths(&analyzer, &[lib_file.clone(), main_file.clone()]).await;
+
+    assert_eq!(files.len(), 2);
+    assert!(files[0].diagnostics.is_empty());
+    assert!(has_errors(&files));
+    assert!(!has_errors(&files[..1]));
+
+    let main_path = main_file.to_string_lossy().replace('\\', "/");
+
+    let text = format_text(&files);
+    assert!(text.contains("error[undefined-symbol]: \"b\" is not defined"));
+    assert!(text.contains(&format!(" --> {main_path}:2:9")));
+    assert!(text.contains("2 | let c = b\n  |         ^\n"));
+    assert!(text.ends_with("Checked 2 files: 1 error, 0 warnings\n"));
+
+    let json = format_json(&files);
+    assert_eq!(json["summary"]["errors"], 1);
+    assert_eq!(json["diagnostics"][0]["path"], main_path);
+    assert_eq!(json["diagnostics"][0]["code"], "undefined-symbol");
+    assert_eq!(json["diagnostics"][0]["range"]["start"]["line"], 2);
+    assert_eq!(json["diagnostics"][0]["range"]["start"]["column"], 9);
+
+    let sarif = format_sarif(&files);
+    assert_eq!(sarif["version"], "2.1.0");
+    let run = &sarif["runs"][0];
+    assert_eq!(run["tool"]["driver"]["rules"][0]["id"], "undefined-symbol");
+    assert_eq!(run["results"][0]["ruleId"], "undefined-symbol");
+    assert_eq!(run["results"][0]["level"], "error");
+    assert_eq!(
+        run["results"][0]["locations"][0]["physicalLocation"]["region"]["startLine"],
+        2
+    );
+}
+
+#[test]
+async fn test_check_reports_missing_files() {
+    let analyzer = Arc::new(Analyzer::new(
+        AmberVersion::Alpha034,
+        Arc::new(MemoryFS::new()),
+    ));
+
+    let files = check_paths(&analyzer, &[project_dir().join("missing.ab")]).await;
+
+    assert!(has_errors(&files));
+    assert!(format_text(&files).contains("error: Could not read the file"));
+}
+
+#[test]
+async fn test_check_finds_files_in_directories() {
+    let analyzer = Arc::new(Analyzer::new(
+        AmberVersion::Alpha040,
+        Arc::new(MemoryFS::new()),
+    ));
+
+    let vfs = &analyzer.files.fs;
+
+    let project = project_dir();
+    let main_file = project.join("main.ab");
+    let lib_file = project.join("src").join("lib.ab");
+
+    vfs.write(&main_file, "let a = 1\n").await.unwrap();
+    vfs.write(&lib_file, "let c = b\n").await.unwrap();
+
+    let files = check_paths(&analyzer, &[project.to_path_buf()]).await;
+
+    let mut paths = files
+        .iter()
+        .map(|file| file.path.clone())
+        .collect::<Vec<_>>();
+    paths.sort();
+    let mut expected = vec![main_file, lib_file];
+    expected.sort();
+
+    assert_eq!(paths, expected);
+    assert!(has_errors(&files));
+}
@@ -0,0 +1,112 @@
+use std::{path::Path, sync::Arc};
+
+use amber_lsp::{
+    backend::{AmberVersion, Backend},
+    format::{format_diff, format_document, DEFAULT_INDENT},
+    fs::MemoryFS,
+};
+use tokio::test;
+use tower_lsp_server::{
+    lsp_types::{
+        DidOpenTextDocumentParams, DocumentFormattingParams, FormattingOptions, Position, Range,
+        TextDocumentIdentifier, TextDocumentItem, Uri,
+    },
+    LanguageServer, LspService, UriExt,
+};
+
+#[test]
+async fn test_format_document() {
+    let text = "\n\nimport { foo } from \"lib.ab\"   \n\n\n\nfun bar(a: Num) {\nif a > 1 {\n  echo \"big\"\n    } else {\necho [\n1,\n2\n]\n}\n}\n\n\n";
+
+    let formatted = format_document(text, DEFAULT_INDENT);
+
+    assert_eq!(
+        formatted,
+        "import { foo } from \"lib.ab\"\n\nfun bar(a: Num) {\n    if a > 1 {\n        echo \"big\"\n    } else {\n        echo [\n            1,\n            2\n        ]\n    }\n}\n"
+    );
+    assert_eq!(format_document(&formatted, DEFAULT_INDENT), formatted);
+}
+
+#[test]
+async fn test_format_keeps_literals() {
+    let text = "#!/usr/bin/env amber  \nfun foo() {\n$echo \"{\"\n   echo  \"}\"$\nlet text = \"a {1 + 1}\n    b  \n  c\"\n// comment {\necho text\n}\n";
+
+    assert_eq!(
+        format_document(text, "\t"),
+        "#!/usr/bin/env amber\nfun foo() {\n\t$echo \"{\"\n   echo  \"}\"$\n\tlet text = \"a {1 + 1}\n    b  \n  c\"\n\t// comment {\n\techo text\n}\n"
+    );
+    assert_eq!(format_document("", DEFAULT_INDENT), "");
+    assert_eq!(
+        format_document("echo 1\r\n  echo 2  \r\n", DEFAULT_INDENT),
+        "echo 1\r\necho 2\r\n"
+    );
+}
+
+#[test]
+async fn test_format_diff() {
+    let diff = format_diff(
+        "main.ab",
+        "if true {\necho 1\n}\n",
+        "if true {\n    echo 1\n}\n",
+    );
+
+    assert!(diff.starts_with("--- main.ab\n+++ main.ab\n"));
+    assert!(diff.contains("\n-echo 1\n+    echo 1\n"));
+}
+
+#[test]
+async fn test_formatting_request() {
+    let (service, _) = LspService::new(|client| {
+        Backend::new(
+            client,
+            AmberVersion::Alpha040,
+            Some(Arc::new(MemoryFS::new())),
+        )
+    });
+
+    let backend = service.inner();
+
+    let file = {
+        #[cfg(windows)]
+        {
+            Path::new("C:\\main.ab")
+        }
+        #[cfg(unix)]
+        {
+            Path::new("/main.ab")
+        }
+    };
+    let uri = Uri::from_file_path(file).unwrap();
+
+    backend
+        .did_open(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem {
+                uri: uri.clone(),
+                language_id: "amber".to_string(),
+                version: 1,
+                text: "if true {\necho 1\n}".to_string(),
+            },
+        })
+        .await;
+
+    let edits = backend
+        .formatting(DocumentFormattingParams {
+            text_document: TextDocumentIdentifier { uri },
+            options: FormattingOptions {
+                tab_size: 2,
+                insert_spaces: true,
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(edits.len(), 1);
+    assert_eq!(
+        edits[0].range,
+        Range::new(Position::new(0, 0), Position::new(2, 1))
+    );
+    assert_eq!(edits[0].new_text, "if true {\n  echo 1\n}\n");
+}
@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use amber_lsp::{
+    analyzer::{AmberVersion, Analyzer},
+    diagnostics::Diagnostic,
+    fs::MemoryFS,
+    grammar::{alpha040::AmberCompiler, lex, LSPAnalysis, LexerResponse, Span, Token},
+};
+use heraclitus_compiler::prelude::*;
+
+use crate::main_file;
+
+fn words(tokens: &[(Token, Span)]) -> Vec<(&str, usize, usize)> {
+    tokens
+        .iter()
+        .map(|(token, span)| (token.0.as_str(), span.start, span.end))
+        .collect()
+}
+
+#[test]
+fn test_lex_without_errors() {
+    let LexerResponse { tokens, errors } = AmberCompiler::new().lex("let a = \"text\"\r\necho a\n");
+
+    assert!(errors.is_empty());
+    assert_eq!(
+        words(&tokens),
+        vec![
+            ("let", 0, 3),
+            ("a", 4, 5),
+            ("=", 6, 7),
+            ("\"", 8, 9),
+            ("text", 9, 13),
+            ("\"", 13, 14),
+            ("echo", 15, 19),
+            ("a", 20, 21),
+        ]
+    );
+}
+
+#[test]
+fn test_lex_recovers_from_errors() {
+    let rules = Rules::new(
+        vec!['=', '\''],
+        vec![],
+        reg![reg!(string as "string literal" => {
+            begin: "'",
+            end: "'",
+            singleline: true
+        })],
+    );
+    let lexer = prelude::lexer::Lexer::new(rules);
+
+    let LexerResponse { tokens, errors } = lex(&lexer, "let a = 'abc\nlet b = 1\n");
+
+    // The error is reported at the end of the line of the unclosed region
+    assert_eq!(
+        errors,
+        vec![(
+            "Region can't span multiple lines".to_string(),
+            Span::new(12, 13)
+        )]
+    );
+
+    // The line after the error is still tokenized
+    assert_eq!(
+        words(&tokens),
+        vec![("let", 13, 16), ("b", 17, 18), ("=", 19, 20), ("1", 21, 22)]
+    );
+}
+
+#[tokio::test]
+async fn test_unclosed_string_keeps_previous_lines() {
+    let analyzer = Arc::new(Analyzer::new(
+        AmberVersion::Alpha040,
+        Arc::new(MemoryFS::new()),
+    ));
+
+    let text = "let a = 1\necho a\nlet b = \"abc\n";
+    let (file_id, version) = analyzer.analyze_source(main_file(), text).await.unwrap();
+
+    // Amber regions may be left open, so the lexer doesn't fail and the parser reports the error
+    let string_start = text.find('"').unwrap();
+    assert!(analyzer
+        .get_diagnostics(file_id, version)
+        .iter()
+        .any(
+            |diagnostic| matches!(diagnostic.diagnostic, Diagnostic::Syntax(_))
+                && diagnostic.span.start >= string_start
+        ));
+
+    let symbol_table = analyzer
+        .files
+        .symbol_table
+        .get(&(file_id, version))
+        .unwrap()
+        .clone();
+    assert_eq!(symbol_table.symbols.get(&15).unwrap().name, "a");
+}
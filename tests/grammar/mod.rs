@@ -1,3 +1,4 @@
 pub mod alpha034;
 pub mod alpha035;
 pub mod alpha040;
+pub mod lexer;
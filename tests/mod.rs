@@ -1,2 +1,33 @@
+use std::path::Path;
+
 pub mod analysis;
+pub mod backend;
+pub mod check;
+pub mod format;
 pub mod grammar;
+pub mod panic;
+pub mod record;
+
+/// Root of the project, that tests write their files to.
+pub fn project_dir() -> &'static Path {
+    #[cfg(windows)]
+    {
+        Path::new("C:\\project")
+    }
+    #[cfg(unix)]
+    {
+        Path::new("/project")
+    }
+}
+
+/// File, that tests with a single document write it to.
+pub fn main_file() -> &'static Path {
+    #[cfg(windows)]
+    {
+        Path::new("C:\\main.ab")
+    }
+    #[cfg(unix)]
+    {
+        Path::new("/main.ab")
+    }
+}
@@ -0,0 +1,165 @@
+use std::{
+    future::{poll_fn, Future},
+    io::Result as IoResult,
+    panic::AssertUnwindSafe,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use amber_lsp::{
+    analyzer::Analyzer,
+    backend::{AmberVersion, Backend},
+    files::DEFAULT_VERSION,
+    fs::{MemoryFS, FS},
+    panic::CatchPanic,
+};
+use futures::FutureExt;
+use serde_json::{json, Value};
+use tokio::test;
+use tower_lsp_server::{
+    jsonrpc::{Request, Result},
+    lsp_types::Uri,
+    LspService, UriExt,
+};
+use tower_service::Service;
+
+async fn panicking(_: &Backend, _: Value) -> Result<Value> {
+    panic!("something went wrong")
+}
+
+async fn call(service: &mut CatchPanic, request: Request) -> Value {
+    poll_fn(|cx| service.poll_ready(cx)).await.unwrap();
+
+    let response = service.call(request).await.unwrap().unwrap();
+
+    serde_json::to_value(response).unwrap()
+}
+
+#[test]
+async fn test_panics_become_internal_errors() {
+    let (service, _socket) = LspService::build(|client| {
+        Backend::new(
+            client,
+            AmberVersion::Alpha040,
+            Some(Arc::new(MemoryFS::new())),
+        )
+    })
+    .custom_method("test/panic", panicking)
+    .finish();
+    let mut service = CatchPanic::new(service);
+
+    let initialize = Request::build("initialize")
+        .id(1)
+        .params(json!({ "capabilities": {} }))
+        .finish();
+    assert!(call(&mut service, initialize).await["result"].is_object());
+
+    let request = Request::build("test/panic")
+        .id(2)
+        .params(json!({}))
+        .finish();
+    let response = call(&mut service, request).await;
+
+    assert_eq!(response["id"], 2);
+    assert_eq!(response["error"]["code"], -32603);
+    assert!(response["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("something went wrong"));
+
+    // The server keeps handling requests
+    let shutdown = Request::build("shutdown").id(3).finish();
+    assert_eq!(call(&mut service, shutdown).await["result"], Value::Null);
+}
+
+/// File system, that panics the first time a file is read after being armed.
+#[derive(Debug, Default)]
+struct PanickingFS {
+    fs: MemoryFS,
+    armed: AtomicBool,
+}
+
+impl FS for PanickingFS {
+    fn read<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> Pin<Box<dyn Future<Output = IoResult<String>> + Send + 'a>> {
+        if path.ends_with("lib.ab") && self.armed.swap(false, Ordering::Relaxed) {
+            panic!("could not read {}", path.display());
+        }
+
+        self.fs.read(path)
+    }
+
+    fn write<'a>(
+        &'a self,
+        path: &'a Path,
+        content: &'a str,
+    ) -> Pin<Box<dyn Future<Output = IoResult<()>> + Send + 'a>> {
+        self.fs.write(path, content)
+    }
+
+    fn exists<'a>(&'a self, path: &'a Path) -> Pin<Box<dyn Future<Output = bool> + Send + 'a>> {
+        self.fs.exists(path)
+    }
+
+    fn is_dir<'a>(&'a self, path: &'a Path) -> Pin<Box<dyn Future<Output = bool> + Send + 'a>> {
+        self.fs.is_dir(path)
+    }
+
+    fn read_dir<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> Pin<Box<dyn Future<Output = Vec<PathBuf>> + Send + 'a>> {
+        self.fs.read_dir(path)
+    }
+
+    fn create_dir_all<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> Pin<Box<dyn Future<Output = IoResult<()>> + Send + 'a>> {
+        self.fs.create_dir_all(path)
+    }
+}
+
+#[test]
+async fn test_file_is_analyzed_again_after_panic() {
+    let fs = Arc::new(PanickingFS::default());
+    let analyzer = Arc::new(Analyzer::new(AmberVersion::Alpha040, fs.clone()));
+
+    let (main_file, lib_file) = {
+        #[cfg(windows)]
+        {
+            (Path::new("C:\\main.ab"), Path::new("C:\\lib.ab"))
+        }
+        #[cfg(unix)]
+        {
+            (Path::new("/main.ab"), Path::new("/lib.ab"))
+        }
+    };
+    let uri = Uri::from_file_path(main_file).unwrap();
+
+    fs.write(main_file, "import * from \"lib.ab\"\n")
+        .await
+        .unwrap();
+    fs.write(lib_file, "pub fun foo() {}\n").await.unwrap();
+
+    // Reading the import panics in the middle of the analysis of the main file
+    fs.armed.store(true, Ordering::Relaxed);
+    let result = AssertUnwindSafe(analyzer.open_document(&uri))
+        .catch_unwind()
+        .await;
+    assert!(result.is_err());
+
+    let file_id = analyzer.files.get(&uri).unwrap();
+    let file = (file_id, DEFAULT_VERSION);
+    assert!(!analyzer.files.analyze_lock.contains_key(&file));
+
+    analyzer.analyze_document(file_id, DEFAULT_VERSION).await;
+
+    assert!(analyzer.files.is_file_analyzed(&file).await);
+}
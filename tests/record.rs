@@ -0,0 +1,199 @@
+use std::{env::temp_dir, path::Path, process, sync::Arc};
+
+use amber_lsp::{
+    backend::{AmberVersion, Backend},
+    fs::MemoryFS,
+    record::{read_recording, replay, Recorder, RecordingReader, RecordingWriter, Sender},
+};
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, DuplexStream},
+    test,
+};
+use tower_lsp_server::{lsp_types::Uri, LspService, Server, UriExt};
+
+async fn send(writer: &mut (impl AsyncWrite + Unpin), message: Value) {
+    let body = message.to_string();
+
+    writer
+        .write_all(format!("Content-Length: {}\r\n\r\n{body}", body.len()).as_bytes())
+        .await
+        .unwrap();
+}
+
+async fn receive(reader: &mut BufReader<tokio::io::ReadHalf<DuplexStream>>) -> Value {
+    let mut content_length = 0;
+
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).await.unwrap();
+
+        if header.trim().is_empty() {
+            break;
+        }
+
+        if let Some(length) = header.strip_prefix("Content-Length:") {
+            content_length = length.trim().parse().unwrap();
+        }
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).await.unwrap();
+
+    serde_json::from_slice(&body).unwrap()
+}
+
+/// Receives messages until one matches, answering requests of the server on the way.
+async fn receive_until(
+    reader: &mut BufReader<tokio::io::ReadHalf<DuplexStream>>,
+    writer: &mut tokio::io::WriteHalf<DuplexStream>,
+    matches: impl Fn(&Value) -> bool,
+) -> Value {
+    loop {
+        let message = receive(reader).await;
+
+        if matches(&message) {
+            return message;
+        }
+
+        if let (Some(_), Some(id)) = (message.get("method"), message.get("id")) {
+            send(
+                writer,
+                json!({ "jsonrpc": "2.0", "id": id, "result": null }),
+            )
+            .await;
+        }
+    }
+}
+
+#[test]
+async fn test_recorded_session_replays() {
+    let recording = temp_dir().join(format!("amber-lsp-recording-{}.jsonl", process::id()));
+    let recorder = Arc::new(Recorder::create(&recording).unwrap());
+
+    let file = {
+        #[cfg(windows)]
+        {
+            Path::new("C:\\main.ab")
+        }
+        #[cfg(unix)]
+        {
+            Path::new("/main.ab")
+        }
+    };
+    let uri = Uri::from_file_path(file).unwrap();
+
+    let (client_stream, server_stream) = tokio::io::duplex(1 << 16);
+    let (server_input, server_output) = tokio::io::split(server_stream);
+    let (client_input, mut client_output) = tokio::io::split(client_stream);
+    let mut client_input = BufReader::new(client_input);
+
+    let (service, socket) = LspService::new(|client| {
+        Backend::new(
+            client,
+            AmberVersion::Alpha040,
+            Some(Arc::new(MemoryFS::new())),
+        )
+    });
+
+    let server = Server::new(
+        RecordingReader::new(server_input, Some(recorder.clone())),
+        RecordingWriter::new(server_output, Some(recorder.clone())),
+        socket,
+    )
+    .serve(service);
+
+    let client = async move {
+        let is_response = |id: i64| {
+            move |message: &Value| {
+                message.get("id") == Some(&json!(id)) && message.get("method").is_none()
+            }
+        };
+
+        send(
+            &mut client_output,
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": { "capabilities": {} } }),
+        )
+        .await;
+        receive_until(&mut client_input, &mut client_output, is_response(1)).await;
+
+        send(
+            &mut client_output,
+            json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} }),
+        )
+        .await;
+        send(
+            &mut client_output,
+            json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didOpen",
+                "params": {
+                    "textDocument": {
+                        "uri": uri.as_str(),
+                        "languageId": "amber",
+                        "version": 1,
+                        "text": "let value = 1\necho value\n",
+                    },
+                },
+            }),
+        )
+        .await;
+        receive_until(&mut client_input, &mut client_output, |message| {
+            message.get("method") == Some(&json!("textDocument/publishDiagnostics"))
+        })
+        .await;
+
+        send(
+            &mut client_output,
+            json!({
+                "jsonrpc": "2.0",
+                "id": 2,
+                "method": "textDocument/hover",
+                "params": {
+                    "textDocument": { "uri": uri.as_str() },
+                    "position": { "line": 1, "character": 6 },
+                },
+            }),
+        )
+        .await;
+        let hover = receive_until(&mut client_input, &mut client_output, is_response(2)).await;
+        assert!(hover["result"].is_object());
+
+        send(
+            &mut client_output,
+            json!({ "jsonrpc": "2.0", "id": 3, "method": "shutdown" }),
+        )
+        .await;
+        receive_until(&mut client_input, &mut client_output, is_response(3)).await;
+
+        send(
+            &mut client_output,
+            json!({ "jsonrpc": "2.0", "method": "exit" }),
+        )
+        .await;
+
+        drop(client_output);
+    };
+
+    tokio::join!(server, client);
+
+    let messages = read_recording(&recording).unwrap();
+    assert!(messages.iter().any(|message| message.from == Sender::Client
+        && message.message["method"] == "textDocument/hover"));
+    assert!(messages
+        .iter()
+        .any(|message| message.from == Sender::Server && message.message["id"] == 2));
+
+    let mismatches = replay(&messages, |client| {
+        Backend::new(
+            client,
+            AmberVersion::Alpha040,
+            Some(Arc::new(MemoryFS::new())),
+        )
+    })
+    .await;
+
+    assert_eq!(mismatches, vec![]);
+
+    std::fs::remove_file(recording).unwrap();
+}